@@ -0,0 +1,366 @@
+// 把 AST 打印成带括号的前缀形式，比如 `(* (group (+ 1 2)) 3)`，用来查看解析结果
+use crate::expr::{
+    Assignment, Binary, Call, Comma, Compound, Conditional, Expr, Get, Grouping, Index, IndexSet,
+    Lambda, List, Literal, Logical, Map, Optional, Set, Super, This, Unary, Update, Variable,
+    Visitor,
+};
+use crate::stmt::{Break, Continue, Function, Imported, Stmt};
+use crate::token::LiteralType;
+
+pub(crate) struct AstPrinter;
+
+impl AstPrinter {
+    pub(crate) fn print_expr(&mut self, expr: &Expr) -> String {
+        expr.accept(self)
+    }
+
+    pub(crate) fn print_stmt(&mut self, stmt: &Stmt) -> String {
+        match stmt {
+            Stmt::Block(block) => {
+                let mut out = "(block".to_string();
+                for stmt in &block.statements {
+                    out.push(' ');
+                    out.push_str(&self.print_stmt(stmt));
+                }
+                out.push(')');
+                out
+            }
+            Stmt::Break(Break { keyword }) | Stmt::Continue(Continue { keyword }) => {
+                format!("({})", keyword.lexeme)
+            }
+            Stmt::Class(class) => {
+                let mut out = format!("(class {}", class.name.lexeme);
+                if let Some(superclass) = &class.superclass {
+                    out.push_str(&format!(" < {}", superclass.name.lexeme));
+                }
+                for method in &class.methods {
+                    out.push(' ');
+                    out.push_str(&self.print_function(method));
+                }
+                out.push(')');
+                out
+            }
+            Stmt::Export(export) => format!("(export {})", self.print_stmt(&export.declaration)),
+            Stmt::Expression(expression) => format!("(; {})", self.print_expr(&expression.expr)),
+            Stmt::Function(function) => self.print_function(function),
+            Stmt::If(stmt) => {
+                let condition = self.print_expr(&stmt.condition);
+                let then_branch = self.print_stmt(&stmt.then_branch);
+                match &stmt.else_branch {
+                    Some(else_branch) => format!(
+                        "(if {} {} {})",
+                        condition,
+                        then_branch,
+                        self.print_stmt(else_branch)
+                    ),
+                    None => format!("(if {} {})", condition, then_branch),
+                }
+            }
+            Stmt::Import(import) => match &import.imported {
+                Imported::Module(name) => {
+                    format!("(import {} as {})", import.path.lexeme, name.lexeme)
+                }
+                Imported::Names(names) => {
+                    let names: Vec<_> = names.iter().map(|name| name.lexeme.as_str()).collect();
+                    format!("(import ({}) from {})", names.join(" "), import.path.lexeme)
+                }
+            },
+            Stmt::Print(print) => format!("(print {})", self.print_expr(&print.expr)),
+            Stmt::Return(stmt) => match &stmt.value {
+                Some(value) => format!("(return {})", self.print_expr(value)),
+                None => "(return)".to_string(),
+            },
+            Stmt::Const(constant) => format!(
+                "(const {} {})",
+                constant.name.lexeme,
+                self.print_expr(&constant.initializer)
+            ),
+            Stmt::Var(var) => match &var.initializer {
+                Some(initializer) => {
+                    format!("(var {} {})", var.name.lexeme, self.print_expr(initializer))
+                }
+                None => format!("(var {})", var.name.lexeme),
+            },
+            Stmt::While(stmt) => format!(
+                "(while {} {})",
+                self.print_expr(&stmt.condition),
+                self.print_stmt(&stmt.body)
+            ),
+            Stmt::For(stmt) => {
+                // 省略的部分用 `_` 占位
+                let initializer = match &stmt.initializer {
+                    Some(initializer) => self.print_stmt(initializer),
+                    None => "_".to_string(),
+                };
+                let mut clause = |expr: &Option<Expr>| match expr {
+                    Some(expr) => self.print_expr(expr),
+                    None => "_".to_string(),
+                };
+                let condition = clause(&stmt.condition);
+                let increment = clause(&stmt.increment);
+                format!(
+                    "(for {} {} {} {})",
+                    initializer,
+                    condition,
+                    increment,
+                    self.print_stmt(&stmt.body)
+                )
+            }
+            Stmt::ForIn(stmt) => format!(
+                "(for-in {} {} {})",
+                stmt.name.lexeme,
+                self.print_expr(&stmt.iterable),
+                self.print_stmt(&stmt.body)
+            ),
+        }
+    }
+
+    fn print_function(&mut self, function: &Function) -> String {
+        self.print_callable(&format!("fun {}", function.name.lexeme), function)
+    }
+
+    /// 具名函数和匿名函数只有开头不同
+    fn print_callable(&mut self, head: &str, function: &Function) -> String {
+        let mut params: Vec<_> = function
+            .params
+            .iter()
+            .map(|param| match &param.default {
+                Some(default) => self.parenthesize(&format!("= {}", param.name.lexeme), &[default]),
+                None => param.name.lexeme.clone(),
+            })
+            .collect();
+        if let Some(rest) = &function.rest {
+            params.push(format!("...{}", rest.lexeme));
+        }
+        let mut out = format!("({} ({})", head, params.join(" "));
+        for stmt in &function.body {
+            out.push(' ');
+            out.push_str(&self.print_stmt(stmt));
+        }
+        out.push(')');
+        out
+    }
+
+    fn parenthesize(&mut self, name: &str, exprs: &[&Expr]) -> String {
+        let mut out = format!("({}", name);
+        for expr in exprs {
+            out.push(' ');
+            out.push_str(&expr.accept(self));
+        }
+        out.push(')');
+        out
+    }
+}
+
+impl Visitor<String> for AstPrinter {
+    fn visit_assignment(&mut self, expr: &Assignment) -> String {
+        self.parenthesize(&format!("= {}", expr.name.lexeme), &[&expr.value])
+    }
+
+    fn visit_binary(&mut self, expr: &Binary) -> String {
+        self.parenthesize(&expr.operator.lexeme, &[&expr.left, &expr.right])
+    }
+
+    fn visit_call(&mut self, expr: &Call) -> String {
+        let mut exprs = vec![expr.callee.as_ref()];
+        exprs.extend(&expr.arguments);
+        let mut out = self.parenthesize("call", &exprs);
+        out.pop();
+        for (name, argument) in &expr.named {
+            let argument = self.parenthesize(&format!("{}:", name.lexeme), &[argument]);
+            out.push(' ');
+            out.push_str(&argument);
+        }
+        out.push(')');
+        out
+    }
+
+    fn visit_comma(&mut self, expr: &Comma) -> String {
+        self.parenthesize(",", &[&expr.left, &expr.right])
+    }
+
+    fn visit_compound(&mut self, expr: &Compound) -> String {
+        self.parenthesize(&expr.operator.lexeme, &[&expr.target, &expr.value])
+    }
+
+    fn visit_conditional(&mut self, expr: &Conditional) -> String {
+        self.parenthesize(
+            "?:",
+            &[&expr.condition, &expr.then_branch, &expr.else_branch],
+        )
+    }
+
+    fn visit_get(&mut self, expr: &Get) -> String {
+        let dot = if expr.optional { "?." } else { "." };
+        self.parenthesize(&format!("{} {}", dot, expr.name.lexeme), &[&expr.object])
+    }
+
+    fn visit_grouping(&mut self, expr: &Grouping) -> String {
+        self.parenthesize("group", &[&expr.expr])
+    }
+
+    fn visit_lambda(&mut self, expr: &Lambda) -> String {
+        self.print_callable("lambda", &expr.function)
+    }
+
+    fn visit_index(&mut self, expr: &Index) -> String {
+        self.parenthesize("[]", &[&expr.object, &expr.index])
+    }
+
+    fn visit_index_set(&mut self, expr: &IndexSet) -> String {
+        self.parenthesize("= []", &[&expr.object, &expr.index, &expr.value])
+    }
+
+    fn visit_list(&mut self, expr: &List) -> String {
+        let elements: Vec<_> = expr.elements.iter().collect();
+        self.parenthesize("list", &elements)
+    }
+
+    fn visit_map(&mut self, expr: &Map) -> String {
+        let entries: Vec<_> = expr.entries.iter().flat_map(|(k, v)| [k, v]).collect();
+        self.parenthesize("map", &entries)
+    }
+
+    fn visit_literal(&self, expr: &Literal) -> String {
+        match &expr.value {
+            LiteralType::String(s) => format!("{:?}", s),
+            value => value.to_string(),
+        }
+    }
+
+    fn visit_logical(&mut self, expr: &Logical) -> String {
+        self.parenthesize(&expr.operator.lexeme, &[&expr.left, &expr.right])
+    }
+
+    fn visit_optional(&mut self, expr: &Optional) -> String {
+        self.parenthesize("optional", &[&expr.expr])
+    }
+
+    fn visit_set(&mut self, expr: &Set) -> String {
+        self.parenthesize(
+            &format!("= .{}", expr.name.lexeme),
+            &[&expr.object, &expr.value],
+        )
+    }
+
+    fn visit_super(&mut self, expr: &Super) -> String {
+        format!("super.{}", expr.method.lexeme)
+    }
+
+    fn visit_this(&mut self, _expr: &This) -> String {
+        "this".to_string()
+    }
+
+    fn visit_unary(&mut self, expr: &Unary) -> String {
+        self.parenthesize(&expr.operator.lexeme, &[&expr.right])
+    }
+
+    fn visit_update(&mut self, expr: &Update) -> String {
+        // 前缀写成 `(++ x)`，后缀写成 `(x ++)`
+        let target = self.print_expr(&expr.target);
+        if expr.prefix {
+            format!("({} {})", expr.operator.lexeme, target)
+        } else {
+            format!("({} {})", target, expr.operator.lexeme)
+        }
+    }
+
+    fn visit_variable(&mut self, expr: &Variable) -> String {
+        expr.name.lexeme.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{Parse, parse_expr};
+    use crate::scanner::Scanner;
+
+    #[test]
+    fn test_print_expr() {
+        assert_eq!(
+            AstPrinter.print_expr(&parse_expr("-123 * (45.67)")),
+            "(* (- 123) (group 45.67))"
+        );
+        assert_eq!(
+            AstPrinter.print_expr(&parse_expr("a = b or \"c\"")),
+            "(= a (or b \"c\"))"
+        );
+        assert_eq!(
+            AstPrinter.print_expr(&parse_expr("f(1)(g(), 2)")),
+            "(call (call f 1) (call g) 2)"
+        );
+        assert_eq!(
+            AstPrinter.print_expr(&parse_expr("xs[0] = [1, ys[-1]]")),
+            "(= [] xs 0 (list 1 ([] ys (- 1))))"
+        );
+        assert_eq!(
+            AstPrinter.print_expr(&parse_expr("{\"a\": 1, 2: {}}")),
+            "(map \"a\" 1 2 (map))"
+        );
+        assert_eq!(
+            AstPrinter.print_expr(&parse_expr("a ? b : c ? d, e : f or g")),
+            "(?: a b (?: c (, d e) (or f g)))"
+        );
+        assert_eq!(
+            AstPrinter.print_expr(&parse_expr("a = 1, f(b, [c, d])")),
+            "(, (= a 1) (call f b (list c d)))"
+        );
+        assert_eq!(
+            AstPrinter.print_expr(&parse_expr("-a ** b ** -c % d | e & f << 1 ^ ~g")),
+            "(| (% (- (** a (** b (- c)))) d) (^ (& e (<< f 1)) (~ g)))"
+        );
+        assert_eq!(
+            AstPrinter.print_expr(&parse_expr("a.b += xs[i++] -= -(--c)")),
+            "(+= (. b a) (-= ([] xs (i ++)) (- (group (-- c)))))"
+        );
+        assert_eq!(
+            AstPrinter.print_expr(&parse_expr("a?.b.c() ?? d or e")),
+            "(?? (optional (call (. c (?. b a)))) (or d e))"
+        );
+        assert_eq!(
+            AstPrinter.print_expr(&parse_expr("f(fun (a) { return a; }, (a, b) => a + b)")),
+            "(call f (lambda (a) (return a)) (lambda (a b) (return (+ a b))))"
+        );
+        assert_eq!(
+            AstPrinter.print_expr(&parse_expr("(a, b) + (() => {})()")),
+            "(+ (group (, a b)) (call (group (lambda ()))))"
+        );
+        assert_eq!(
+            AstPrinter.print_expr(&parse_expr("f(1, b: 2, c: d)((x = 1, ...xs) => x)")),
+            "(call (call f 1 (b: 2) (c: d)) (lambda ((= x 1) ...xs) (return x)))"
+        );
+    }
+
+    #[test]
+    fn test_print_stmt() {
+        let mut scanner = Scanner::new(
+            "var a; const b = 1; for (var i = 0; i < 2;) { if (i) print i; else a = i; }
+             fun f(x, y = 1, ...z) { return; }
+             class B < A { init() { this.x = super.y; } }
+             for (x in [1]) { if (x) break; continue; }
+             import \"m\" as m; import { a, b } from \"n\"; export fun g() {}"
+                .to_string(),
+        );
+        scanner.scan_tokens();
+        let statements = Parse::new(scanner).parse();
+        let printed: Vec<_> = statements
+            .iter()
+            .map(|stmt| AstPrinter.print_stmt(stmt))
+            .collect();
+        assert_eq!(
+            printed,
+            [
+                "(var a)",
+                "(const b 1)",
+                "(for (var i 0) (< i 2) _ (block (if i (print i) (; (= a i)))))",
+                "(fun f (x (= y 1) ...z) (return))",
+                "(class B < A (fun init () (; (= .x this super.y))))",
+                "(for-in x (list 1) (block (if x (break)) (continue)))",
+                "(import \"m\" as m)",
+                "(import (a b) from \"n\")",
+                "(export (fun g ()))",
+            ]
+        );
+    }
+}
@@ -0,0 +1,679 @@
+// 无损具体语法树 (CST)
+// 扫描时保留 trivia，解析得到 AST 后，再按 AST 的形状把 token 依次挂回节点上。
+// 有语法错误时不再对齐 AST，所有 token 都放进一个 Error 节点，所以任何输入都能逐字节还原。
+use std::fmt::Display;
+
+use crate::diagnostic::Diagnostic;
+use crate::expr::Expr;
+use crate::parser::Parse;
+use crate::scanner::Scanner;
+use crate::stmt::{Function, Imported, Return, Stmt};
+use crate::token::{Token, TokenType};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SyntaxKind {
+    /// 整个文件，最后一个子元素总是 EOF token
+    Root,
+    Block,
+    Break,
+    /// 类声明，方法是 Function 子节点
+    Class,
+    Const,
+    Continue,
+    /// 被导出的声明是子节点
+    Export,
+    ExprStmt,
+    /// 函数声明和方法，函数体是一个 Block 子节点
+    Function,
+    /// 带默认值的参数 `name = value`，没有默认值的参数是 token
+    Parameter,
+    If,
+    /// `as`、`from` 是 IDENTIFIER token
+    Import,
+    Print,
+    Return,
+    Var,
+    While,
+    For,
+    /// `for (x in xs)`，循环变量和 in 是 token
+    ForIn,
+    Assignment,
+    Binary,
+    Call,
+    Comma,
+    /// 第一个子节点是赋值目标
+    Compound,
+    Conditional,
+    Get,
+    Grouping,
+    Index,
+    /// 第一个子节点是被赋值的 Index
+    IndexSet,
+    /// `fun` 形式的函数体是 Block 子节点，箭头形式的可以是表达式子节点
+    Lambda,
+    List,
+    Map,
+    Literal,
+    /// 命名实参 `name: value`，是 Call 的子节点
+    NamedArgument,
+    Logical,
+    /// 含有 `?.` 的整条访问链，唯一的子节点是链本身
+    Optional,
+    /// 第一个子节点是被赋值的 Get
+    Set,
+    Super,
+    This,
+    Unary,
+    /// 前缀时运算符在前，后缀时在后
+    Update,
+    Variable,
+    /// 有语法错误时的全部 token
+    Error,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct SyntaxNode {
+    pub(crate) kind: SyntaxKind,
+    pub(crate) children: Vec<SyntaxElement>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(Token),
+}
+
+/// 解析结果
+pub(crate) struct SyntaxTree {
+    pub(crate) root: SyntaxNode,
+    /// 扫描和解析的错误
+    pub(crate) diagnostics: Vec<Diagnostic>,
+}
+
+impl SyntaxTree {
+    pub(crate) fn parse(source: &str) -> Self {
+        let mut scanner = Scanner::with_trivia(source.to_string());
+        let tokens = scanner.scan_tokens();
+        let mut diagnostics = std::mem::take(&mut scanner.diagnostics);
+
+        let mut parser = Parse::new(scanner);
+        let statements = parser.parse();
+        let mut builder = Builder { tokens, cursor: 0 };
+        let mut children = Vec::new();
+        if parser.diagnostics.is_empty() {
+            for stmt in &statements {
+                children.push(SyntaxElement::Node(builder.stmt(stmt)));
+            }
+        } else {
+            let rest = builder.tokens.len() - 1;
+            let error = builder.tokens[..rest]
+                .iter()
+                .cloned()
+                .map(SyntaxElement::Token)
+                .collect();
+            children.push(SyntaxElement::Node(SyntaxNode {
+                kind: SyntaxKind::Error,
+                children: error,
+            }));
+            builder.cursor = rest;
+        }
+        diagnostics.append(&mut parser.diagnostics);
+        children.push(SyntaxElement::Token(builder.tokens[builder.cursor].clone()));
+
+        Self {
+            root: SyntaxNode {
+                kind: SyntaxKind::Root,
+                children,
+            },
+            diagnostics,
+        }
+    }
+}
+
+impl SyntaxNode {
+    /// 按源码顺序列出节点里的所有 token
+    pub(crate) fn tokens(&self) -> Vec<&Token> {
+        let mut tokens = Vec::new();
+        self.collect_tokens(&mut tokens);
+        tokens
+    }
+
+    fn collect_tokens<'a>(&'a self, tokens: &mut Vec<&'a Token>) {
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => node.collect_tokens(tokens),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+    }
+}
+
+/// 输出节点对应的原文，包括 trivia
+impl Display for SyntaxNode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for token in self.tokens() {
+            for trivia in &token.leading {
+                f.write_str(&trivia.text)?;
+            }
+            f.write_str(&token.lexeme)?;
+            for trivia in &token.trailing {
+                f.write_str(&trivia.text)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 沿着 AST 把 token 依次分配到节点上
+struct Builder {
+    tokens: Vec<Token>,
+    cursor: usize,
+}
+
+impl Builder {
+    fn stmt(&mut self, stmt: &Stmt) -> SyntaxNode {
+        let mut children = Vec::new();
+        let kind = match stmt {
+            Stmt::Block(block) => return self.block(&block.statements),
+            Stmt::Break(_) => {
+                self.bump(&mut children, &[TokenType::BREAK]);
+                self.bump(&mut children, &[TokenType::SEMICOLON]);
+                SyntaxKind::Break
+            }
+            Stmt::Continue(_) => {
+                self.bump(&mut children, &[TokenType::CONTINUE]);
+                self.bump(&mut children, &[TokenType::SEMICOLON]);
+                SyntaxKind::Continue
+            }
+            Stmt::Expression(expression) => {
+                children.push(SyntaxElement::Node(self.expr(&expression.expr)));
+                self.bump(&mut children, &[TokenType::SEMICOLON]);
+                SyntaxKind::ExprStmt
+            }
+            Stmt::Class(class) => {
+                self.bump(&mut children, &[TokenType::CLASS]);
+                self.bump(&mut children, &[TokenType::IDENTIFIER]);
+                if class.superclass.is_some() {
+                    self.bump(&mut children, &[TokenType::LESS]);
+                    self.bump(&mut children, &[TokenType::IDENTIFIER]);
+                }
+                self.bump(&mut children, &[TokenType::LEFT_BRACE]);
+                for method in &class.methods {
+                    children.push(SyntaxElement::Node(self.function(method)));
+                }
+                self.bump(&mut children, &[TokenType::RIGHT_BRACE]);
+                SyntaxKind::Class
+            }
+            Stmt::Function(function) => return self.function(function),
+            Stmt::If(stmt) => {
+                self.bump(&mut children, &[TokenType::IF]);
+                self.bump(&mut children, &[TokenType::LEFT_PAREN]);
+                children.push(SyntaxElement::Node(self.expr(&stmt.condition)));
+                self.bump(&mut children, &[TokenType::RIGHT_PAREN]);
+                children.push(SyntaxElement::Node(self.stmt(&stmt.then_branch)));
+                if let Some(else_branch) = &stmt.else_branch {
+                    self.bump(&mut children, &[TokenType::ELSE]);
+                    children.push(SyntaxElement::Node(self.stmt(else_branch)));
+                }
+                SyntaxKind::If
+            }
+            Stmt::Print(print) => {
+                self.bump(&mut children, &[TokenType::PRINT]);
+                children.push(SyntaxElement::Node(self.expr(&print.expr)));
+                self.bump(&mut children, &[TokenType::SEMICOLON]);
+                SyntaxKind::Print
+            }
+            Stmt::Return(stmt) => {
+                self.bump(&mut children, &[TokenType::RETURN]);
+                if let Some(value) = &stmt.value {
+                    children.push(SyntaxElement::Node(self.expr(value)));
+                }
+                self.bump(&mut children, &[TokenType::SEMICOLON]);
+                SyntaxKind::Return
+            }
+            Stmt::Var(var) => {
+                self.bump(&mut children, &[TokenType::VAR]);
+                self.bump(&mut children, &[TokenType::IDENTIFIER]);
+                if let Some(initializer) = &var.initializer {
+                    self.bump(&mut children, &[TokenType::EQUAL]);
+                    children.push(SyntaxElement::Node(self.expr(initializer)));
+                }
+                self.bump(&mut children, &[TokenType::SEMICOLON]);
+                SyntaxKind::Var
+            }
+            Stmt::Export(export) => {
+                self.bump(&mut children, &[TokenType::EXPORT]);
+                children.push(SyntaxElement::Node(self.stmt(&export.declaration)));
+                SyntaxKind::Export
+            }
+            Stmt::Import(import) => {
+                self.bump(&mut children, &[TokenType::IMPORT]);
+                if let Imported::Names(names) = &import.imported {
+                    self.bump(&mut children, &[TokenType::LEFT_BRACE]);
+                    for i in 0..names.len() {
+                        if i > 0 {
+                            self.bump(&mut children, &[TokenType::COMMA]);
+                        }
+                        self.bump(&mut children, &[TokenType::IDENTIFIER]);
+                    }
+                    self.bump(&mut children, &[TokenType::RIGHT_BRACE]);
+                    self.bump(&mut children, &[TokenType::IDENTIFIER]);
+                }
+                self.bump(&mut children, &[TokenType::STRING]);
+                if let Imported::Module(_) = &import.imported {
+                    self.bump(&mut children, &[TokenType::IDENTIFIER]);
+                    self.bump(&mut children, &[TokenType::IDENTIFIER]);
+                }
+                self.bump(&mut children, &[TokenType::SEMICOLON]);
+                SyntaxKind::Import
+            }
+            Stmt::Const(constant) => {
+                self.bump(&mut children, &[TokenType::CONST]);
+                self.bump(&mut children, &[TokenType::IDENTIFIER]);
+                self.bump(&mut children, &[TokenType::EQUAL]);
+                children.push(SyntaxElement::Node(self.expr(&constant.initializer)));
+                self.bump(&mut children, &[TokenType::SEMICOLON]);
+                SyntaxKind::Const
+            }
+            Stmt::While(stmt) => {
+                self.bump(&mut children, &[TokenType::WHILE]);
+                self.bump(&mut children, &[TokenType::LEFT_PAREN]);
+                children.push(SyntaxElement::Node(self.expr(&stmt.condition)));
+                self.bump(&mut children, &[TokenType::RIGHT_PAREN]);
+                children.push(SyntaxElement::Node(self.stmt(&stmt.body)));
+                SyntaxKind::While
+            }
+            Stmt::For(stmt) => {
+                self.bump(&mut children, &[TokenType::FOR]);
+                self.bump(&mut children, &[TokenType::LEFT_PAREN]);
+                match &stmt.initializer {
+                    // 初始化部分是完整的语句，自带分号
+                    Some(initializer) => children.push(SyntaxElement::Node(self.stmt(initializer))),
+                    None => self.bump(&mut children, &[TokenType::SEMICOLON]),
+                }
+                if let Some(condition) = &stmt.condition {
+                    children.push(SyntaxElement::Node(self.expr(condition)));
+                }
+                self.bump(&mut children, &[TokenType::SEMICOLON]);
+                if let Some(increment) = &stmt.increment {
+                    children.push(SyntaxElement::Node(self.expr(increment)));
+                }
+                self.bump(&mut children, &[TokenType::RIGHT_PAREN]);
+                children.push(SyntaxElement::Node(self.stmt(&stmt.body)));
+                SyntaxKind::For
+            }
+            Stmt::ForIn(stmt) => {
+                self.bump(&mut children, &[TokenType::FOR]);
+                self.bump(&mut children, &[TokenType::LEFT_PAREN]);
+                self.bump(&mut children, &[TokenType::IDENTIFIER]);
+                self.bump(&mut children, &[TokenType::IN]);
+                children.push(SyntaxElement::Node(self.expr(&stmt.iterable)));
+                self.bump(&mut children, &[TokenType::RIGHT_PAREN]);
+                children.push(SyntaxElement::Node(self.stmt(&stmt.body)));
+                SyntaxKind::ForIn
+            }
+        };
+        SyntaxNode { kind, children }
+    }
+
+    /// 方法前面没有 fun，bump 会跳过
+    fn function(&mut self, function: &Function) -> SyntaxNode {
+        let mut children = Vec::new();
+        self.bump(&mut children, &[TokenType::FUN]);
+        self.bump(&mut children, &[TokenType::IDENTIFIER]);
+        self.bump(&mut children, &[TokenType::LEFT_PAREN]);
+        self.parameters(&mut children, function);
+        self.bump(&mut children, &[TokenType::RIGHT_PAREN]);
+        children.push(SyntaxElement::Node(self.block(&function.body)));
+        SyntaxNode {
+            kind: SyntaxKind::Function,
+            children,
+        }
+    }
+
+    /// 参数列表，不含括号
+    fn parameters(&mut self, children: &mut Vec<SyntaxElement>, function: &Function) {
+        for (i, param) in function.params.iter().enumerate() {
+            if i > 0 {
+                self.bump(children, &[TokenType::COMMA]);
+            }
+            let Some(default) = &param.default else {
+                self.bump(children, &[TokenType::IDENTIFIER]);
+                continue;
+            };
+            let mut parameter = Vec::new();
+            self.bump(&mut parameter, &[TokenType::IDENTIFIER]);
+            self.bump(&mut parameter, &[TokenType::EQUAL]);
+            parameter.push(SyntaxElement::Node(self.expr(default)));
+            children.push(SyntaxElement::Node(SyntaxNode {
+                kind: SyntaxKind::Parameter,
+                children: parameter,
+            }));
+        }
+        if function.rest.is_some() {
+            if !function.params.is_empty() {
+                self.bump(children, &[TokenType::COMMA]);
+            }
+            self.bump(children, &[TokenType::DOT_DOT_DOT]);
+            self.bump(children, &[TokenType::IDENTIFIER]);
+        }
+    }
+
+    fn block(&mut self, statements: &[Stmt]) -> SyntaxNode {
+        let mut children = Vec::new();
+        self.bump(&mut children, &[TokenType::LEFT_BRACE]);
+        for stmt in statements {
+            children.push(SyntaxElement::Node(self.stmt(stmt)));
+        }
+        self.bump(&mut children, &[TokenType::RIGHT_BRACE]);
+        SyntaxNode {
+            kind: SyntaxKind::Block,
+            children,
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) -> SyntaxNode {
+        let mut children = Vec::new();
+        let kind = match expr {
+            Expr::Assignment(assignment) => {
+                self.bump(&mut children, &[TokenType::IDENTIFIER]);
+                self.bump(&mut children, &[TokenType::EQUAL]);
+                children.push(SyntaxElement::Node(self.expr(&assignment.value)));
+                SyntaxKind::Assignment
+            }
+            Expr::Binary(binary) => {
+                children.push(SyntaxElement::Node(self.expr(&binary.left)));
+                self.bump(&mut children, &[binary.operator.token_type]);
+                children.push(SyntaxElement::Node(self.expr(&binary.right)));
+                SyntaxKind::Binary
+            }
+            Expr::Call(call) => {
+                children.push(SyntaxElement::Node(self.expr(&call.callee)));
+                self.bump(&mut children, &[TokenType::LEFT_PAREN]);
+                for (i, argument) in call.arguments.iter().enumerate() {
+                    if i > 0 {
+                        self.bump(&mut children, &[TokenType::COMMA]);
+                    }
+                    children.push(SyntaxElement::Node(self.expr(argument)));
+                }
+                for (i, (_, argument)) in call.named.iter().enumerate() {
+                    if i > 0 || !call.arguments.is_empty() {
+                        self.bump(&mut children, &[TokenType::COMMA]);
+                    }
+                    let mut named = Vec::new();
+                    self.bump(&mut named, &[TokenType::IDENTIFIER]);
+                    self.bump(&mut named, &[TokenType::COLON]);
+                    named.push(SyntaxElement::Node(self.expr(argument)));
+                    children.push(SyntaxElement::Node(SyntaxNode {
+                        kind: SyntaxKind::NamedArgument,
+                        children: named,
+                    }));
+                }
+                self.bump(&mut children, &[TokenType::RIGHT_PAREN]);
+                SyntaxKind::Call
+            }
+            Expr::Get(get) => {
+                children.push(SyntaxElement::Node(self.expr(&get.object)));
+                self.bump(&mut children, &[TokenType::DOT, TokenType::QUESTION_DOT]);
+                self.bump(&mut children, &[TokenType::IDENTIFIER]);
+                SyntaxKind::Get
+            }
+            Expr::Set(set) => {
+                let mut target = vec![SyntaxElement::Node(self.expr(&set.object))];
+                self.bump(&mut target, &[TokenType::DOT]);
+                self.bump(&mut target, &[TokenType::IDENTIFIER]);
+                children.push(SyntaxElement::Node(SyntaxNode {
+                    kind: SyntaxKind::Get,
+                    children: target,
+                }));
+                self.bump(&mut children, &[TokenType::EQUAL]);
+                children.push(SyntaxElement::Node(self.expr(&set.value)));
+                SyntaxKind::Set
+            }
+            Expr::Index(index) => {
+                children.push(SyntaxElement::Node(self.expr(&index.object)));
+                self.bump(&mut children, &[TokenType::LEFT_BRACKET]);
+                children.push(SyntaxElement::Node(self.expr(&index.index)));
+                self.bump(&mut children, &[TokenType::RIGHT_BRACKET]);
+                SyntaxKind::Index
+            }
+            Expr::IndexSet(index_set) => {
+                let mut target = vec![SyntaxElement::Node(self.expr(&index_set.object))];
+                self.bump(&mut target, &[TokenType::LEFT_BRACKET]);
+                target.push(SyntaxElement::Node(self.expr(&index_set.index)));
+                self.bump(&mut target, &[TokenType::RIGHT_BRACKET]);
+                children.push(SyntaxElement::Node(SyntaxNode {
+                    kind: SyntaxKind::Index,
+                    children: target,
+                }));
+                self.bump(&mut children, &[TokenType::EQUAL]);
+                children.push(SyntaxElement::Node(self.expr(&index_set.value)));
+                SyntaxKind::IndexSet
+            }
+            Expr::List(list) => {
+                self.bump(&mut children, &[TokenType::LEFT_BRACKET]);
+                for (i, element) in list.elements.iter().enumerate() {
+                    if i > 0 {
+                        self.bump(&mut children, &[TokenType::COMMA]);
+                    }
+                    children.push(SyntaxElement::Node(self.expr(element)));
+                }
+                self.bump(&mut children, &[TokenType::RIGHT_BRACKET]);
+                SyntaxKind::List
+            }
+            Expr::Map(map) => {
+                self.bump(&mut children, &[TokenType::LEFT_BRACE]);
+                for (i, (key, value)) in map.entries.iter().enumerate() {
+                    if i > 0 {
+                        self.bump(&mut children, &[TokenType::COMMA]);
+                    }
+                    children.push(SyntaxElement::Node(self.expr(key)));
+                    self.bump(&mut children, &[TokenType::COLON]);
+                    children.push(SyntaxElement::Node(self.expr(value)));
+                }
+                self.bump(&mut children, &[TokenType::RIGHT_BRACE]);
+                SyntaxKind::Map
+            }
+            Expr::Super(_) => {
+                self.bump(&mut children, &[TokenType::SUPER]);
+                self.bump(&mut children, &[TokenType::DOT]);
+                self.bump(&mut children, &[TokenType::IDENTIFIER]);
+                SyntaxKind::Super
+            }
+            Expr::This(_) => {
+                self.bump(&mut children, &[TokenType::THIS]);
+                SyntaxKind::This
+            }
+            Expr::Lambda(lambda) => {
+                let function = &lambda.function;
+                let arrow = lambda.keyword.token_type == TokenType::ARROW;
+                if !arrow {
+                    self.bump(&mut children, &[TokenType::FUN]);
+                }
+                self.bump(&mut children, &[TokenType::LEFT_PAREN]);
+                self.parameters(&mut children, function);
+                self.bump(&mut children, &[TokenType::RIGHT_PAREN]);
+                if arrow {
+                    self.bump(&mut children, &[TokenType::ARROW]);
+                }
+                // 表达式函数体是解析器包出来的 return，源码里没有 return 关键字
+                match function.body.as_slice() {
+                    [
+                        Stmt::Return(Return {
+                            keyword,
+                            value: Some(value),
+                        }),
+                    ] if keyword.token_type == TokenType::ARROW => {
+                        children.push(SyntaxElement::Node(self.expr(value)));
+                    }
+                    body => children.push(SyntaxElement::Node(self.block(body))),
+                }
+                SyntaxKind::Lambda
+            }
+            Expr::Optional(optional) => {
+                children.push(SyntaxElement::Node(self.expr(&optional.expr)));
+                SyntaxKind::Optional
+            }
+            Expr::Grouping(grouping) => {
+                self.bump(&mut children, &[TokenType::LEFT_PAREN]);
+                children.push(SyntaxElement::Node(self.expr(&grouping.expr)));
+                self.bump(&mut children, &[TokenType::RIGHT_PAREN]);
+                SyntaxKind::Grouping
+            }
+            Expr::Comma(comma) => {
+                children.push(SyntaxElement::Node(self.expr(&comma.left)));
+                self.bump(&mut children, &[TokenType::COMMA]);
+                children.push(SyntaxElement::Node(self.expr(&comma.right)));
+                SyntaxKind::Comma
+            }
+            Expr::Compound(compound) => {
+                children.push(SyntaxElement::Node(self.expr(&compound.target)));
+                self.bump(&mut children, &[compound.operator.token_type]);
+                children.push(SyntaxElement::Node(self.expr(&compound.value)));
+                SyntaxKind::Compound
+            }
+            Expr::Conditional(conditional) => {
+                children.push(SyntaxElement::Node(self.expr(&conditional.condition)));
+                self.bump(&mut children, &[TokenType::QUESTION]);
+                children.push(SyntaxElement::Node(self.expr(&conditional.then_branch)));
+                self.bump(&mut children, &[TokenType::COLON]);
+                children.push(SyntaxElement::Node(self.expr(&conditional.else_branch)));
+                SyntaxKind::Conditional
+            }
+            Expr::Logical(logical) => {
+                children.push(SyntaxElement::Node(self.expr(&logical.left)));
+                self.bump(&mut children, &[logical.operator.token_type]);
+                children.push(SyntaxElement::Node(self.expr(&logical.right)));
+                SyntaxKind::Logical
+            }
+            Expr::Unary(unary) => {
+                self.bump(&mut children, &[unary.operator.token_type]);
+                children.push(SyntaxElement::Node(self.expr(&unary.right)));
+                SyntaxKind::Unary
+            }
+            Expr::Update(update) => {
+                if update.prefix {
+                    self.bump(&mut children, &[update.operator.token_type]);
+                }
+                children.push(SyntaxElement::Node(self.expr(&update.target)));
+                if !update.prefix {
+                    self.bump(&mut children, &[update.operator.token_type]);
+                }
+                SyntaxKind::Update
+            }
+            Expr::Variable(_) => {
+                self.bump(&mut children, &[TokenType::IDENTIFIER]);
+                SyntaxKind::Variable
+            }
+            Expr::Literal(_) => {
+                self.bump(
+                    &mut children,
+                    &[
+                        TokenType::NUMBER,
+                        TokenType::STRING,
+                        TokenType::TRUE,
+                        TokenType::FALSE,
+                        TokenType::NIL,
+                    ],
+                );
+                SyntaxKind::Literal
+            }
+        };
+        SyntaxNode { kind, children }
+    }
+
+    /// 游标处的 token 类型符合时把它加进节点
+    fn bump(&mut self, children: &mut Vec<SyntaxElement>, expected: &[TokenType]) {
+        let token = &self.tokens[self.cursor];
+        if expected.contains(&token.token_type) {
+            children.push(SyntaxElement::Token(token.clone()));
+            self.cursor += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lossless() {
+        let sources = [
+            "",
+            "  \n",
+            "1 + 2;",
+            "// 注释\nprint ( 1 +/* b */2 ) ; // c\r\n\t// d\n",
+            "-(\"多行\n字符串\" == nil);",
+            "var a=1;{ a = a or 2; }\nif (a) print a; else {}\n",
+            "while(false)print 1;for(;;){}for(var i=0;i<1;i=i+1)print i;",
+            "fun f ( a,b ) { return a(b , 1)() ; }\nfun g(){return;}",
+            "class A<B{init(x){this . x=x is A;super.m(); }}a.b.c=1;",
+            "var xs = [ 1,[ ] ,3 ];xs [ -1 ]=xs[0][ 1 ];",
+            "var m={ \"a\" :1,2:{}};{print m[\"a\"];}",
+            "for(x  in\n[1,2])print x;for (k in m) {}",
+            "while(true){break ;continue/* c */;}",
+            "const  N=1 ;{const s = \"a\";}",
+            "import \"m\"as m;import{a ,b}from \"n\" ;export  fun f(){}",
+            "export var a=1;export class C{}",
+            "print a?b :c?d,e:f;f(a ,(b,c));",
+            "print ~a<<1|b%2**-c~/3 ^x&y>>z;a ~//c\n;a~/*c*/1;",
+            "a+=1;b . c*=- -d;xs[i ++]/=2;--e;m[\"k\"]%= f--;",
+            "print a?.b .c()?. d[0]??e ?? f;",
+            "var f=fun( a ){return a;};f((x,y)=>x+y,( )=>{ print 1; });",
+            "fun g(a,b = [1] ,... rest){}g(1,b :2 ,c: (x = 1)=>x);",
+            "import { a",
+
+            "(1 + 2",
+            "1 2 ) @ \"unterminated",
+            "{ var a = ; print a; }",
+            "/* unterminated block",
+        ];
+        for source in sources {
+            let tree = SyntaxTree::parse(source);
+            assert_eq!(tree.root.to_string(), source);
+        }
+    }
+
+    #[test]
+    fn test_trivia() {
+        let tree = SyntaxTree::parse("  1 // one\n + 2;");
+        let tokens = tree.root.tokens();
+        assert_eq!(tokens[0].leading[0].text, "  ");
+        assert_eq!(tokens[0].trailing.len(), 3);
+        assert_eq!(tokens[0].trailing[1].text, "// one");
+        assert_eq!(tokens[1].leading[0].text, " ");
+        assert_eq!(tokens[1].trailing[0].text, " ");
+    }
+
+    #[test]
+    fn test_error_node() {
+        let tree = SyntaxTree::parse("1 2;");
+        assert_eq!(tree.diagnostics.len(), 1);
+        let SyntaxElement::Node(error) = &tree.root.children[0] else {
+            panic!("expected an error node");
+        };
+        assert_eq!(error.kind, SyntaxKind::Error);
+        assert_eq!(error.tokens().len(), 3);
+    }
+
+    #[test]
+    fn test_statement_nodes() {
+        let tree = SyntaxTree::parse("for (var i = 0; i < 1;) { print i; }");
+        let SyntaxElement::Node(node) = &tree.root.children[0] else {
+            panic!("expected a statement node");
+        };
+        assert_eq!(node.kind, SyntaxKind::For);
+        let kinds: Vec<_> = node
+            .children
+            .iter()
+            .filter_map(|child| match child {
+                SyntaxElement::Node(node) => Some(node.kind),
+                SyntaxElement::Token(_) => None,
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            [SyntaxKind::Var, SyntaxKind::Binary, SyntaxKind::Block]
+        );
+    }
+}
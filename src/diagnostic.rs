@@ -0,0 +1,107 @@
+// 编译期（扫描、解析）错误和 lint 警告
+// 扫描器和解析器把错误收集起来，由调用方决定输出到 stderr 还是发给编辑器。
+use std::{fmt::Display, ops::Range};
+
+use crate::token::{Token, TokenType};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub(crate) severity: Severity,
+    /// 产生这条诊断的 lint 规则
+    pub(crate) rule: Option<&'static str>,
+    pub(crate) line: u32,
+    /// 出错位置的描述，比如 ` at '+'`、` at end`，扫描错误为空
+    pub(crate) wher: String,
+    pub(crate) message: String,
+    /// 出错文本在源码中的字节范围
+    pub(crate) span: Range<usize>,
+    /// 指向另一处相关源码的附注
+    pub(crate) note: Option<Note>,
+}
+
+/// 附注，比如被赋值的常量是在哪里声明的
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Note {
+    pub(crate) line: u32,
+    pub(crate) span: Range<usize>,
+    pub(crate) message: String,
+}
+
+impl Display for Note {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[line {}] Note: {}", self.line, self.message)
+    }
+}
+
+impl Diagnostic {
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    pub(crate) fn new(line: u32, span: Range<usize>, message: &str) -> Self {
+        Self {
+            severity: Severity::Error,
+            rule: None,
+            line,
+            wher: String::new(),
+            message: message.to_string(),
+            span,
+            note: None,
+        }
+    }
+
+    /// 某个 token 处的错误，格式和书中 jlox 一致
+    pub(crate) fn at_token(token: &Token, message: &str) -> Self {
+        let wher = if token.token_type == TokenType::EOF {
+            " at end".to_string()
+        } else {
+            format!(" at '{}'", token.lexeme)
+        };
+        Self {
+            severity: Severity::Error,
+            rule: None,
+            line: token.line,
+            wher,
+            message: message.to_string(),
+            span: token.offset..token.offset + token.lexeme.len(),
+            note: None,
+        }
+    }
+
+    /// 附上指向 `token` 的附注
+    pub(crate) fn with_note(mut self, token: &Token, message: &str) -> Self {
+        self.note = Some(Note {
+            line: token.line,
+            span: token.offset..token.offset + token.lexeme.len(),
+            message: message.to_string(),
+        });
+        self
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "Error",
+            Severity::Warning => "Warning",
+        };
+        write!(
+            f,
+            "[line {}] {}{}: {}",
+            self.line, severity, self.wher, self.message
+        )?;
+        if let Some(rule) = self.rule {
+            write!(f, " ({})", rule)?;
+        }
+        if let Some(note) = &self.note {
+            write!(f, "\n{}", note)?;
+        }
+        Ok(())
+    }
+}
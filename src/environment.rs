@@ -0,0 +1,89 @@
+// 变量环境：每个作用域一张表，通过 enclosing 链到外层作用域
+// resolver 给每个变量算好了作用域距离，运行时直接走到那一层，不会被后来声明的同名变量截住。
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::value::Value;
+
+#[derive(Debug, Default)]
+pub(crate) struct Environment {
+    values: HashMap<String, Value>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+}
+
+impl Environment {
+    pub(crate) fn new(enclosing: Rc<RefCell<Environment>>) -> Self {
+        Self {
+            values: HashMap::new(),
+            enclosing: Some(enclosing),
+        }
+    }
+
+    /// 定义变量，同名时覆盖（允许在全局重新声明）
+    pub(crate) fn define(&mut self, name: &str, value: Value) {
+        self.values.insert(name.to_string(), value);
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<Value> {
+        match self.values.get(name) {
+            Some(value) => Some(value.clone()),
+            None => self.enclosing.as_ref()?.borrow().get(name),
+        }
+    }
+
+    /// 读取 resolver 解析过的变量：往外走 `distance` 层再查。
+    /// 全局作用域里找不到时继续查外面的标准库
+    pub(crate) fn get_at(
+        environment: &Rc<RefCell<Environment>>,
+        distance: usize,
+        name: &str,
+    ) -> Option<Value> {
+        ancestor(environment, distance).borrow().get(name)
+    }
+
+    /// 给 resolver 解析过的变量赋值，见 `get_at`
+    pub(crate) fn assign_at(
+        environment: &Rc<RefCell<Environment>>,
+        distance: usize,
+        name: &str,
+        value: Value,
+    ) -> bool {
+        ancestor(environment, distance)
+            .borrow_mut()
+            .assign(name, value)
+    }
+
+    /// 这个作用域里的所有变量，按名字排序
+    pub(crate) fn bindings(&self) -> Vec<(String, Value)> {
+        let mut bindings: Vec<_> = self
+            .values
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        bindings.sort_by(|a, b| a.0.cmp(&b.0));
+        bindings
+    }
+
+    /// 给已有变量赋值，变量不存在时返回 false
+    pub(crate) fn assign(&mut self, name: &str, value: Value) -> bool {
+        if let Some(slot) = self.values.get_mut(name) {
+            *slot = value;
+            return true;
+        }
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow_mut().assign(name, value),
+            None => false,
+        }
+    }
+}
+
+/// 往外第 `distance` 层作用域，resolver 保证这一层存在
+fn ancestor(environment: &Rc<RefCell<Environment>>, distance: usize) -> Rc<RefCell<Environment>> {
+    let mut environment = environment.clone();
+    for _ in 0..distance {
+        let enclosing = environment.borrow().enclosing.clone();
+        environment = enclosing.expect("resolver counted an existing scope");
+    }
+    environment
+}
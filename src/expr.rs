@@ -1,34 +1,66 @@
 // 表达式相关定义，包含所有 AST 表达式节点类型
+use std::cell::Cell;
+use std::rc::Rc;
+
+use crate::stmt::Function;
 use crate::token::{LiteralType, Token};
-use std::hash::Hash;
 
 /// AST 表达式枚举，代表所有可能的表达式类型
 #[derive(Debug, Clone)]
 pub enum Expr {
     /// 赋值表达式
-    // Assignment(Assignment),
+    Assignment(Assignment),
     /// 二元运算表达式
     Binary(Binary),
+    /// 函数调用
+    Call(Call),
+    /// 逗号表达式 `a, b`，依次求值，结果是右边的值
+    Comma(Comma),
+    /// 复合赋值 `target += value`
+    Compound(Compound),
+    /// 条件表达式 `a ? b : c`
+    Conditional(Conditional),
+    /// 属性访问 `object.name`
+    Get(Get),
     /// 分组表达式 (括号)
     Grouping(Grouping),
+    /// 下标访问 `object[index]`
+    Index(Index),
+    /// 下标赋值 `object[index] = value`
+    IndexSet(IndexSet),
+    /// 匿名函数 `fun (x) { ... }` 和箭头函数 `(x) => x * 2`
+    Lambda(Lambda),
+    /// 列表字面量 `[a, b, c]`
+    List(List),
+    /// 映射字面量 `{"a": 1, "b": 2}`
+    Map(Map),
     /// 字面量表达式
     Literal(Literal),
-    /// 逻辑运算表达式 (and/or)
-    // Logical(Logical),
-    // / 一元运算表达式
+    /// 逻辑运算表达式 (and/or) 和空值合并 `a ?? b`
+    Logical(Logical),
+    /// 含有 `?.` 的访问链，左边是 nil 时整条链的结果都是 nil
+    Optional(Optional),
+    /// 属性赋值 `object.name = value`
+    Set(Set),
+    /// 调用父类方法 `super.method`
+    Super(Super),
+    /// 方法里的 `this`
+    This(This),
+    /// 一元运算表达式
     Unary(Unary),
-    // 变量表达式
-    // Variable(Variable),
+    /// 自增自减 `++target`、`target--`
+    Update(Update),
+    /// 变量表达式
+    Variable(Variable),
 }
 
-
 /// 赋值表达式结构体
 #[derive(Debug, Clone)]
 pub struct Assignment {
-    /// 唯一 id
-    // pub uuid: usize,
     /// 变量名
     pub name: Token,
+    /// 见 `Variable::distance`
+    pub distance: Cell<Option<usize>>,
     /// 赋值的表达式
     pub value: Box<Expr>,
 }
@@ -41,27 +73,144 @@ pub struct Binary {
     pub right: Box<Expr>,
 }
 
+/// 函数调用表达式结构体
+#[derive(Debug, Clone)]
+pub struct Call {
+    pub callee: Box<Expr>,
+    /// 右括号，运行时错误定位用
+    pub paren: Token,
+    pub arguments: Vec<Expr>,
+    /// 命名实参 `name: value`，都在位置实参后面
+    pub named: Vec<(Token, Expr)>,
+}
+
+/// 逗号表达式结构体
+#[derive(Debug, Clone)]
+pub struct Comma {
+    pub left: Box<Expr>,
+    pub right: Box<Expr>,
+}
+
+/// 复合赋值表达式结构体
+#[derive(Debug, Clone)]
+pub struct Compound {
+    /// 变量、属性访问或下标访问，里面的子表达式只求值一次
+    pub target: Box<Expr>,
+    /// `+=` 这样的运算符
+    pub operator: Token,
+    pub value: Box<Expr>,
+}
+
+/// 条件表达式结构体
+#[derive(Debug, Clone)]
+pub struct Conditional {
+    pub condition: Box<Expr>,
+    pub then_branch: Box<Expr>,
+    pub else_branch: Box<Expr>,
+}
+
+/// 属性访问表达式结构体
+#[derive(Debug, Clone)]
+pub struct Get {
+    pub object: Box<Expr>,
+    pub name: Token,
+    /// `object?.name`
+    pub optional: bool,
+}
+
 /// 分组表达式结构体 (括号)
 #[derive(Debug, Clone)]
 pub struct Grouping {
     pub expr: Box<Expr>,
 }
 
+/// 下标访问表达式结构体
+#[derive(Debug, Clone)]
+pub struct Index {
+    pub object: Box<Expr>,
+    /// 右方括号，运行时错误定位用
+    pub bracket: Token,
+    pub index: Box<Expr>,
+}
+
+/// 下标赋值表达式结构体
+#[derive(Debug, Clone)]
+pub struct IndexSet {
+    pub object: Box<Expr>,
+    pub bracket: Token,
+    pub index: Box<Expr>,
+    pub value: Box<Expr>,
+}
+
+/// 列表字面量结构体
+#[derive(Debug, Clone)]
+pub struct List {
+    pub elements: Vec<Expr>,
+}
+
+/// 映射字面量结构体
+#[derive(Debug, Clone)]
+pub struct Map {
+    /// 右花括号，键的类型不对时定位用
+    pub brace: Token,
+    pub entries: Vec<(Expr, Expr)>,
+}
+
+/// 匿名函数结构体
+#[derive(Debug, Clone)]
+pub struct Lambda {
+    /// `fun` 或 `=>`，区分两种写法
+    pub keyword: Token,
+    /// 名字固定是 `lambda`，箭头后面的表达式函数体包成一条 return 语句
+    pub function: Rc<Function>,
+}
+
 /// 字面量表达式结构体
 #[derive(Debug, Clone)]
 pub struct Literal {
     pub value: LiteralType,
 }
 
-/// 逻辑运算表达式结构体 (and/or)
+/// 逻辑运算表达式结构体 (and/or/??)
 #[derive(Debug, Clone)]
 pub struct Logical {
-    pub uuid: usize,
     pub left: Box<Expr>,
     pub operator: Token,
     pub right: Box<Expr>,
 }
 
+/// 可选访问链结构体
+#[derive(Debug, Clone)]
+pub struct Optional {
+    /// 整条访问链，里面至少有一个 `?.`
+    pub expr: Box<Expr>,
+}
+
+/// 属性赋值表达式结构体
+#[derive(Debug, Clone)]
+pub struct Set {
+    pub object: Box<Expr>,
+    pub name: Token,
+    pub value: Box<Expr>,
+}
+
+/// super 表达式结构体
+#[derive(Debug, Clone)]
+pub struct Super {
+    pub keyword: Token,
+    pub method: Token,
+    /// 到定义 super 的作用域的距离，this 在它里面一层
+    pub distance: Cell<Option<usize>>,
+}
+
+/// this 表达式结构体
+#[derive(Debug, Clone)]
+pub struct This {
+    pub keyword: Token,
+    /// 见 `Variable::distance`
+    pub distance: Cell<Option<usize>>,
+}
+
 /// 一元运算表达式结构体
 #[derive(Debug, Clone)]
 pub struct Unary {
@@ -69,41 +218,87 @@ pub struct Unary {
     pub right: Box<Expr>,
 }
 
+/// 自增自减表达式结构体
+#[derive(Debug, Clone)]
+pub struct Update {
+    /// 变量、属性访问或下标访问，里面的子表达式只求值一次
+    pub target: Box<Expr>,
+    /// `++` 或 `--`
+    pub operator: Token,
+    /// 前缀的结果是新值，后缀的结果是旧值
+    pub prefix: bool,
+}
+
 /// 变量表达式结构体
 #[derive(Debug, Clone)]
 pub struct Variable {
     pub name: Token,
+    /// resolver 算出的作用域距离：从当前作用域往外走几层能找到这个名字，
+    /// 没有解析过时是 None，按全局变量查找
+    pub distance: Cell<Option<usize>>,
 }
 
+impl Variable {
+    pub(crate) fn new(name: Token) -> Self {
+        Self {
+            name,
+            distance: Cell::new(None),
+        }
+    }
+}
 
 /// 访问者模式 trait，用于遍历和处理不同类型的表达式节点
 pub trait Visitor<T> {
     fn visit_assignment(&mut self, expr: &Assignment) -> T;
     fn visit_binary(&mut self, expr: &Binary) -> T;
     fn visit_grouping(&mut self, expr: &Grouping) -> T;
+    fn visit_lambda(&mut self, expr: &Lambda) -> T;
     fn visit_literal(&self, expr: &Literal) -> T;
     fn visit_logical(&mut self, expr: &Logical) -> T;
+    fn visit_optional(&mut self, expr: &Optional) -> T;
     fn visit_unary(&mut self, expr: &Unary) -> T;
+    fn visit_update(&mut self, expr: &Update) -> T;
     fn visit_variable(&mut self, expr: &Variable) -> T;
-    // fn visit_call(&mut self, expr: &Call) -> T;
-    // fn visit_get(&mut self, expr: &Get) -> T;
-    // fn visit_set(&mut self, expr: &Set) -> T;
-    // fn visit_this(&mut self, expr: &This) -> T;
-    // fn visit_super(&mut self, expr: &Super) -> T;
+    fn visit_call(&mut self, expr: &Call) -> T;
+    fn visit_comma(&mut self, expr: &Comma) -> T;
+    fn visit_compound(&mut self, expr: &Compound) -> T;
+    fn visit_conditional(&mut self, expr: &Conditional) -> T;
+    fn visit_get(&mut self, expr: &Get) -> T;
+    fn visit_set(&mut self, expr: &Set) -> T;
+    fn visit_this(&mut self, expr: &This) -> T;
+    fn visit_super(&mut self, expr: &Super) -> T;
+    fn visit_index(&mut self, expr: &Index) -> T;
+    fn visit_index_set(&mut self, expr: &IndexSet) -> T;
+    fn visit_list(&mut self, expr: &List) -> T;
+    fn visit_map(&mut self, expr: &Map) -> T;
 }
 
 impl Expr {
     /// 访问者模式入口，根据表达式类型分派到对应的 visit 方法
     pub fn accept<T>(&self, visitor: &mut dyn Visitor<T>) -> T {
         match self {
-            // Expr::Assignment(assignment) => visitor.visit_assignment(assignment),
+            Expr::Assignment(assignment) => visitor.visit_assignment(assignment),
             Expr::Binary(binary) => visitor.visit_binary(binary),
+            Expr::Call(call) => visitor.visit_call(call),
+            Expr::Comma(comma) => visitor.visit_comma(comma),
+            Expr::Compound(compound) => visitor.visit_compound(compound),
+            Expr::Conditional(conditional) => visitor.visit_conditional(conditional),
             Expr::Grouping(grouping) => visitor.visit_grouping(grouping),
+            Expr::Lambda(lambda) => visitor.visit_lambda(lambda),
             Expr::Literal(literal) => visitor.visit_literal(literal),
-            // Expr::Logical(logical) => visitor.visit_logical(logical),
+            Expr::Logical(logical) => visitor.visit_logical(logical),
+            Expr::Optional(optional) => visitor.visit_optional(optional),
             Expr::Unary(unary) => visitor.visit_unary(unary),
-            // Expr::Variable(variable) => visitor.visit_variable(variable),
+            Expr::Update(update) => visitor.visit_update(update),
+            Expr::Variable(variable) => visitor.visit_variable(variable),
+            Expr::Get(get) => visitor.visit_get(get),
+            Expr::Set(set) => visitor.visit_set(set),
+            Expr::This(this) => visitor.visit_this(this),
+            Expr::Super(sup) => visitor.visit_super(sup),
+            Expr::Index(index) => visitor.visit_index(index),
+            Expr::IndexSet(index_set) => visitor.visit_index_set(index_set),
+            Expr::List(list) => visitor.visit_list(list),
+            Expr::Map(map) => visitor.visit_map(map),
         }
     }
-
-}
\ No newline at end of file
+}
@@ -0,0 +1,850 @@
+// 源码格式化 (lox fmt)
+// 在无损语法树上工作：按节点结构重新排版 token，注释从 token 的 trivia 里取回。
+// 每条语句占一行，代码块缩进一级；语句之间的空行最多保留一行。
+use crate::cst::{SyntaxElement, SyntaxKind, SyntaxNode, SyntaxTree};
+use crate::diagnostic::Diagnostic;
+use crate::token::{Token, TokenType, Trivia, TriviaKind};
+
+/// 格式化选项
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormatConfig {
+    /// 每级缩进的空格数
+    pub indent_width: usize,
+    /// 单行最大宽度，超过时在运算符前换行，括号里的列表每项一行
+    pub max_width: usize,
+}
+
+impl Default for FormatConfig {
+    fn default() -> Self {
+        Self {
+            indent_width: 4,
+            max_width: 80,
+        }
+    }
+}
+
+/// 格式化一段源码
+///
+/// 代码有词法或语法错误时不做格式化，返回这些错误，由调用方决定怎么输出。
+pub fn format_source(source: &str, config: &FormatConfig) -> Result<String, Vec<Diagnostic>> {
+    let tree = SyntaxTree::parse(source);
+    if !tree.diagnostics.is_empty() {
+        return Err(tree.diagnostics);
+    }
+
+    let mut printer = Printer {
+        config,
+        lines: Vec::new(),
+        line: String::new(),
+        break_pending: false,
+        space_pending: false,
+        keep_blank: false,
+    };
+    for child in &tree.root.children {
+        match child {
+            SyntaxElement::Node(node) => printer.stmt(node, 0),
+            // 文件末尾单独成行的注释
+            SyntaxElement::Token(eof) => {
+                printer.newline(0);
+                printer.comments(&eof.leading);
+            }
+        }
+    }
+    Ok(printer.finish())
+}
+
+struct Printer<'a> {
+    config: &'a FormatConfig,
+    lines: Vec<String>,
+    line: String,
+    /// 上一个 token 后面跟着单行注释，下一个 token 要换行
+    break_pending: bool,
+    /// 刚输出行内块注释，下一个 token 和它隔一个空格，右括号、分号和逗号除外
+    space_pending: bool,
+    /// 正在输出语句第一个 token 的前导 trivia，其中的空行要保留
+    keep_blank: bool,
+}
+
+impl Printer<'_> {
+    /// 另起一行输出语句
+    fn stmt(&mut self, node: &SyntaxNode, depth: usize) {
+        self.newline(depth);
+        self.keep_blank = true;
+        self.stmt_inline(node, depth);
+    }
+
+    /// 在当前行接着输出语句，`depth` 是语句所在的缩进级别
+    fn stmt_inline(&mut self, node: &SyntaxNode, depth: usize) {
+        let children = &node.children;
+        match node.kind {
+            SyntaxKind::Block => self.braced(children, depth),
+            SyntaxKind::Class => {
+                // class Name < Super { ... }
+                let open = children
+                    .iter()
+                    .position(|child| is_token(child, TokenType::LEFT_BRACE))
+                    .unwrap_or(children.len());
+                for child in &children[..open] {
+                    self.element(child, depth, 0);
+                    self.line.push(' ');
+                }
+                self.braced(&children[open..], depth);
+            }
+            SyntaxKind::ExprStmt => {
+                self.element(&children[0], depth, 1);
+                self.element(&children[1], depth, 0);
+            }
+            SyntaxKind::Print => {
+                self.element(&children[0], depth, 0);
+                self.line.push(' ');
+                self.element(&children[1], depth, 1);
+                self.element(&children[2], depth, 0);
+            }
+            SyntaxKind::Function => {
+                // fun name(a, b) { ... }，方法没有 fun
+                let open = children
+                    .iter()
+                    .position(|child| is_token(child, TokenType::LEFT_PAREN))
+                    .unwrap_or(0);
+                for child in &children[..open] {
+                    self.element(child, depth, 0);
+                    if is_token(child, TokenType::FUN) {
+                        self.line.push(' ');
+                    }
+                }
+                // 参数列表后面还有 ` {`
+                let params = &children[open..children.len() - 1];
+                self.delimited(SyntaxKind::Function, params, depth, 2);
+                self.body(&children[children.len() - 1], depth);
+            }
+            SyntaxKind::Export => {
+                self.element(&children[0], depth, 0);
+                self.line.push(' ');
+                match &children[1] {
+                    SyntaxElement::Node(node) => self.stmt_inline(node, depth),
+                    token => self.element(token, depth, 0),
+                }
+            }
+            SyntaxKind::Import => {
+                // import { a, b } from "path"; 或 import "path" as name;
+                for (i, child) in children.iter().enumerate() {
+                    if i > 0 && !is_comma(child) && !is_token(child, TokenType::SEMICOLON) {
+                        self.line.push(' ');
+                    }
+                    self.element(child, depth, 0);
+                }
+            }
+            SyntaxKind::Return => {
+                self.element(&children[0], depth, 0);
+                if children.len() > 2 {
+                    self.line.push(' ');
+                    self.element(&children[1], depth, 1);
+                }
+                self.element(&children[children.len() - 1], depth, 0);
+            }
+            SyntaxKind::Var | SyntaxKind::Const => {
+                self.element(&children[0], depth, 0);
+                self.line.push(' ');
+                self.element(&children[1], depth, 0);
+                if children.len() > 3 {
+                    self.line.push(' ');
+                    self.element(&children[2], depth, 0);
+                    self.line.push(' ');
+                    self.element(&children[3], depth, 1);
+                }
+                self.element(&children[children.len() - 1], depth, 0);
+            }
+            SyntaxKind::If => {
+                self.header(&children[..4], depth);
+                self.body(&children[4], depth);
+                if children.len() > 5 {
+                    // `} // 注释` 之后 else 另起一行，和 if 对齐
+                    if is_block(&children[4]) && !self.break_pending {
+                        self.line.push(' ');
+                    } else {
+                        self.newline(depth);
+                    }
+                    self.element(&children[5], depth, 0);
+                    match &children[6] {
+                        // else if 接在同一行
+                        SyntaxElement::Node(node) if node.kind == SyntaxKind::If => {
+                            self.line.push(' ');
+                            self.stmt_inline(node, depth);
+                        }
+                        body => self.body(body, depth),
+                    }
+                }
+            }
+            SyntaxKind::While => {
+                self.header(&children[..4], depth);
+                self.body(&children[4], depth);
+            }
+            SyntaxKind::For => {
+                self.element(&children[0], depth, 0);
+                self.line.push(' ');
+                self.element(&children[1], depth, 0);
+                let clauses = &children[2..children.len() - 1];
+                for child in clauses {
+                    match child {
+                        SyntaxElement::Node(node) => {
+                            if self.line.ends_with(';') {
+                                self.line.push(' ');
+                            }
+                            if matches!(node.kind, SyntaxKind::Var | SyntaxKind::ExprStmt) {
+                                self.stmt_inline(node, depth);
+                            } else {
+                                self.node(node, depth + 1, 1);
+                            }
+                        }
+                        SyntaxElement::Token(token) => self.token(token),
+                    }
+                }
+                self.body(&children[children.len() - 1], depth);
+            }
+            SyntaxKind::ForIn => {
+                // for (x in xs)
+                self.element(&children[0], depth, 0);
+                self.line.push(' ');
+                self.element(&children[1], depth, 0);
+                self.element(&children[2], depth, 0);
+                self.line.push(' ');
+                self.element(&children[3], depth, 0);
+                self.line.push(' ');
+                self.element(&children[4], depth + 1, 1);
+                self.element(&children[5], depth, 0);
+                self.body(&children[6], depth);
+            }
+            _ => self.flat(node),
+        }
+    }
+
+    /// 花括号包起来的语句或方法，每个一行
+    fn braced(&mut self, children: &[SyntaxElement], depth: usize) {
+        self.element(&children[0], depth, 0);
+        let inner = &children[1..children.len() - 1];
+        for (i, child) in inner.iter().enumerate() {
+            let SyntaxElement::Node(node) = child else {
+                continue;
+            };
+            if i == 0 {
+                // 块开头不留空行
+                self.newline(depth + 1);
+                self.stmt_inline(node, depth + 1);
+            } else {
+                self.stmt(node, depth + 1);
+            }
+        }
+        let SyntaxElement::Token(close) = &children[children.len() - 1] else {
+            return;
+        };
+        // 右花括号前的注释属于块内部
+        if !inner.is_empty() || has_comments(&close.leading) {
+            self.newline(depth + 1);
+            self.comments(&close.leading);
+            self.newline(depth);
+        }
+        // `{ // 注释` 之后的右花括号和左花括号所在的行对齐
+        if self.break_pending {
+            self.newline(depth);
+        }
+        self.token_body(close);
+    }
+
+    /// `if (条件)`、`while (条件)`
+    fn header(&mut self, children: &[SyntaxElement], depth: usize) {
+        self.element(&children[0], depth, 0);
+        self.line.push(' ');
+        self.element(&children[1], depth, 0);
+        self.element(&children[2], depth + 1, 1);
+        self.element(&children[3], depth, 0);
+    }
+
+    /// 循环体和分支：代码块跟在同一行，其余语句另起一行缩进
+    fn body(&mut self, body: &SyntaxElement, depth: usize) {
+        let SyntaxElement::Node(node) = body else {
+            return;
+        };
+        if node.kind == SyntaxKind::Block {
+            if self.break_pending {
+                self.newline(depth);
+            } else {
+                self.line.push(' ');
+            }
+            self.stmt_inline(node, depth);
+        } else {
+            self.stmt(node, depth + 1);
+        }
+    }
+
+    /// `trailing` 是节点后面必须跟在同一行的宽度，比如分组的右括号
+    fn node(&mut self, node: &SyntaxNode, level: usize, trailing: usize) {
+        if self.fits(node, trailing) {
+            self.flat(node);
+            return;
+        }
+        let children = &node.children;
+        match node.kind {
+            // 放不下时在运算符前换行，左结合的运算链会排成同一缩进
+            SyntaxKind::Binary | SyntaxKind::Logical => {
+                self.element(&children[0], level, 0);
+                self.newline(level + 1);
+                self.element(&children[1], level + 1, 0);
+                self.line.push(' ');
+                self.element(&children[2], level + 1, trailing);
+            }
+            // 放不下时 `?` 和 `:` 各起一行
+            SyntaxKind::Conditional => {
+                self.element(&children[0], level, 0);
+                for (operator, branch_trailing) in [(1, 0), (3, trailing)] {
+                    self.newline(level + 1);
+                    self.element(&children[operator], level + 1, 0);
+                    self.line.push(' ');
+                    self.element(&children[operator + 1], level + 1, branch_trailing);
+                }
+            }
+            SyntaxKind::Grouping => {
+                self.element(&children[0], level, 0);
+                self.element(&children[1], level + 1, trailing + 1);
+                self.element(&children[2], level, 0);
+            }
+            SyntaxKind::Unary => {
+                self.element(&children[0], level, 0);
+                if is_double_negation(node) {
+                    self.line.push(' ');
+                }
+                self.element(&children[1], level, trailing);
+            }
+            SyntaxKind::Assignment
+            | SyntaxKind::Compound
+            | SyntaxKind::Set
+            | SyntaxKind::IndexSet => {
+                self.element(&children[0], level, 0);
+                self.line.push(' ');
+                self.element(&children[1], level, 0);
+                self.line.push(' ');
+                self.element(&children[2], level, trailing);
+            }
+            SyntaxKind::Call => {
+                self.element(&children[0], level, 1);
+                self.delimited(SyntaxKind::Call, &children[1..], level, trailing);
+            }
+            SyntaxKind::List | SyntaxKind::Map => {
+                self.delimited(node.kind, children, level, trailing)
+            }
+            SyntaxKind::Lambda => self.lambda(node, level, trailing),
+            _ => self.flat(node),
+        }
+    }
+
+    /// 括号包起来、逗号分隔的实参、参数、列表元素或映射条目
+    ///
+    /// 放不下时每项一行，缩进一级，右括号单独一行。
+    fn delimited(
+        &mut self,
+        kind: SyntaxKind,
+        children: &[SyntaxElement],
+        level: usize,
+        trailing: usize,
+    ) {
+        let list = SyntaxNode {
+            kind,
+            children: children.to_vec(),
+        };
+        if children.len() <= 2 || self.fits(&list, trailing) {
+            self.flat(&list);
+            return;
+        }
+        let inner = &children[1..children.len() - 1];
+        self.element(&children[0], level, 0);
+        self.newline(level + 1);
+        for (i, child) in inner.iter().enumerate() {
+            match child {
+                SyntaxElement::Token(token) if is_comma(child) => {
+                    self.token(token);
+                    self.newline(level + 1);
+                }
+                SyntaxElement::Token(token) if is_separator(&list, child) => {
+                    self.token(token);
+                    self.line.push(' ');
+                }
+                _ => {
+                    // 后面紧跟的逗号或冒号要留在同一行
+                    let followed = inner
+                        .get(i + 1)
+                        .is_some_and(|next| is_separator(&list, next));
+                    self.element(child, level + 1, usize::from(followed));
+                }
+            }
+        }
+        self.newline(level);
+        self.element(&children[children.len() - 1], level, 0);
+    }
+
+    /// `fun (a, b) { ... }` 和 `(a, b) => ...`，代码块函数体总是展开成多行
+    fn lambda(&mut self, node: &SyntaxNode, level: usize, trailing: usize) {
+        // 函数体里的语句相对当前行缩进
+        let indent = self.line.len() - self.line.trim_start().len();
+        let depth = indent / self.config.indent_width.max(1);
+        let children = &node.children;
+        let open = children
+            .iter()
+            .position(|child| is_token(child, TokenType::LEFT_PAREN))
+            .unwrap_or(0);
+        let close = children
+            .iter()
+            .position(|child| is_token(child, TokenType::RIGHT_PAREN))
+            .unwrap_or(open);
+        for child in &children[..open] {
+            self.element(child, level, 0);
+            self.line.push(' ');
+        }
+        // 参数列表后面至少还有 ` {` 或 ` =>`
+        self.delimited(SyntaxKind::Function, &children[open..=close], level, 2);
+        for child in &children[close + 1..] {
+            match child {
+                SyntaxElement::Node(block) if block.kind == SyntaxKind::Block => {
+                    if !self.line.ends_with(' ') {
+                        self.line.push(' ');
+                    }
+                    self.braced(&block.children, depth);
+                }
+                SyntaxElement::Node(_) => self.element(child, level, trailing),
+                SyntaxElement::Token(token) => {
+                    if token.token_type == TokenType::ARROW {
+                        self.line.push(' ');
+                    }
+                    self.token(token);
+                    if matches!(
+                        token.token_type,
+                        TokenType::FUN | TokenType::ARROW | TokenType::COMMA
+                    ) {
+                        self.line.push(' ');
+                    }
+                }
+            }
+        }
+    }
+
+    fn element(&mut self, element: &SyntaxElement, level: usize, trailing: usize) {
+        match element {
+            SyntaxElement::Node(node) => self.node(node, level, trailing),
+            SyntaxElement::Token(token) => self.token(token),
+        }
+    }
+
+    /// 不换行输出整个节点
+    fn flat(&mut self, node: &SyntaxNode) {
+        if node.kind == SyntaxKind::Lambda {
+            self.lambda(node, 0, 0);
+            return;
+        }
+        for (i, child) in node.children.iter().enumerate() {
+            if i > 0 && (is_spaced(node.kind) || is_double_negation(node)) {
+                self.line.push(' ');
+            }
+            match child {
+                SyntaxElement::Node(node) => self.flat(node),
+                SyntaxElement::Token(token) => {
+                    self.token(token);
+                    if is_separator(node, child) {
+                        self.line.push(' ');
+                    }
+                }
+            }
+        }
+    }
+
+    /// 节点能否放在当前行：宽度不超限，且中间没有单行注释
+    fn fits(&self, node: &SyntaxNode, trailing: usize) -> bool {
+        let tokens = node.tokens();
+        let mut width = self.line.chars().count() + flat_width(node) + trailing;
+        for (i, token) in tokens.iter().enumerate() {
+            let inner = token.leading.iter().filter(|_| i > 0);
+            let inner = inner.chain(token.trailing.iter().filter(|_| i + 1 < tokens.len()));
+            for trivia in inner {
+                match trivia.kind {
+                    TriviaKind::LineComment => return false,
+                    TriviaKind::BlockComment => width += trivia.text.chars().count() + 1,
+                    _ => {}
+                }
+            }
+        }
+        width <= self.config.max_width
+    }
+
+    /// 输出 token 和它前后的注释
+    fn token(&mut self, token: &Token) {
+        self.comments(&token.leading);
+        self.keep_blank = false;
+        self.token_body(token);
+    }
+
+    /// 输出 token 和尾随注释，前导注释由调用方处理
+    fn token_body(&mut self, token: &Token) {
+        if self.break_pending {
+            self.break_after_comment();
+        }
+        if std::mem::take(&mut self.space_pending)
+            && !self.line.ends_with(' ')
+            && !matches!(
+                token.token_type,
+                TokenType::RIGHT_PAREN
+                    | TokenType::RIGHT_BRACKET
+                    | TokenType::SEMICOLON
+                    | TokenType::COMMA
+            )
+        {
+            self.line.push(' ');
+        }
+        self.line.push_str(&token.lexeme);
+        self.comments(&token.trailing);
+    }
+
+    fn comments(&mut self, trivia: &[Trivia]) {
+        // 上一行已经结束，再遇到换行就是空行
+        let mut line_ended = true;
+        for trivia in trivia {
+            let is_line_comment = match trivia.kind {
+                TriviaKind::LineComment => true,
+                TriviaKind::BlockComment => false,
+                TriviaKind::Newline => {
+                    // 语句前面的空行，连续多个只留一个
+                    if self.keep_blank
+                        && line_ended
+                        && self.line.trim().is_empty()
+                        && self.lines.last().is_some_and(|line| !line.is_empty())
+                    {
+                        self.lines.push(String::new());
+                    }
+                    line_ended = true;
+                    continue;
+                }
+                _ => continue,
+            };
+            line_ended = false;
+            if self.break_pending {
+                self.break_after_comment();
+            }
+            if self.line.trim().is_empty() {
+                // 行首的注释单独占一行
+                let indent = self.line.clone();
+                self.line.push_str(&trivia.text);
+                self.lines.push(std::mem::replace(&mut self.line, indent));
+                continue;
+            }
+            // 左括号后面紧跟注释，不加空格
+            if !self.line.ends_with([' ', '(', '[']) {
+                self.line.push(' ');
+            }
+            self.line.push_str(&trivia.text);
+            if is_line_comment {
+                self.break_pending = true;
+            } else {
+                self.space_pending = true;
+            }
+        }
+    }
+
+    /// 单行注释之后换行，续行多缩进一级
+    fn break_after_comment(&mut self) {
+        self.break_pending = false;
+        let indent = self.line.len() - self.line.trim_start().len();
+        self.newline(indent / self.config.indent_width.max(1) + 1);
+    }
+
+    fn newline(&mut self, level: usize) {
+        // 换行之后单行注释就不再影响后面的 token
+        self.break_pending = false;
+        self.space_pending = false;
+        let line = std::mem::take(&mut self.line);
+        if !line.trim().is_empty() {
+            self.lines.push(line.trim_end().to_string());
+        }
+        self.line = " ".repeat(level * self.config.indent_width);
+    }
+
+    fn finish(mut self) -> String {
+        if !self.line.trim().is_empty() {
+            self.lines.push(self.line.trim_end().to_string());
+        }
+        let mut out = self.lines.join("\n");
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// 子元素之间用空格隔开的节点
+fn is_spaced(kind: SyntaxKind) -> bool {
+    matches!(
+        kind,
+        SyntaxKind::Binary
+            | SyntaxKind::Logical
+            | SyntaxKind::Conditional
+            | SyntaxKind::Assignment
+            | SyntaxKind::Compound
+            | SyntaxKind::Set
+            | SyntaxKind::IndexSet
+            | SyntaxKind::Parameter
+    )
+}
+
+/// `- -a` 和 `- --a` 的负号后面要留空格，连在一起就成了自减
+fn is_double_negation(node: &SyntaxNode) -> bool {
+    node.kind == SyntaxKind::Unary
+        && node.children.len() == 2
+        && is_token(&node.children[0], TokenType::MINUS)
+        && matches!(
+            &node.children[1],
+            SyntaxElement::Node(operand) if operand.tokens().first().is_some_and(|token| {
+                matches!(token.token_type, TokenType::MINUS | TokenType::MINUS_MINUS)
+            })
+        )
+}
+
+fn is_block(element: &SyntaxElement) -> bool {
+    matches!(element, SyntaxElement::Node(node) if node.kind == SyntaxKind::Block)
+}
+
+fn is_comma(element: &SyntaxElement) -> bool {
+    is_token(element, TokenType::COMMA)
+}
+
+/// 后面跟一个空格的 token：逗号、映射和命名实参里的冒号
+fn is_separator(node: &SyntaxNode, element: &SyntaxElement) -> bool {
+    is_comma(element)
+        || (matches!(node.kind, SyntaxKind::Map | SyntaxKind::NamedArgument)
+            && is_token(element, TokenType::COLON))
+}
+
+fn is_token(element: &SyntaxElement, token_type: TokenType) -> bool {
+    matches!(element, SyntaxElement::Token(token) if token.token_type == token_type)
+}
+
+fn has_comments(trivia: &[Trivia]) -> bool {
+    trivia.iter().any(|trivia| {
+        matches!(
+            trivia.kind,
+            TriviaKind::LineComment | TriviaKind::BlockComment
+        )
+    })
+}
+
+/// 节点不换行时的宽度，不含注释
+fn flat_width(node: &SyntaxNode) -> usize {
+    let mut width = 0;
+    for (i, child) in node.children.iter().enumerate() {
+        if i > 0 && (is_spaced(node.kind) || is_double_negation(node)) {
+            width += 1;
+        }
+        width += match child {
+            SyntaxElement::Node(node) => flat_width(node),
+            // 逗号后面跟一个空格
+            SyntaxElement::Token(_) if is_separator(node, child) => 2,
+            // `fun ` 和 ` => `
+            SyntaxElement::Token(token) if token.token_type == TokenType::FUN => 4,
+            SyntaxElement::Token(token) if token.token_type == TokenType::ARROW => 4,
+            SyntaxElement::Token(token) => token.lexeme.chars().count(),
+        };
+    }
+    width
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fmt(source: &str) -> String {
+        format_source(source, &FormatConfig::default()).unwrap()
+    }
+
+    #[test]
+    fn test_spacing() {
+        assert_eq!(fmt("1+2*  3;"), "1 + 2 * 3;\n");
+        assert_eq!(fmt("( -1 )==!true ;"), "(-1) == !true;\n");
+        assert_eq!(fmt("print\"a\"  >=\n\n\"b\";"), "print \"a\" >= \"b\";\n");
+        assert_eq!(
+            fmt("a?b:c?d : e;f(a,(b ,c));"),
+            "a ? b : c ? d : e;\nf(a, (b, c));\n"
+        );
+        assert_eq!(fmt("~a<<1|b%2**-c~/3;"), "~a << 1 | b % 2 ** -c ~/ 3;\n");
+        assert_eq!(fmt("a ?. b?.c()??d;"), "a?.b?.c() ?? d;\n");
+        assert_eq!(
+            fmt("a+=1;xs[i ++]-=- -b;-- c;"),
+            "a += 1;\nxs[i++] -= - -b;\n--c;\n"
+        );
+    }
+
+    #[test]
+    fn test_comments() {
+        let source = "// leading\n1 +\n  // inside\n 2; // trailing\n/* end */";
+        assert_eq!(
+            fmt(source),
+            "// leading\n1\n    + // inside\n        2; // trailing\n/* end */\n"
+        );
+        assert_eq!(fmt("// only\n\n// comments"), "// only\n// comments\n");
+        assert_eq!(
+            fmt("if (a) { print 1; } // c\nelse { print 2; }"),
+            "if (a) {\n    print 1;\n} // c\nelse {\n    print 2;\n}\n"
+        );
+        assert_eq!(fmt("class A { // c\n}"), "class A { // c\n}\n");
+        assert_eq!(fmt("print 1 + /* mid */ 2;"), "print 1 + /* mid */ 2;\n");
+        assert_eq!(fmt("print a /* x */ ;"), "print a /* x */;\n");
+        assert_eq!(fmt("f(/* none */);"), "f(/* none */);\n");
+    }
+
+    #[test]
+    fn test_line_length() {
+        let config = FormatConfig {
+            indent_width: 2,
+            max_width: 14,
+        };
+        assert_eq!(
+            format_source("1 + 2 + 3 * (4 - 5);", &config).unwrap(),
+            "1 + 2\n  + 3\n    * (4 - 5);\n"
+        );
+        assert_eq!(
+            format_source(
+                "1 + 2 + 3 * (4 - 5);",
+                &FormatConfig {
+                    max_width: 13,
+                    ..config
+                }
+            )
+            .unwrap(),
+            "1 + 2\n  + 3\n    * (4\n        - 5);\n"
+        );
+        assert_eq!(
+            format_source("x = a ? bbbb : cccc;", &config).unwrap(),
+            "x = a\n  ? bbbb\n  : cccc;\n"
+        );
+        assert_eq!(
+            format_source("f(aaaa, bb + c);", &config).unwrap(),
+            "f(\n  aaaa,\n  bb + c\n);\n"
+        );
+        assert_eq!(
+            format_source("var xs = [1, 22, 333];", &config).unwrap(),
+            "var xs = [\n  1,\n  22,\n  333\n];\n"
+        );
+        assert_eq!(
+            format_source("var m = {\"a\": 1, b: 2};", &config).unwrap(),
+            "var m = {\n  \"a\": 1,\n  b: 2\n};\n"
+        );
+        assert_eq!(
+            format_source("fun f(aaa, b = 1, ...c) {}", &config).unwrap(),
+            "fun f(\n  aaa,\n  b = 1,\n  ...c\n) {}\n"
+        );
+        assert_eq!(
+            format_source("var g = (aaa, bbb) => aaa;", &config).unwrap(),
+            "var g = (\n  aaa,\n  bbb\n) => aaa;\n"
+        );
+    }
+
+    #[test]
+    fn test_statements() {
+        assert_eq!(
+            fmt("var a=1;{var b;print a+b;}"),
+            "var a = 1;\n{\n    var b;\n    print a + b;\n}\n"
+        );
+        assert_eq!(
+            fmt("if(a)print 1;else if(b){print 2;}else print 3;"),
+            "if (a)\n    print 1;\nelse if (b) {\n    print 2;\n} else\n    print 3;\n"
+        );
+        assert_eq!(
+            fmt("while(a)a=a-1;for(;;){}for(var i=0;i<1;)print i;"),
+            "while (a)\n    a = a - 1;\nfor (;;) {}\nfor (var i = 0; i < 1;)\n    print i;\n"
+        );
+        assert_eq!(
+            fmt("fun add(a,b){return a+b;}fun f(){return;}print add(1,f());"),
+            "fun add(a, b) {\n    return a + b;\n}\nfun f() {\n    return;\n}\nprint add(1, f());\n"
+        );
+        assert_eq!(
+            fmt("class A<B{init(x){this.x=x;}m(){return super.m() is B;}}class C{}"),
+            "class A < B {\n    init(x) {\n        this.x = x;\n    }\n    m() {\n        return super.m() is B;\n    }\n}\nclass C {}\n"
+        );
+        assert_eq!(
+            fmt("var xs=[1,[ ],3];xs [-1]=xs[0]+1;"),
+            "var xs = [1, [], 3];\nxs[-1] = xs[0] + 1;\n"
+        );
+        assert_eq!(
+            fmt("var m={\"a\" :1,2:{}};m[\"a\"]=m . keys();"),
+            "var m = {\"a\": 1, 2: {}};\nm[\"a\"] = m.keys();\n"
+        );
+        assert_eq!(
+            fmt("for(x  in xs)print x;for(k in m){}"),
+            "for (x in xs)\n    print x;\nfor (k in m) {}\n"
+        );
+        assert_eq!(
+            fmt("import\"m\"as m;import{a ,b}from\"n\";export  fun f(){}export var x=1;"),
+            "import \"m\" as m;\nimport { a, b } from \"n\";\nexport fun f() {}\nexport var x = 1;\n"
+        );
+        assert_eq!(
+            fmt("const N=1;while(a){if(b)break ;continue;}"),
+            "const N = 1;\nwhile (a) {\n    if (b)\n        break;\n    continue;\n}\n"
+        );
+        assert_eq!(
+            fmt("var f=fun(a,b){return a;};g((x)=>x*2,()=>{},fun(){print 1;});"),
+            "var f = fun (a, b) {\n    return a;\n};\ng((x) => x * 2, () => {}, fun () {\n    print 1;\n});\n"
+        );
+        assert_eq!(
+            fmt("fun f(a,b=1+2,... rest){}f(1,b :2);var g=(x=[])=>x;"),
+            "fun f(a, b = 1 + 2, ...rest) {}\nf(1, b: 2);\nvar g = (x = []) => x;\n"
+        );
+    }
+
+    #[test]
+    fn test_blank_lines() {
+        assert_eq!(
+            fmt("\n\nvar a;\n\n\n\nvar b;\n// c\n\nvar c;\n"),
+            "var a;\n\nvar b;\n// c\n\nvar c;\n"
+        );
+        assert_eq!(
+            fmt("{\n\n  print 1; // one\n  // last\n}"),
+            "{\n    print 1; // one\n    // last\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_syntax_error() {
+        assert!(format_source("(1 + 2;", &FormatConfig::default()).is_err());
+        assert!(format_source("1 2;", &FormatConfig::default()).is_err());
+        let errors = format_source("print 1", &FormatConfig::default()).unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 1] Error at end: Expect ';' after value."
+        );
+    }
+
+    #[test]
+    fn test_idempotent() {
+        let config = FormatConfig {
+            indent_width: 2,
+            max_width: 16,
+        };
+        let sources = [
+            "1+2*  3;",
+            "// a\n( 1 +/* b */2 ) ; // c\n// d",
+            "print 1 + 2 + 3 * (4 - 5) + \"long string literal\" == -(6 / 7);",
+            "!(true == \n// why\nfalse);",
+            "var a=1;\n\n\n// x\n{a=a+1; // y\n\n\nprint a;}",
+            "if (a) b = c or d and e; else { // e\n}",
+            "for(var i=0;i<3;i=i+1)if(i>1)print i;else print \"long string\" + i;",
+            "fun f(a,b){return g(a,b,\"long string\");}",
+            "class A{m(){this.field.x=this.other+\"long string\";}}",
+            "{var f=(x)=>x+\"long string\";h(fun(a){return a;});}",
+            "print call(first, second + 1, [3], name: \"long string\");",
+            "var xs = [first, [second, third], \"long string\"];",
+            "var m = {\"key\": value, \"other\": {\"nested\": 1}};",
+            "fun f(first, second = 1, ...rest) { return g(first, second); }",
+            "var h = (first, second) => first(second, [1, 2, 3]);",
+            "if (a) { print 1; } // c\nelse { print 2; }",
+            "class A { // c\n}\nprint f(/* none */) /* x */ ;",
+        ];
+        for source in sources {
+            let once = format_source(source, &config).unwrap();
+            let twice = format_source(&once, &config).unwrap();
+            assert_eq!(once, twice, "not idempotent for {:?}", source);
+        }
+    }
+}
@@ -1,3 +1,5 @@
+// 生成 AST 结构体的小工具，目前只在测试里调用
+#![allow(dead_code)]
 use std::{fs::File, io::Write};
 
 pub fn define_ast(output_dir: &str, base_name: &str, types: &[&str]) {
@@ -40,8 +42,9 @@ mod test {
     use super::*;
     #[test]
     fn test_define_ast() {
+        // 写到临时目录，避免测试在 src 下生成文件
         define_ast(
-            "./src",
+            std::env::temp_dir().to_str().unwrap(),
             "Expr",
             &[
         "Binary|left_expr:Box<Expr>,token:Token,right_expr:Box<Expr>",
@@ -0,0 +1,1977 @@
+// 树遍历解释器：执行语句、对表达式求值
+//
+// `Interpreter` 也是给 Rust 宿主程序用的嵌入接口：
+//
+//     let mut lox = Interpreter::new();
+//     lox.eval("fun add(a, b) { return a + b; }")?;
+//     let sum = lox.call_function("add", &[1.0.into(), 2.0.into()])?;
+//
+// 出错时返回 `LoxError`，不会输出到 stderr 也不会退出进程。
+// 文件、环境变量这类标准库函数默认关闭，用 `set_capabilities` 打开。
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use crate::diagnostic::Diagnostic;
+use crate::environment::Environment;
+use crate::list;
+use crate::map;
+use crate::module::{self, LoxModule, Modules};
+use crate::expr::{
+    Binary, Call, Comma, Compound, Conditional, Expr, Get, Index, IndexSet, Lambda, List, Logical,
+    Map, Optional, Set, Super, Unary, Update, Variable,
+};
+use crate::optimizer::{OptimizerConfig, optimize, optimize_program};
+use crate::parser::Parse;
+use crate::resolver::Resolver;
+use crate::scanner::Scanner;
+use crate::stdlib::{self, Capabilities, Host};
+use crate::stmt::{Class, Const, For, ForIn, Function, Import, Imported, Stmt, Var};
+use crate::token::{Token, TokenType};
+use crate::native::{NativeClass, NativeFunction, NativeResult};
+use crate::value::{LoxClass, LoxFunction, LoxInstance, LoxMap, Value};
+
+/// 调用栈的最大深度，超过时报 Stack overflow
+const MAX_CALL_DEPTH: usize = 256;
+/// 每次调用前宿主栈至少要剩这么多，不够时在堆上另开一段 `STACK_SEGMENT` 大小的栈，
+/// 这样在多小的线程上都能递归到 `MAX_CALL_DEPTH` 而不会让宿主进程崩溃
+const STACK_RED_ZONE: usize = 256 * 1024;
+const STACK_SEGMENT: usize = 2 * 1024 * 1024;
+
+/// 运行时错误，记录出错 token 所在的行
+#[derive(Debug, Clone)]
+pub struct RuntimeError {
+    pub(crate) line: u32,
+    pub(crate) message: String,
+    /// 脚本调用了 `exit(code)`，借用错误的路径一直传到最外层
+    pub(crate) exit: Option<i32>,
+}
+
+impl RuntimeError {
+    fn new(token: &Token, message: &str) -> Self {
+        Self::at(token.line, message.to_string())
+    }
+
+    fn at(line: u32, message: String) -> Self {
+        Self {
+            line,
+            message,
+            exit: None,
+        }
+    }
+
+    fn exit(line: u32, code: i32) -> Self {
+        Self {
+            exit: Some(code),
+            ..Self::at(line, format!("Exited with code {}.", code))
+        }
+    }
+
+    /// 出错的行号，宿主直接调用函数出错时为 0
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+/// 格式和书中 jlox 一致
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}\n[line {}]", self.message, self.line)
+    }
+}
+
+/// 执行一段代码的错误
+#[derive(Debug, Clone)]
+pub enum LoxError {
+    /// 扫描或解析错误，代码没有执行
+    Compile(Vec<Diagnostic>),
+    Runtime(RuntimeError),
+    /// 脚本调用了 `exit(code)`，不算错误，`report` 不输出任何东西
+    Exit(i32),
+}
+
+impl Display for LoxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoxError::Compile(diagnostics) => {
+                for (i, diagnostic) in diagnostics.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{}", diagnostic)?;
+                }
+                Ok(())
+            }
+            LoxError::Runtime(error) => write!(f, "{}", error),
+            LoxError::Exit(code) => write!(f, "Exited with code {}.", code),
+        }
+    }
+}
+
+impl std::error::Error for LoxError {}
+
+impl From<RuntimeError> for LoxError {
+    fn from(error: RuntimeError) -> Self {
+        match error.exit {
+            Some(code) => LoxError::Exit(code),
+            None => LoxError::Runtime(error),
+        }
+    }
+}
+
+/// 扫描、解析、检查常量并优化一段程序，`constants` 是之前声明过的全局常量
+fn compile(
+    source: &str,
+    constants: &HashMap<String, Token>,
+    optimizer: &OptimizerConfig,
+) -> Result<Vec<Stmt>, LoxError> {
+    let mut scanner = Scanner::new(source.to_string());
+    scanner.scan_tokens();
+    let mut diagnostics = std::mem::take(&mut scanner.diagnostics);
+    let mut parser = Parse::new(scanner);
+    let statements = parser.parse();
+    diagnostics.append(&mut parser.diagnostics);
+    if diagnostics.is_empty() {
+        let mut resolver = Resolver::new(constants);
+        resolver.resolve(&statements);
+        diagnostics = resolver.diagnostics;
+    }
+    if !diagnostics.is_empty() {
+        return Err(LoxError::Compile(diagnostics));
+    }
+    Ok(optimize_program(statements, optimizer))
+}
+
+/// 输入正好是一个完整的表达式时返回它，否则应该当作语句解析
+pub(crate) fn parse_bare_expression(source: &str) -> Option<Expr> {
+    let mut scanner = Scanner::new(source.to_string());
+    scanner.scan_tokens();
+    if !scanner.diagnostics.is_empty() {
+        return None;
+    }
+    Parse::new(scanner).parse_expression()
+}
+
+/// 打断正常执行顺序的情况：运行时错误，return 带着返回值跳出函数，
+/// 或者 break、continue 跳到循环的末尾
+enum Interrupt {
+    Error(RuntimeError),
+    Return(Value),
+    Break,
+    Continue,
+}
+
+impl From<RuntimeError> for Interrupt {
+    fn from(error: RuntimeError) -> Self {
+        Interrupt::Error(error)
+    }
+}
+
+/// 解释器保存着全局变量，REPL 里多次输入共用同一个解释器
+pub struct Interpreter {
+    globals: Rc<RefCell<Environment>>,
+    /// 标准库所在的作用域，每个模块的全局作用域都套在它外面
+    builtins: Rc<RefCell<Environment>>,
+    /// 当前作用域
+    environment: Rc<RefCell<Environment>>,
+    /// print 语句的输出
+    stdout: Box<dyn Write>,
+    /// 错误报告的输出，见 `report`
+    stderr: Box<dyn Write>,
+    /// 标准库里和宿主进程打交道的函数共享的状态
+    pub(crate) host: Rc<Host>,
+    /// 已经执行过的全局常量声明，之后输入的代码也不能给它们赋值
+    constants: HashMap<String, Token>,
+    pub(crate) modules: Modules,
+    /// 编译时用的优化开关
+    pub(crate) optimizer: OptimizerConfig,
+    call_depth: usize,
+}
+
+/// 新的解释器自带标准库，见 `stdlib`
+impl Default for Interpreter {
+    fn default() -> Self {
+        let mut interpreter = Self::bare();
+        stdlib::install(&mut interpreter);
+        // 标准库放在全局作用域外面一层，`:env` 只列出脚本自己的全局变量，
+        // 脚本也可以用同名变量覆盖内置函数
+        let globals = Rc::new(RefCell::new(Environment::new(interpreter.globals.clone())));
+        interpreter.environment = globals.clone();
+        interpreter.builtins = std::mem::replace(&mut interpreter.globals, globals);
+        interpreter
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 没有标准库的解释器，优化器折叠常量时用
+    pub(crate) fn bare() -> Self {
+        let globals = Rc::new(RefCell::new(Environment::default()));
+        Self {
+            environment: globals.clone(),
+            builtins: globals.clone(),
+            globals,
+            stdout: Box::new(std::io::stdout()),
+            stderr: Box::new(std::io::stderr()),
+            host: Rc::new(Host::default()),
+            constants: HashMap::new(),
+            modules: Modules::default(),
+            optimizer: OptimizerConfig::default(),
+            call_depth: 0,
+        }
+    }
+
+    /// 设置 print 语句的输出，默认是进程的 stdout
+    pub fn set_stdout(&mut self, writer: impl Write + 'static) {
+        self.stdout = Box::new(writer);
+    }
+
+    /// 设置 `report` 的输出，默认是进程的 stderr
+    pub fn set_stderr(&mut self, writer: impl Write + 'static) {
+        self.stderr = Box::new(writer);
+    }
+
+    /// 设置 `readLine` 的输入，默认是进程的 stdin
+    pub fn set_stdin(&mut self, reader: impl BufRead + 'static) {
+        *self.host.stdin.borrow_mut() = Some(Box::new(reader));
+    }
+
+    /// 允许脚本使用的受限函数，默认全部禁止
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.host.capabilities.set(capabilities);
+    }
+
+    /// `args()` 返回给脚本的参数
+    pub fn set_args(&mut self, args: Vec<String>) {
+        *self.host.args.borrow_mut() = args;
+    }
+
+    /// 编译时打开哪些优化，默认全部打开
+    pub fn set_optimizer(&mut self, config: OptimizerConfig) {
+        self.optimizer = config;
+    }
+
+    /// import 在导入文件所在的目录里找不到模块时，依次查找的目录
+    pub fn set_module_path(&mut self, directories: Vec<PathBuf>) {
+        self.modules.search_path = directories;
+    }
+
+    /// 执行一段代码，全局变量保留到下一次调用
+    ///
+    /// 代码是单个表达式（可以不带分号）时返回它的值；
+    /// 否则返回最后一条表达式语句的值，最后一条不是表达式语句时返回 nil。
+    pub fn eval(&mut self, source: &str) -> Result<Value, LoxError> {
+        if let Some(expr) = parse_bare_expression(source) {
+            let mut resolver = Resolver::new(&self.constants);
+            resolver.resolve_expr(&expr);
+            if !resolver.diagnostics.is_empty() {
+                return Err(LoxError::Compile(resolver.diagnostics));
+            }
+            return Ok(self.evaluate(&optimize(expr, &self.optimizer))?);
+        }
+        let mut statements = compile(source, &self.constants, &self.optimizer)?;
+        let last = match statements.last() {
+            Some(Stmt::Expression(_)) => statements.pop(),
+            _ => None,
+        };
+        self.interpret(&statements)?;
+        match last {
+            Some(Stmt::Expression(expression)) => Ok(self.evaluate(&expression.expr)?),
+            _ => Ok(Value::Nil),
+        }
+    }
+
+    /// 按脚本执行一段代码，不接受裸表达式
+    pub(crate) fn run(&mut self, source: &str) -> Result<(), LoxError> {
+        let statements = compile(source, &self.constants, &self.optimizer)?;
+        self.interpret(&statements)?;
+        Ok(())
+    }
+
+    /// 调用全局函数，脚本里定义的和原生函数都可以
+    pub fn call_function(&mut self, name: &str, args: &[Value]) -> Result<Value, LoxError> {
+        let Some(callee) = self.get_global(name) else {
+            return Err(LoxError::Runtime(RuntimeError::at(
+                0,
+                format!("Undefined variable '{}'.", name),
+            )));
+        };
+        // 没有调用处，错误的行号是 0
+        Ok(self.call_value(callee, args.to_vec(), Vec::new(), 0)?)
+    }
+
+    /// 把 Rust 闭包注册成全局函数
+    ///
+    /// 调用时先检查参数个数；闭包返回的 `Err` 变成调用处的运行时错误。
+    /// 参数可以用 `arg` 按类型取出。
+    pub fn register_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&[Value]) -> NativeResult + 'static,
+    ) {
+        let native = NativeFunction {
+            name: name.to_string(),
+            arity,
+            function: Box::new(function),
+        };
+        self.set_global(name, Value::Native(Rc::new(native)));
+    }
+
+    /// 把原生类注册成同名的全局变量，返回的类用来包装宿主的值
+    pub fn register_class(&mut self, class: NativeClass) -> Rc<NativeClass> {
+        let class = Rc::new(class);
+        self.set_global(class.name(), Value::NativeClass(class.clone()));
+        class
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.globals.borrow().get(name)
+    }
+
+    /// 定义或覆盖一个全局变量
+    pub fn set_global(&mut self, name: &str, value: impl Into<Value>) {
+        self.globals.borrow_mut().define(name, value.into());
+    }
+
+    /// 把错误按书中 jlox 的格式写到 stderr，`exit` 不输出
+    pub fn report(&mut self, error: &LoxError) {
+        if !matches!(error, LoxError::Exit(_)) {
+            let _ = writeln!(self.stderr, "{}", error);
+        }
+    }
+
+    /// 所有全局变量，按名字排序
+    pub(crate) fn globals(&self) -> Vec<(String, Value)> {
+        self.globals.borrow().bindings()
+    }
+
+    pub(crate) fn interpret(&mut self, statements: &[Stmt]) -> Result<(), RuntimeError> {
+        for stmt in statements {
+            match self.execute(stmt) {
+                Ok(()) => {}
+                Err(Interrupt::Error(error)) => return Err(error),
+                // 解析器不允许顶层 return，也不允许循环外的 break 和 continue
+                Err(Interrupt::Return(_) | Interrupt::Break | Interrupt::Continue) => break,
+            }
+        }
+        Ok(())
+    }
+
+    fn execute_all(&mut self, statements: &[Stmt]) -> Result<(), Interrupt> {
+        for stmt in statements {
+            self.execute(stmt)?;
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, stmt: &Stmt) -> Result<(), Interrupt> {
+        match stmt {
+            Stmt::Block(block) => {
+                let environment = Environment::new(self.environment.clone());
+                self.in_scope(environment, |interpreter| {
+                    interpreter.execute_all(&block.statements)
+                })?;
+            }
+            Stmt::Break(_) => return Err(Interrupt::Break),
+            Stmt::Continue(_) => return Err(Interrupt::Continue),
+            Stmt::Class(class) => self.class(class)?,
+            Stmt::Const(constant) => self.constant(constant)?,
+            Stmt::Export(export) => self.execute(&export.declaration)?,
+            Stmt::Import(import) => self.import(import)?,
+            Stmt::Expression(expression) => {
+                self.evaluate(&expression.expr)?;
+            }
+            Stmt::Function(declaration) => self.function(declaration),
+            Stmt::If(stmt) => {
+                if self.evaluate(&stmt.condition)?.is_truthy() {
+                    self.execute(&stmt.then_branch)?;
+                } else if let Some(else_branch) = &stmt.else_branch {
+                    self.execute(else_branch)?;
+                }
+            }
+            Stmt::Print(print) => {
+                let value = self.evaluate(&print.expr)?;
+                let _ = writeln!(self.stdout, "{}", value);
+            }
+            Stmt::Return(stmt) => {
+                let value = match &stmt.value {
+                    Some(value) => self.evaluate(value)?,
+                    None => Value::Nil,
+                };
+                return Err(Interrupt::Return(value));
+            }
+            Stmt::Var(var) => self.var(var)?,
+            Stmt::While(stmt) => {
+                while self.evaluate(&stmt.condition)?.is_truthy() {
+                    if broke(self.execute(&stmt.body))? {
+                        break;
+                    }
+                }
+            }
+            Stmt::For(stmt) => {
+                // 初始化部分声明的变量只在循环里可见
+                let environment = Environment::new(self.environment.clone());
+                self.in_scope(environment, |interpreter| interpreter.for_loop(stmt))?;
+            }
+            Stmt::ForIn(stmt) => self.for_in(stmt)?,
+        }
+        Ok(())
+    }
+
+    fn class(&mut self, class: &Class) -> Result<(), RuntimeError> {
+        let superclass = match &class.superclass {
+            Some(superclass) => match self.lookup(&superclass.name, superclass.distance.get())? {
+                Value::Class(superclass) => Some(superclass),
+                _ => {
+                    return Err(RuntimeError::new(
+                        &superclass.name,
+                        "Superclass must be a class.",
+                    ));
+                }
+            },
+            None => None,
+        };
+        // 方法的闭包在类所在的作用域之上再套一层 super
+        let closure = match &superclass {
+            Some(superclass) => {
+                let mut environment = Environment::new(self.environment.clone());
+                environment.define("super", Value::Class(superclass.clone()));
+                Rc::new(RefCell::new(environment))
+            }
+            None => self.environment.clone(),
+        };
+        let methods = class
+            .methods
+            .iter()
+            .map(|method| {
+                let function = LoxFunction {
+                    declaration: method.clone(),
+                    closure: closure.clone(),
+                    is_initializer: method.name.lexeme == "init",
+                };
+                (method.name.lexeme.clone(), Rc::new(function))
+            })
+            .collect();
+        let class_value = LoxClass {
+            name: class.name.lexeme.clone(),
+            superclass,
+            methods,
+        };
+        self.environment
+            .borrow_mut()
+            .define(&class.name.lexeme, Value::Class(Rc::new(class_value)));
+        Ok(())
+    }
+
+    fn function(&mut self, declaration: &Rc<Function>) {
+        let function = LoxFunction {
+            declaration: declaration.clone(),
+            closure: self.environment.clone(),
+            is_initializer: false,
+        };
+        self.environment
+            .borrow_mut()
+            .define(&declaration.name.lexeme, Value::Function(Rc::new(function)));
+    }
+
+    fn var(&mut self, var: &Var) -> Result<(), RuntimeError> {
+        let value = match &var.initializer {
+            Some(initializer) => self.evaluate(initializer)?,
+            None => Value::Nil,
+        };
+        self.environment
+            .borrow_mut()
+            .define(&var.name.lexeme, value);
+        Ok(())
+    }
+
+    fn constant(&mut self, constant: &Const) -> Result<(), Interrupt> {
+        let value = self.evaluate(&constant.initializer)?;
+        self.environment
+            .borrow_mut()
+            .define(&constant.name.lexeme, value);
+        if Rc::ptr_eq(&self.environment, &self.globals) {
+            self.constants
+                .insert(constant.name.lexeme.clone(), constant.name.clone());
+        }
+        Ok(())
+    }
+
+    fn import(&mut self, import: &Import) -> Result<(), RuntimeError> {
+        let module = self.load_module(import)?;
+        let mut environment = self.environment.borrow_mut();
+        match &import.imported {
+            Imported::Module(name) => environment.define(&name.lexeme, Value::Module(module)),
+            Imported::Names(names) => {
+                for name in names {
+                    let value = module.get(&name.lexeme).ok_or_else(|| no_export(&module, name))?;
+                    environment.define(&name.lexeme, value);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 第一次导入时在模块自己的全局作用域里执行它，之后直接用缓存
+    fn load_module(&mut self, import: &Import) -> Result<Rc<LoxModule>, RuntimeError> {
+        let name = import.module();
+        let line = import.keyword.line;
+        let Some(path) = self.modules.find(name) else {
+            return Err(RuntimeError::at(line, format!("Could not find module '{}'.", name)));
+        };
+        if let Some(cycle) = self.modules.cycle(&path) {
+            return Err(RuntimeError::at(line, format!("Import cycle: {}.", cycle)));
+        }
+        if let Some(module) = self.modules.cached(&path) {
+            return Ok(module);
+        }
+        let source = std::fs::read_to_string(&path).map_err(|e| {
+            RuntimeError::at(line, format!("Could not read module '{}': {}.", name, e))
+        })?;
+        let statements = compile(&source, &HashMap::new(), &self.optimizer).map_err(|error| {
+            RuntimeError::at(
+                line,
+                format!("Could not compile module '{}':\n{}", name, error),
+            )
+        })?;
+
+        let globals = Rc::new(RefCell::new(Environment::new(self.builtins.clone())));
+        let saved = (
+            std::mem::replace(&mut self.globals, globals.clone()),
+            std::mem::replace(&mut self.environment, globals.clone()),
+            std::mem::take(&mut self.constants),
+        );
+        self.modules.enter(path.clone(), name.to_string());
+        let result = self.interpret(&statements);
+        self.modules.leave();
+        (self.globals, self.environment, self.constants) = saved;
+        result?;
+
+        let module = Rc::new(LoxModule {
+            name: name.to_string(),
+            globals,
+            exports: module::exports(&statements),
+        });
+        self.modules.insert(path, module.clone());
+        Ok(module)
+    }
+
+    fn for_loop(&mut self, stmt: &For) -> Result<(), Interrupt> {
+        if let Some(initializer) = &stmt.initializer {
+            self.execute(initializer)?;
+        }
+        loop {
+            if let Some(condition) = &stmt.condition
+                && !self.evaluate(condition)?.is_truthy()
+            {
+                break;
+            }
+            // continue 之后照常执行递增部分
+            if broke(self.execute(&stmt.body))? {
+                break;
+            }
+            if let Some(increment) = &stmt.increment {
+                self.evaluate(increment)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 列表、映射（遍历键）和字符串（遍历字符）直接遍历，
+    /// 其余对象走迭代协议：`iter()` 返回迭代器，`done` 为假时调用 `next()` 取下一个值
+    fn for_in(&mut self, stmt: &ForIn) -> Result<(), Interrupt> {
+        let keyword = &stmt.keyword;
+        match self.evaluate(&stmt.iterable)? {
+            Value::List(values) => {
+                // 每轮重新取元素，循环体里修改列表也能看到
+                for i in 0.. {
+                    let Some(value) = values.borrow().get(i).cloned() else {
+                        break;
+                    };
+                    if self.for_in_body(stmt, value)? {
+                        break;
+                    }
+                }
+            }
+            Value::Map(map) => {
+                let keys: Vec<_> = map.borrow().iter().map(|(key, _)| key).collect();
+                for key in keys {
+                    if self.for_in_body(stmt, key)? {
+                        break;
+                    }
+                }
+            }
+            Value::String(s) => {
+                for c in s.chars() {
+                    if self.for_in_body(stmt, c.to_string().into())? {
+                        break;
+                    }
+                }
+            }
+            object @ (Value::Instance(_) | Value::Userdata(_)) => {
+                let iter = self.property(object, &identifier(keyword, "iter"))?;
+                let iterator = self.call_value(iter, Vec::new(), Vec::new(), keyword.line)?;
+                let done = identifier(keyword, "done");
+                let next = identifier(keyword, "next");
+                while !self.property(iterator.clone(), &done)?.is_truthy() {
+                    let method = self.property(iterator.clone(), &next)?;
+                    let value = self.call_value(method, Vec::new(), Vec::new(), keyword.line)?;
+                    if self.for_in_body(stmt, value)? {
+                        break;
+                    }
+                }
+            }
+            value => {
+                let message = format!("Can't iterate over {}.", value.type_name());
+                return Err(RuntimeError::new(keyword, &message).into());
+            }
+        }
+        Ok(())
+    }
+
+    /// 每轮在新的作用域里定义循环变量，闭包捕获的是这一轮的值；返回是否 break
+    fn for_in_body(&mut self, stmt: &ForIn, value: Value) -> Result<bool, Interrupt> {
+        let mut environment = Environment::new(self.environment.clone());
+        environment.define(&stmt.name.lexeme, value);
+        broke(self.in_scope(environment, |interpreter| interpreter.execute(&stmt.body)))
+    }
+
+    /// 在新的作用域里执行，结束后（包括出错时）恢复原来的作用域
+    fn in_scope<T, E>(
+        &mut self,
+        environment: Environment,
+        f: impl FnOnce(&mut Self) -> Result<T, E>,
+    ) -> Result<T, E> {
+        let previous = std::mem::replace(
+            &mut self.environment,
+            Rc::new(RefCell::new(environment)),
+        );
+        let result = f(self);
+        self.environment = previous;
+        result
+    }
+
+    /// 调用函数：参数绑定在闭包作用域之下的新作用域里
+    fn call(&mut self, function: &LoxFunction, arguments: Bound) -> Result<Value, RuntimeError> {
+        let declaration = &function.declaration;
+        let environment = Environment::new(function.closure.clone());
+        self.call_depth += 1;
+        let result = stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT, || {
+            self.in_scope(environment, |interpreter| {
+                interpreter.parameters(declaration, arguments)?;
+                interpreter.execute_all(&declaration.body)
+            })
+        });
+        self.call_depth -= 1;
+        match result {
+            Err(Interrupt::Error(error)) => Err(error),
+            // 解析器不允许 break 和 continue 跨出函数
+            Err(Interrupt::Break | Interrupt::Continue) => unreachable!(),
+            // init 不管怎么返回都得到实例本身
+            _ if function.is_initializer => Ok(function
+                .closure
+                .borrow()
+                .get("this")
+                .unwrap_or(Value::Nil)),
+            Ok(()) => Ok(Value::Nil),
+            Err(Interrupt::Return(value)) => Ok(value),
+        }
+    }
+
+    /// 在函数的作用域里定义参数，没传的参数按顺序求默认值
+    fn parameters(&mut self, declaration: &Function, arguments: Bound) -> Result<(), RuntimeError> {
+        for (param, argument) in declaration.params.iter().zip(arguments.params) {
+            let value = match (argument, &param.default) {
+                (Some(value), _) => value,
+                (None, Some(default)) => self.evaluate(default)?,
+                // 绑定实参时已经检查过必填参数
+                (None, None) => Value::Nil,
+            };
+            self.environment
+                .borrow_mut()
+                .define(&param.name.lexeme, value);
+        }
+        if let Some(rest) = &declaration.rest {
+            self.environment
+                .borrow_mut()
+                .define(&rest.lexeme, Value::list(arguments.rest));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn evaluate(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        match expr {
+            Expr::Assignment(assignment) => {
+                let value = self.evaluate(&assignment.value)?;
+                self.assign(&assignment.name, assignment.distance.get(), value.clone())?;
+                Ok(value)
+            }
+            Expr::Binary(binary) => self.binary(binary),
+            Expr::Call(call) => self.call_expr(call),
+            Expr::Comma(comma) => self.comma(comma),
+            Expr::Compound(compound) => self.compound(compound),
+            Expr::Conditional(conditional) => self.conditional(conditional),
+            Expr::Get(get) => self.get(get),
+            Expr::Grouping(grouping) => self.evaluate(&grouping.expr),
+            Expr::Index(index) => self.index(index),
+            Expr::IndexSet(index_set) => self.index_set(index_set),
+            Expr::Lambda(lambda) => Ok(self.lambda(lambda)),
+            Expr::List(literal) => self.list(literal),
+            Expr::Map(literal) => self.map(literal),
+            Expr::Literal(literal) => Ok(literal.value.clone().into()),
+            Expr::Logical(logical) => self.logical(logical),
+            Expr::Optional(optional) => self.optional(optional),
+            Expr::Set(set) => self.set(set),
+            Expr::Super(sup) => self.super_method(sup),
+            Expr::This(this) => self.lookup(&this.keyword, this.distance.get()),
+            Expr::Unary(unary) => self.unary(unary),
+            Expr::Update(update) => self.update(update),
+            Expr::Variable(variable) => self.lookup(&variable.name, variable.distance.get()),
+        }
+    }
+
+    /// 按 resolver 算好的距离读取变量，没有解析过的按全局变量处理
+    fn lookup(&self, name: &Token, distance: Option<usize>) -> Result<Value, RuntimeError> {
+        let value = match distance {
+            Some(distance) => Environment::get_at(&self.environment, distance, &name.lexeme),
+            None => self.globals.borrow().get(&name.lexeme),
+        };
+        value.ok_or_else(|| undefined_variable(name))
+    }
+
+    fn assign(
+        &self,
+        name: &Token,
+        distance: Option<usize>,
+        value: Value,
+    ) -> Result<(), RuntimeError> {
+        let assigned = match distance {
+            Some(distance) => {
+                Environment::assign_at(&self.environment, distance, &name.lexeme, value)
+            }
+            None => self.globals.borrow_mut().assign(&name.lexeme, value),
+        };
+        if !assigned {
+            return Err(undefined_variable(name));
+        }
+        Ok(())
+    }
+
+    /// 求值赋值目标里的子表达式，得到可以读写的位置
+    fn place<'a>(&mut self, target: &'a Expr) -> Result<Place<'a>, RuntimeError> {
+        match target {
+            Expr::Variable(variable) => Ok(Place::Variable(variable)),
+            Expr::Get(get) => Ok(Place::Property(self.evaluate(&get.object)?, &get.name)),
+            Expr::Index(index) => {
+                let object = self.evaluate(&index.object)?;
+                let key = self.evaluate(&index.index)?;
+                Ok(Place::Element(object, key, &index.bracket))
+            }
+            _ => unreachable!("parser only allows variables, properties and elements"),
+        }
+    }
+
+    fn read(&mut self, place: &Place) -> Result<Value, RuntimeError> {
+        match place {
+            Place::Variable(variable) => self.lookup(&variable.name, variable.distance.get()),
+            Place::Property(object, name) => self.property(object.clone(), name),
+            Place::Element(object, key, bracket) => element(object, key, bracket),
+        }
+    }
+
+    fn write(&mut self, place: &Place, value: Value) -> Result<(), RuntimeError> {
+        match place {
+            Place::Variable(variable) => {
+                self.assign(&variable.name, variable.distance.get(), value)
+            }
+            Place::Property(object, name) => set_property(object, name, value),
+            Place::Element(object, key, bracket) => {
+                set_element(object, key.clone(), value, bracket)
+            }
+        }
+    }
+
+    /// `a += b`：目标只求值一次，运算和对应的二元运算符一样
+    fn compound(&mut self, compound: &Compound) -> Result<Value, RuntimeError> {
+        let place = self.place(&compound.target)?;
+        let current = self.read(&place)?;
+        let value = self.evaluate(&compound.value)?;
+        let operator = match compound.operator.token_type {
+            TokenType::PLUS_EQUAL => TokenType::PLUS,
+            TokenType::MINUS_EQUAL => TokenType::MINUS,
+            TokenType::STAR_EQUAL => TokenType::STAR,
+            TokenType::SLASH_EQUAL => TokenType::SLASH,
+            TokenType::PERCENT_EQUAL => TokenType::PERCENT,
+            token_type => unreachable!("unknown compound operator {:?}", token_type),
+        };
+        let result = operate(&compound.operator, operator, current, value)?;
+        self.write(&place, result.clone())?;
+        Ok(result)
+    }
+
+    fn update(&mut self, update: &Update) -> Result<Value, RuntimeError> {
+        let place = self.place(&update.target)?;
+        let old = number_operand(&update.operator, &self.read(&place)?)?;
+        let new = match update.operator.token_type {
+            TokenType::PLUS_PLUS => old + 1.0,
+            _ => old - 1.0,
+        };
+        self.write(&place, Value::Number(new))?;
+        Ok(Value::Number(if update.prefix { new } else { old }))
+    }
+
+    /// 匿名函数和具名函数一样捕获当前环境
+    fn lambda(&self, lambda: &Lambda) -> Value {
+        Value::Function(Rc::new(LoxFunction {
+            declaration: lambda.function.clone(),
+            closure: self.environment.clone(),
+            is_initializer: false,
+        }))
+    }
+
+    fn optional(&mut self, optional: &Optional) -> Result<Value, RuntimeError> {
+        Ok(self.chain(&optional.expr)?.unwrap_or(Value::Nil))
+    }
+
+    /// 求值 `?.` 所在的访问链，`?.` 左边是 nil 时返回 `None`，链上后面的访问和调用都跳过
+    fn chain(&mut self, expr: &Expr) -> Result<Option<Value>, RuntimeError> {
+        match expr {
+            Expr::Get(get) => {
+                let Some(object) = self.chain(&get.object)? else {
+                    return Ok(None);
+                };
+                if get.optional && object == Value::Nil {
+                    return Ok(None);
+                }
+                self.property(object, &get.name).map(Some)
+            }
+            Expr::Call(call) => match self.chain(&call.callee)? {
+                Some(callee) => {
+                    let arguments = self.arguments(call)?;
+                    let named = self.named(call)?;
+                    self.call_value(callee, arguments, named, call.paren.line)
+                        .map(Some)
+                }
+                None => Ok(None),
+            },
+            Expr::Index(index) => {
+                let Some(object) = self.chain(&index.object)? else {
+                    return Ok(None);
+                };
+                let key = self.evaluate(&index.index)?;
+                element(&object, &key, &index.bracket).map(Some)
+            }
+            expr => self.evaluate(expr).map(Some),
+        }
+    }
+
+    /// 属性访问：实例先找字段再找方法，原生对象交给它的 getter
+    fn get(&mut self, get: &Get) -> Result<Value, RuntimeError> {
+        let object = self.evaluate(&get.object)?;
+        self.property(object, &get.name)
+    }
+
+    /// 读取属性：实例的字段或方法、原生对象的属性、列表和映射的方法
+    fn property(&mut self, object: Value, name: &Token) -> Result<Value, RuntimeError> {
+        match object {
+            Value::Instance(instance) => {
+                if let Some(value) = instance.get_field(&name.lexeme) {
+                    return Ok(value);
+                }
+                match instance.class.find_method(&name.lexeme) {
+                    Some(method) => Ok(Value::Function(Rc::new(method.bind(instance.clone())))),
+                    None => Err(undefined_property(name)),
+                }
+            }
+            Value::Userdata(userdata) => userdata
+                .get(&name.lexeme)
+                .map_err(|message| RuntimeError::new(name, &message)),
+            Value::List(values) => {
+                list::method(&values, &name.lexeme).ok_or_else(|| undefined_property(name))
+            }
+            Value::Map(entries) => {
+                map::method(&entries, &name.lexeme).ok_or_else(|| undefined_property(name))
+            }
+            Value::Module(module) => {
+                module.get(&name.lexeme).ok_or_else(|| no_export(&module, name))
+            }
+            _ => Err(RuntimeError::new(name, "Only instances have properties.")),
+        }
+    }
+
+    fn set(&mut self, set: &Set) -> Result<Value, RuntimeError> {
+        let object = self.evaluate(&set.object)?;
+        // 先检查对象再求值右边
+        if !matches!(object, Value::Instance(_) | Value::Userdata(_)) {
+            return Err(RuntimeError::new(&set.name, "Only instances have fields."));
+        }
+        let value = self.evaluate(&set.value)?;
+        set_property(&object, &set.name, value.clone())?;
+        Ok(value)
+    }
+
+    fn list(&mut self, literal: &List) -> Result<Value, RuntimeError> {
+        let mut values = Vec::with_capacity(literal.elements.len());
+        for element in &literal.elements {
+            values.push(self.evaluate(element)?);
+        }
+        Ok(Value::list(values))
+    }
+
+    fn map(&mut self, literal: &Map) -> Result<Value, RuntimeError> {
+        let mut map = LoxMap::new();
+        for (key, value) in &literal.entries {
+            let key = self.evaluate(key)?;
+            let value = self.evaluate(value)?;
+            map.insert(key, value)
+                .map_err(|message| RuntimeError::new(&literal.brace, &message))?;
+        }
+        Ok(Value::map(map))
+    }
+
+    fn index(&mut self, index: &Index) -> Result<Value, RuntimeError> {
+        let object = self.evaluate(&index.object)?;
+        let key = self.evaluate(&index.index)?;
+        element(&object, &key, &index.bracket)
+    }
+
+    fn index_set(&mut self, index_set: &IndexSet) -> Result<Value, RuntimeError> {
+        let object = self.evaluate(&index_set.object)?;
+        let key = self.evaluate(&index_set.index)?;
+        let value = self.evaluate(&index_set.value)?;
+        set_element(&object, key, value.clone(), &index_set.bracket)?;
+        Ok(value)
+    }
+
+    /// `super.method`：从父类找方法，绑定到当前的 this
+    fn super_method(&mut self, sup: &Super) -> Result<Value, RuntimeError> {
+        // this 在定义 super 的作用域里面一层
+        let distance = sup.distance.get();
+        let this = distance
+            .and_then(|distance| Environment::get_at(&self.environment, distance - 1, "this"));
+        let (Value::Class(superclass), Some(Value::Instance(this))) =
+            (self.lookup(&sup.keyword, distance)?, this)
+        else {
+            unreachable!("parser only allows 'super' inside subclass methods");
+        };
+        match superclass.find_method(&sup.method.lexeme) {
+            Some(method) => Ok(Value::Function(Rc::new(method.bind(this)))),
+            None => Err(undefined_property(&sup.method)),
+        }
+    }
+
+    fn call_expr(&mut self, call: &Call) -> Result<Value, RuntimeError> {
+        let callee = self.evaluate(&call.callee)?;
+        let arguments = self.arguments(call)?;
+        let named = self.named(call)?;
+        self.call_value(callee, arguments, named, call.paren.line)
+    }
+
+    fn arguments(&mut self, call: &Call) -> Result<Vec<Value>, RuntimeError> {
+        let mut arguments = Vec::with_capacity(call.arguments.len());
+        for argument in &call.arguments {
+            arguments.push(self.evaluate(argument)?);
+        }
+        Ok(arguments)
+    }
+
+    /// 命名实参在位置实参之后求值，和源码顺序一致
+    fn named(&mut self, call: &Call) -> Result<Named, RuntimeError> {
+        let mut named = Vec::with_capacity(call.named.len());
+        for (name, argument) in &call.named {
+            named.push((name.lexeme.clone(), self.evaluate(argument)?));
+        }
+        Ok(named)
+    }
+
+    /// 检查参数个数后调用，错误都记在调用处的 `line`
+    ///
+    /// 脚本函数之间的递归都经过这里，类和原生函数放在单独的方法里，让这一帧尽量小。
+    fn call_value(
+        &mut self,
+        callee: Value,
+        arguments: Vec<Value>,
+        named: Named,
+        line: u32,
+    ) -> Result<Value, RuntimeError> {
+        let Value::Function(function) = callee else {
+            return self.call_other(callee, arguments, named, line);
+        };
+        let bound = bind(&function.declaration, function.name(), arguments, named)
+            .map_err(|message| RuntimeError::at(line, message))?;
+        self.check_depth(line)?;
+        self.call(&function, bound)
+    }
+
+    fn check_depth(&self, line: u32) -> Result<(), RuntimeError> {
+        if self.call_depth >= MAX_CALL_DEPTH {
+            return Err(RuntimeError::at(line, "Stack overflow.".to_string()));
+        }
+        Ok(())
+    }
+
+    /// 调用类和原生函数
+    fn call_other(
+        &mut self,
+        callee: Value,
+        arguments: Vec<Value>,
+        named: Named,
+        line: u32,
+    ) -> Result<Value, RuntimeError> {
+        let error = |message: String| RuntimeError::at(line, message);
+        if let Value::Class(class) = callee {
+            // 类的参数由 init 决定
+            let init = match class.find_method("init") {
+                Some(init) => {
+                    let bound = bind(&init.declaration, class.name(), arguments, named);
+                    Some((init, bound.map_err(error)?))
+                }
+                None if arguments.is_empty() && named.is_empty() => None,
+                None => {
+                    let got = arguments.len() + named.len();
+                    return Err(error(format!("Expected 0 arguments but got {}.", got)));
+                }
+            };
+            self.check_depth(line)?;
+            let instance = Rc::new(LoxInstance::new(class.clone()));
+            if let Some((init, bound)) = init {
+                self.call(&init.bind(instance.clone()), bound)?;
+            }
+            return Ok(Value::Instance(instance));
+        }
+        let arity = match &callee {
+            Value::Native(native) => native.arity(),
+            // 没有构造函数时由 construct 报错
+            Value::NativeClass(class) => class.arity().unwrap_or(arguments.len()),
+            _ => return Err(error("Can only call functions and classes.".to_string())),
+        };
+        if !named.is_empty() {
+            return Err(error(
+                "Native functions don't take named arguments.".to_string(),
+            ));
+        }
+        if arguments.len() != arity {
+            return Err(error(format!(
+                "Expected {} arguments but got {}.",
+                arity,
+                arguments.len()
+            )));
+        }
+        self.check_depth(line)?;
+        match callee {
+            Value::Native(native) => {
+                let result = (native.function)(&arguments).map_err(error);
+                match self.host.exit.take() {
+                    Some(code) => Err(RuntimeError::exit(line, code)),
+                    None => result,
+                }
+            }
+            Value::NativeClass(class) => class.construct(&arguments).map_err(error),
+            _ => unreachable!(),
+        }
+    }
+
+    /// and/or/?? 短路求值，结果是决定结果的那个操作数本身
+    fn logical(&mut self, logical: &Logical) -> Result<Value, RuntimeError> {
+        let left = self.evaluate(&logical.left)?;
+        let short_circuit = match logical.operator.token_type {
+            TokenType::OR => left.is_truthy(),
+            // 只有 nil 会换成右边，false 不会
+            TokenType::QUESTION_QUESTION => left != Value::Nil,
+            _ => !left.is_truthy(),
+        };
+        if short_circuit {
+            return Ok(left);
+        }
+        self.evaluate(&logical.right)
+    }
+
+    /// 左边只为了副作用求值
+    fn comma(&mut self, comma: &Comma) -> Result<Value, RuntimeError> {
+        self.evaluate(&comma.left)?;
+        self.evaluate(&comma.right)
+    }
+
+    /// 只求值选中的那个分支
+    fn conditional(&mut self, conditional: &Conditional) -> Result<Value, RuntimeError> {
+        if self.evaluate(&conditional.condition)?.is_truthy() {
+            self.evaluate(&conditional.then_branch)
+        } else {
+            self.evaluate(&conditional.else_branch)
+        }
+    }
+
+    fn unary(&mut self, unary: &Unary) -> Result<Value, RuntimeError> {
+        let right = self.evaluate(&unary.right)?;
+        match unary.operator.token_type {
+            TokenType::BANG => Ok(Value::Bool(!right.is_truthy())),
+            TokenType::MINUS => {
+                let n = number_operand(&unary.operator, &right)?;
+                Ok(Value::Number(-n))
+            }
+            TokenType::TILDE => {
+                let n = number_operand(&unary.operator, &right)?;
+                match integer(n) {
+                    Some(n) => Ok(Value::Number(!n as f64)),
+                    None => Err(RuntimeError::new(
+                        &unary.operator,
+                        "Operand must be an integer.",
+                    )),
+                }
+            }
+            _ => unreachable!("unknown unary operator {:?}", unary.operator.token_type),
+        }
+    }
+
+    fn binary(&mut self, binary: &Binary) -> Result<Value, RuntimeError> {
+        let left = self.evaluate(&binary.left)?;
+        let right = self.evaluate(&binary.right)?;
+        let op = &binary.operator;
+        operate(op, op.token_type, left, right)
+    }
+}
+
+/// 命名实参的名字和值
+type Named = Vec<(String, Value)>;
+
+/// 按参数对好的实参
+struct Bound {
+    /// 和参数一一对应，`None` 表示没传，用默认值
+    params: Vec<Option<Value>>,
+    /// 收集进 `...rest` 的多余位置实参
+    rest: Vec<Value>,
+}
+
+/// 把实参对到参数上，出错时的信息里带上函数签名
+fn bind(
+    function: &Function,
+    name: &str,
+    arguments: Vec<Value>,
+    named: Named,
+) -> Result<Bound, String> {
+    let params = &function.params;
+    let required = params
+        .iter()
+        .filter(|param| param.default.is_none())
+        .count();
+    let got = arguments.len() + named.len();
+    let has_named = !named.is_empty();
+    let signature = || signature(function, name);
+    // 参数个数固定的函数保持原来的信息，个数本身就说明了签名
+    let count_error = || {
+        let expected = match &function.rest {
+            Some(_) => format!("at least {}", required),
+            None if required == params.len() => required.to_string(),
+            None => format!("{} to {}", required, params.len()),
+        };
+        let message = format!("Expected {} arguments but got {}.", expected, got);
+        if required == params.len() && function.rest.is_none() {
+            message
+        } else {
+            format!("{} Signature: {}.", message, signature())
+        }
+    };
+    if arguments.len() > params.len() && function.rest.is_none() {
+        return Err(count_error());
+    }
+    let mut arguments = arguments.into_iter();
+    let mut values: Vec<_> = arguments.by_ref().take(params.len()).map(Some).collect();
+    values.resize(params.len(), None);
+    for (name, value) in named {
+        let Some(index) = params.iter().position(|param| param.name.lexeme == name) else {
+            return Err(format!(
+                "Unknown parameter '{}'. Signature: {}.",
+                name,
+                signature()
+            ));
+        };
+        if values[index].is_some() {
+            return Err(format!(
+                "Parameter '{}' got more than one value. Signature: {}.",
+                name,
+                signature()
+            ));
+        }
+        values[index] = Some(value);
+    }
+    let missing = params
+        .iter()
+        .zip(&values)
+        .find(|(param, value)| value.is_none() && param.default.is_none());
+    match missing {
+        Some(_) if !has_named => Err(count_error()),
+        Some((param, _)) => Err(format!(
+            "Missing argument for parameter '{}'. Signature: {}.",
+            param.name.lexeme,
+            signature()
+        )),
+        None => Ok(Bound {
+            params: values,
+            rest: arguments.collect(),
+        }),
+    }
+}
+
+/// `f(a, b?, ...rest)`，有默认值的参数后面带 `?`
+fn signature(function: &Function, name: &str) -> String {
+    let mut params: Vec<_> = function
+        .params
+        .iter()
+        .map(|param| match param.default {
+            Some(_) => format!("{}?", param.name.lexeme),
+            None => param.name.lexeme.clone(),
+        })
+        .collect();
+    if let Some(rest) = &function.rest {
+        params.push(format!("...{}", rest.lexeme));
+    }
+    format!("{}({})", name, params.join(", "))
+}
+
+/// 复合赋值和自增自减的目标，子表达式已经求值
+enum Place<'a> {
+    Variable(&'a Variable),
+    Property(Value, &'a Token),
+    /// 列表或映射、下标和右方括号
+    Element(Value, Value, &'a Token),
+}
+
+/// 循环体执行一轮的结果：是否遇到了 break，continue 和正常结束一样继续下一轮
+fn broke(result: Result<(), Interrupt>) -> Result<bool, Interrupt> {
+    match result {
+        Ok(()) | Err(Interrupt::Continue) => Ok(false),
+        Err(Interrupt::Break) => Ok(true),
+        Err(interrupt) => Err(interrupt),
+    }
+}
+
+/// 和 `token` 同一行的标识符，出错时按 token 的位置报告
+fn identifier(token: &Token, name: &str) -> Token {
+    Token::new(TokenType::IDENTIFIER, name.to_string(), None, token.line)
+}
+
+fn undefined_variable(name: &Token) -> RuntimeError {
+    RuntimeError::new(name, &format!("Undefined variable '{}'.", name.lexeme))
+}
+
+fn undefined_property(name: &Token) -> RuntimeError {
+    RuntimeError::new(name, &format!("Undefined property '{}'.", name.lexeme))
+}
+
+fn no_export(module: &LoxModule, name: &Token) -> RuntimeError {
+    let message = format!(
+        "Module '{}' has no export '{}'.",
+        module.name(),
+        name.lexeme
+    );
+    RuntimeError::new(name, &message)
+}
+
+fn number_operand(op: &Token, value: &Value) -> Result<f64, RuntimeError> {
+    match value {
+        Value::Number(n) => Ok(*n),
+        _ => Err(RuntimeError::new(op, "Operand must be a number.")),
+    }
+}
+
+fn number_operands(
+    op: &Token,
+    left: &Value,
+    right: &Value,
+) -> Result<(f64, f64), RuntimeError> {
+    match (left, right) {
+        (Value::Number(a), Value::Number(b)) => Ok((*a, *b)),
+        _ => Err(RuntimeError::new(op, "Operands must be numbers.")),
+    }
+}
+
+/// 二元运算，`op` 用来定位错误，复合赋值时它是 `+=` 这样的 token
+fn operate(
+    op: &Token,
+    operator: TokenType,
+    left: Value,
+    right: Value,
+) -> Result<Value, RuntimeError> {
+    let value = match operator {
+        TokenType::PLUS => match (left, right) {
+            (Value::Number(a), Value::Number(b)) => Value::Number(a + b),
+            (Value::String(a), Value::String(b)) => Value::String(a + &b),
+            _ => {
+                return Err(RuntimeError::new(
+                    op,
+                    "Operands must be two numbers or two strings.",
+                ));
+            }
+        },
+        TokenType::EQUAL_EQUAL => Value::Bool(left == right),
+        TokenType::BANG_EQUAL => Value::Bool(left != right),
+        TokenType::IS => Value::Bool(match (left, right) {
+            (Value::Instance(instance), Value::Class(class)) => {
+                instance.class.is_subclass_of(&class)
+            }
+            (Value::Userdata(userdata), Value::NativeClass(class)) => {
+                Rc::ptr_eq(userdata.class(), &class)
+            }
+            (_, Value::Class(_) | Value::NativeClass(_)) => false,
+            _ => {
+                return Err(RuntimeError::new(
+                    op,
+                    "Right operand of 'is' must be a class.",
+                ));
+            }
+        }),
+        _ => {
+            let (a, b) = number_operands(op, &left, &right)?;
+            match operator {
+                TokenType::MINUS => Value::Number(a - b),
+                TokenType::STAR => Value::Number(a * b),
+                TokenType::SLASH => Value::Number(a / b),
+                TokenType::PERCENT => Value::Number(modulo(op, a, b)?),
+                TokenType::TILDE_SLASH => Value::Number(floor_division(op, a, b)?),
+                TokenType::STAR_STAR => Value::Number(a.powf(b)),
+                TokenType::AMPERSAND
+                | TokenType::PIPE
+                | TokenType::CARET
+                | TokenType::LESS_LESS
+                | TokenType::GREATER_GREATER => Value::Number(bitwise(op, operator, a, b)? as f64),
+                TokenType::GREATER => Value::Bool(a > b),
+                TokenType::GREATER_EQUAL => Value::Bool(a >= b),
+                TokenType::LESS => Value::Bool(a < b),
+                TokenType::LESS_EQUAL => Value::Bool(a <= b),
+                _ => unreachable!("unknown binary operator {:?}", operator),
+            }
+        }
+    };
+    Ok(value)
+}
+
+/// 读取下标对应的元素
+fn element(object: &Value, key: &Value, bracket: &Token) -> Result<Value, RuntimeError> {
+    let error = |message: &str| RuntimeError::new(bracket, message);
+    match object {
+        Value::List(values) => {
+            let values = values.borrow();
+            let position = list::position(key, values.len()).map_err(|m| error(&m))?;
+            Ok(values[position].clone())
+        }
+        Value::Map(map) => match map.borrow().get(key) {
+            Some(value) => Ok(value.clone()),
+            None => Err(error(&map::missing_key(key))),
+        },
+        _ => Err(error("Only lists and maps can be indexed.")),
+    }
+}
+
+fn set_element(
+    object: &Value,
+    key: Value,
+    value: Value,
+    bracket: &Token,
+) -> Result<(), RuntimeError> {
+    let error = |message: &str| RuntimeError::new(bracket, message);
+    match object {
+        Value::List(values) => {
+            let mut values = values.borrow_mut();
+            let position = list::position(&key, values.len()).map_err(|m| error(&m))?;
+            values[position] = value;
+        }
+        Value::Map(map) => {
+            map.borrow_mut().insert(key, value).map_err(|m| error(&m))?;
+        }
+        _ => return Err(error("Only lists and maps can be indexed.")),
+    }
+    Ok(())
+}
+
+fn set_property(object: &Value, name: &Token, value: Value) -> Result<(), RuntimeError> {
+    match object {
+        Value::Instance(instance) => {
+            instance.set_field(&name.lexeme, value);
+            Ok(())
+        }
+        Value::Userdata(userdata) => userdata
+            .set(&name.lexeme, value)
+            .map_err(|message| RuntimeError::new(name, &message)),
+        _ => Err(RuntimeError::new(name, "Only instances have fields.")),
+    }
+}
+
+/// 没有小数部分、在 i64 范围内的数字
+fn integer(n: f64) -> Option<i64> {
+    // i64::MAX 转成 f64 正好是 2^63，已经超出范围
+    (n.fract() == 0.0 && n >= i64::MIN as f64 && n < i64::MAX as f64).then_some(n as i64)
+}
+
+/// 取余的结果和除数同号，和 Python 一样，保证 `a == (a ~/ b) * b + a % b`
+fn modulo(op: &Token, a: f64, b: f64) -> Result<f64, RuntimeError> {
+    if b == 0.0 {
+        return Err(RuntimeError::new(op, "Division by zero."));
+    }
+    let r = a % b;
+    Ok(if r != 0.0 && (r < 0.0) != (b < 0.0) {
+        r + b
+    } else {
+        r
+    })
+}
+
+/// 整除向负无穷取整
+fn floor_division(op: &Token, a: f64, b: f64) -> Result<f64, RuntimeError> {
+    if b == 0.0 {
+        return Err(RuntimeError::new(op, "Division by zero."));
+    }
+    Ok((a / b).floor())
+}
+
+/// 位运算，两边都要是整数
+fn bitwise(op: &Token, operator: TokenType, a: f64, b: f64) -> Result<i64, RuntimeError> {
+    let (Some(a), Some(b)) = (integer(a), integer(b)) else {
+        return Err(RuntimeError::new(op, "Operands must be integers."));
+    };
+    let shift = || {
+        u32::try_from(b)
+            .ok()
+            .filter(|b| *b < i64::BITS)
+            .ok_or_else(|| RuntimeError::new(op, "Shift count must be between 0 and 63."))
+    };
+    Ok(match operator {
+        TokenType::AMPERSAND => a & b,
+        TokenType::PIPE => a | b,
+        TokenType::CARET => a ^ b,
+        TokenType::LESS_LESS => a << shift()?,
+        // 算术右移，负数保持负号
+        TokenType::GREATER_GREATER => a >> shift()?,
+        _ => unreachable!("unknown bitwise operator {:?}", operator),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::native::{NativeClass, arg};
+    use crate::parser::{Parse, parse_expr};
+    use crate::scanner::Scanner;
+
+    fn eval(source: &str) -> Result<String, String> {
+        let expr = parse_expr(source);
+        Resolver::new(&HashMap::new()).resolve_expr(&expr);
+        Interpreter::new()
+            .evaluate(&expr)
+            .map(|value| value.to_string())
+            .map_err(|e| e.message)
+    }
+
+    /// 执行一段程序，然后在同一个解释器里求值 `result`
+    fn run(source: &str, result: &str) -> Result<String, String> {
+        let mut scanner = Scanner::new(source.to_string());
+        scanner.scan_tokens();
+        let statements = Parse::new(scanner).parse();
+        Resolver::new(&HashMap::new()).resolve(&statements);
+        let mut interpreter = Interpreter::new();
+        interpreter.interpret(&statements).map_err(|e| e.message)?;
+        let result = parse_expr(result);
+        Resolver::new(&HashMap::new()).resolve_expr(&result);
+        interpreter
+            .evaluate(&result)
+            .map(|value| value.to_string())
+            .map_err(|e| e.message)
+    }
+
+    #[test]
+    fn test_evaluate() {
+        assert_eq!(eval("1 + 2 * 3"), Ok("7".to_string()));
+        assert_eq!(eval("(1 + 2) / 4"), Ok("0.75".to_string()));
+        assert_eq!(eval("\"a\" + \"b\""), Ok("ab".to_string()));
+        assert_eq!(eval("!nil == true"), Ok("true".to_string()));
+        assert_eq!(eval("1 == \"1\""), Ok("false".to_string()));
+        assert_eq!(eval("-(2 - 5) >= 3"), Ok("true".to_string()));
+        assert_eq!(eval("-7 % 3"), Ok("2".to_string()));
+        assert_eq!(eval("7 % -3"), Ok("-2".to_string()));
+        assert_eq!(eval("-7 ~/ 2"), Ok("-4".to_string()));
+        assert_eq!(eval("7.5 ~/ 2"), Ok("3".to_string()));
+        assert_eq!(eval("-2 ** 2"), Ok("-4".to_string()));
+        assert_eq!(eval("2 ** 3 ** 2"), Ok("512".to_string()));
+        assert_eq!(eval("2 ** -1"), Ok("0.5".to_string()));
+        assert_eq!(eval("6 & 3 | 8 ^ 1"), Ok("11".to_string()));
+        assert_eq!(eval("1 << 2 + 1"), Ok("8".to_string()));
+        assert_eq!(eval("-8 >> 1"), Ok("-4".to_string()));
+        assert_eq!(eval("~5"), Ok("-6".to_string()));
+        assert_eq!(eval("5 & 1 == 1"), Ok("true".to_string()));
+    }
+
+    #[test]
+    fn test_runtime_error() {
+        assert_eq!(eval("-\"a\""), Err("Operand must be a number.".to_string()));
+        assert_eq!(
+            eval("1 < \"2\""),
+            Err("Operands must be numbers.".to_string())
+        );
+        assert_eq!(
+            eval("1 + true"),
+            Err("Operands must be two numbers or two strings.".to_string())
+        );
+        assert_eq!(eval("1 % 0"), Err("Division by zero.".to_string()));
+        assert_eq!(eval("1 ~/ 0"), Err("Division by zero.".to_string()));
+        assert_eq!(
+            eval("1.5 | 1"),
+            Err("Operands must be integers.".to_string())
+        );
+        assert_eq!(eval("~0.5"), Err("Operand must be an integer.".to_string()));
+        assert_eq!(
+            eval("1 << 64"),
+            Err("Shift count must be between 0 and 63.".to_string())
+        );
+        assert!(eval("1 >> -1").is_err());
+        assert!(eval("\"a\" % 2").is_err());
+    }
+
+    #[test]
+    fn test_variables() {
+        assert_eq!(run("var a = 1; a = a + 2;", "a"), Ok("3".to_string()));
+        assert_eq!(run("var a;", "a"), Ok("nil".to_string()));
+        assert_eq!(
+            run("var a = 1; { var a = 2; a = 3; }", "a"),
+            Ok("1".to_string())
+        );
+        assert_eq!(
+            run("var a = 1; { a = 2; }", "a"),
+            Ok("2".to_string())
+        );
+        assert_eq!(
+            run("b = 1;", "nil"),
+            Err("Undefined variable 'b'.".to_string())
+        );
+        assert_eq!(
+            run("{ var c = 1; }", "c"),
+            Err("Undefined variable 'c'.".to_string())
+        );
+        assert_eq!(run("const d = 1; { const d = 2; }", "d"), Ok("1".to_string()));
+    }
+
+    #[test]
+    fn test_optional_chaining() {
+        let class = "class P { init(next) { this.next = next; } name() { return \"p\"; } }";
+        assert_eq!(run(class, "nil?.next.next.name()"), Ok("nil".to_string()));
+        assert_eq!(run(class, "P(nil)?.name()"), Ok("p".to_string()));
+        assert_eq!(
+            run(class, "P(P(nil)).next?.next?.name()"),
+            Ok("nil".to_string())
+        );
+        assert_eq!(
+            run(class, "P(nil).next.name()"),
+            Err("Only instances have properties.".to_string())
+        );
+        // 括号结束访问链
+        assert_eq!(
+            run(class, "(nil?.next).name"),
+            Err("Only instances have properties.".to_string())
+        );
+        assert_eq!(eval("nil ?? 1"), Ok("1".to_string()));
+        assert_eq!(eval("false ?? 1"), Ok("false".to_string()));
+        assert_eq!(eval("nil ?? nil ?? \"c\""), Ok("c".to_string()));
+    }
+
+    #[test]
+    fn test_lambda() {
+        assert_eq!(eval("(fun (a) { return a * 2; })(21)"), Ok("42".to_string()));
+        assert_eq!(eval("((a, b) => a - b)(3, 1)"), Ok("2".to_string()));
+        assert_eq!(eval("(() => { var a = 1; })()"), Ok("nil".to_string()));
+        assert_eq!(eval("fun () {}"), Ok("<fn lambda>".to_string()));
+        // 和具名函数一样捕获定义时的环境
+        let counter = "fun counter() { var n = 0; return () => n += 1; } var c = counter(); c();";
+        assert_eq!(run(counter, "c()"), Ok("2".to_string()));
+        assert_eq!(
+            run("fun twice(f, x) { return f(f(x)); }", "twice((x) => x * x, 3)"),
+            Ok("81".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parameters() {
+        let f = "fun f(a, b = a * 2, ...rest) { return [a, b, rest]; }";
+        assert_eq!(run(f, "f(1)"), Ok("[1, 2, []]".to_string()));
+        assert_eq!(run(f, "f(1, 3, 4, 5)"), Ok("[1, 3, [4, 5]]".to_string()));
+        assert_eq!(run(f, "f(b: 5, a: 1)"), Ok("[1, 5, []]".to_string()));
+        assert_eq!(
+            run(f, "f()"),
+            Err(
+                "Expected at least 1 arguments but got 0. Signature: f(a, b?, ...rest)."
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            run(f, "f(1, a: 2)"),
+            Err("Parameter 'a' got more than one value. Signature: f(a, b?, ...rest).".to_string())
+        );
+        assert_eq!(
+            run("fun g(a, b = 1) {}", "g(1, 2, 3)"),
+            Err("Expected 1 to 2 arguments but got 3. Signature: g(a, b?).".to_string())
+        );
+        let class = "class P { init(x, y = 0) { this.x = x; this.y = y; } }";
+        assert_eq!(run(class, "P(y: 2, x: 1).x"), Ok("1".to_string()));
+        assert_eq!(
+            run(class, "P(z: 1)"),
+            Err("Unknown parameter 'z'. Signature: P(x, y?).".to_string())
+        );
+        assert_eq!(
+            eval("clock(a: 1)"),
+            Err("Native functions don't take named arguments.".to_string())
+        );
+    }
+
+    #[test]
+    fn test_compound_assignment() {
+        assert_eq!(run("var a = 1; a += 2; a *= 4;", "a"), Ok("12".to_string()));
+        assert_eq!(run("var s = \"a\"; s += \"b\";", "s"), Ok("ab".to_string()));
+        assert_eq!(
+            run("var a = 1; var b = a++;", "[a, b]"),
+            Ok("[2, 1]".to_string())
+        );
+        assert_eq!(
+            run("var a = 1; var b = --a;", "[a, b]"),
+            Ok("[0, 0]".to_string())
+        );
+        // 下标只求值一次
+        assert_eq!(
+            run("var i = 0; var xs = [1, 2]; xs[i++] += 10;", "[i, xs]"),
+            Ok("[1, [11, 2]]".to_string())
+        );
+        assert_eq!(
+            run("var m = {\"k\": 1}; m[\"k\"] -= 3; m[\"k\"]--;", "m[\"k\"]"),
+            Ok("-3".to_string())
+        );
+        assert_eq!(
+            run("var a = \"x\"; a++;", "nil"),
+            Err("Operand must be a number.".to_string())
+        );
+        assert_eq!(
+            run("var a = 1; a -= \"x\";", "nil"),
+            Err("Operands must be numbers.".to_string())
+        );
+        assert_eq!(
+            run("b += 1;", "nil"),
+            Err("Undefined variable 'b'.".to_string())
+        );
+    }
+
+    /// 之前输入里声明的全局常量，之后的输入也不能赋值
+    #[test]
+    fn test_constants() {
+        let mut lox = Interpreter::new();
+        lox.eval("const limit = 10; { const local = 1; }").unwrap();
+        let error = lox.eval("limit = 1").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "[line 1] Error at 'limit': Can't assign to a constant.\n\
+             [line 1] Note: Constant 'limit' is declared here."
+        );
+        assert!(lox.eval("fun f() { limit = 1; }").is_err());
+        assert!(lox.eval("var limit;").is_err());
+        assert!(lox.eval("var local = 2; local = 3;").is_ok());
+        assert_eq!(lox.eval("limit").unwrap(), Value::Number(10.0));
+    }
+
+    #[test]
+    fn test_control_flow() {
+        assert_eq!(
+            run("var a = 0; for (var i = 0; i < 5; i = i + 1) a = a + i;", "a"),
+            Ok("10".to_string())
+        );
+        assert_eq!(
+            run("var a = 1; while (a < 100) a = a * 2;", "a"),
+            Ok("128".to_string())
+        );
+        assert_eq!(
+            run("var a; if (nil) a = 1; else if (0) a = 2; else a = 3;", "a"),
+            Ok("2".to_string())
+        );
+        // 循环变量不会泄漏到外面
+        assert_eq!(
+            run("for (var i = 0; i < 1; i = i + 1) {}", "i"),
+            Err("Undefined variable 'i'.".to_string())
+        );
+        assert_eq!(eval("nil or \"x\""), Ok("x".to_string()));
+        assert_eq!(eval("false and undefined"), Ok("false".to_string()));
+    }
+
+    #[test]
+    fn test_functions() {
+        assert_eq!(
+            run(
+                "fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }",
+                "fib(10)"
+            ),
+            Ok("55".to_string())
+        );
+        // 闭包捕获的是变量而不是值
+        assert_eq!(
+            run(
+                "fun counter() { var i = 0; fun inc() { i = i + 1; return i; } return inc; }
+                 var c = counter(); c(); c();",
+                "c()"
+            ),
+            Ok("3".to_string())
+        );
+        assert_eq!(run("fun f() {}", "f()"), Ok("nil".to_string()));
+        assert_eq!(run("fun f() {}", "f"), Ok("<fn f>".to_string()));
+        assert_eq!(
+            run("fun f(a, b) {}", "f(1)"),
+            Err("Expected 2 arguments but got 1.".to_string())
+        );
+        assert_eq!(
+            eval("\"a\"()"),
+            Err("Can only call functions and classes.".to_string())
+        );
+        assert_eq!(
+            run("fun f() { f(); }", "f()"),
+            Err("Stack overflow.".to_string())
+        );
+    }
+
+    #[test]
+    fn test_stack_overflow_on_small_thread() {
+        // 默认大小的线程上，帧很大的递归也要在宿主栈耗尽之前报错
+        let source = "fun rec(n) { if (n == 0) return 0; return 1 + rec(n - 1); }";
+        let thread = std::thread::spawn(move || (run(source, "rec(255)"), run(source, "rec(256)")));
+        let (deepest, overflow) = thread.join().unwrap();
+        assert_eq!(deepest, Ok("255".to_string()));
+        assert_eq!(overflow, Err("Stack overflow.".to_string()));
+    }
+
+    /// 共享同一块缓冲区的输出，测试里用来拿到 print 的内容
+    #[derive(Clone, Default)]
+    struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Buffer {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.borrow().clone()).unwrap()
+        }
+    }
+
+    #[test]
+    fn test_embedding() {
+        let mut lox = Interpreter::new();
+        let stdout = Buffer::default();
+        let stderr = Buffer::default();
+        lox.set_stdout(stdout.clone());
+        lox.set_stderr(stderr.clone());
+
+        assert_eq!(lox.eval("1 + 2").unwrap(), Value::Number(3.0));
+        assert_eq!(
+            lox.eval("fun add(a, b) { return a + b; } print add(1, 2);")
+                .unwrap(),
+            Value::Nil
+        );
+        assert_eq!(lox.eval("var s = \"x\"; s + \"y\";").unwrap(), "xy".into());
+        assert_eq!(stdout.contents(), "3\n");
+
+        assert_eq!(
+            lox.call_function("add", &["a".into(), "b".into()]).unwrap(),
+            "ab".into()
+        );
+        let error = lox.call_function("add", &[1.0.into()]).unwrap_err();
+        assert_eq!(error.to_string(), "Expected 2 arguments but got 1.\n[line 0]");
+        assert!(lox.call_function("s", &[]).is_err());
+        assert!(lox.call_function("missing", &[]).is_err());
+
+        lox.set_global("limit", 10.0);
+        assert_eq!(lox.eval("limit * 2").unwrap(), Value::Number(20.0));
+        assert_eq!(lox.get_global("s"), Some("x".into()));
+        assert_eq!(lox.get_global("missing"), None);
+
+        // 错误交给宿主处理，report 才输出到 stderr
+        let error = lox.eval("print undefined;").unwrap_err();
+        assert!(matches!(&error, LoxError::Runtime(e) if e.line() == 1));
+        let error = lox.eval("print 1").unwrap_err();
+        assert!(matches!(&error, LoxError::Compile(d) if d.len() == 1));
+        assert_eq!(stderr.contents(), "");
+        lox.report(&error);
+        assert_eq!(stderr.contents(), "[line 1] Error at end: Expect ';' after value.\n");
+    }
+
+    #[test]
+    fn test_native_functions() {
+        let mut lox = Interpreter::new();
+        lox.register_native("add", 2, |args| {
+            Ok((arg::<f64>(args, 0)? + arg::<f64>(args, 1)?).into())
+        });
+        lox.register_native("fail", 0, |_| Err("Host said no.".to_string()));
+
+        assert_eq!(lox.eval("add(1, add(2, 3))").unwrap(), Value::Number(6.0));
+        assert_eq!(lox.eval("add").unwrap().to_string(), "<native fn>");
+        assert_eq!(
+            lox.call_function("add", &[1.0.into(), 2.0.into()]).unwrap(),
+            Value::Number(3.0)
+        );
+        // 错误出现在调用处
+        let mut error = |source: &str| match lox.eval(source).unwrap_err() {
+            LoxError::Runtime(e) => e.to_string(),
+            error => panic!("unexpected error {:?}", error),
+        };
+        assert_eq!(
+            error("\n\nadd(1, \"2\");"),
+            "Argument 2 must be a number but got string.\n[line 3]"
+        );
+        assert_eq!(error("add(1);"), "Expected 2 arguments but got 1.\n[line 1]");
+        assert_eq!(error("fun f() { fail(); }\nf();"), "Host said no.\n[line 1]");
+    }
+
+    struct Config {
+        name: String,
+        retries: f64,
+    }
+
+    #[test]
+    fn test_native_class() {
+        let mut lox = Interpreter::new();
+        let class = lox.register_class(
+            NativeClass::builder::<Config>("Config")
+                .constructor(1, |args| {
+                    Ok(Config {
+                        name: arg(args, 0)?,
+                        retries: 0.0,
+                    })
+                })
+                .getter("name", |c| Ok(c.name.as_str().into()))
+                .getter("retries", |c| Ok(c.retries.into()))
+                .setter("retries", |c, value| {
+                    c.retries = arg(&[value], 0)?;
+                    Ok(())
+                })
+                .method("describe", 0, |c, _| {
+                    Ok(format!("{} x{}", c.name, c.retries).into())
+                })
+                .display(|c| format!("<config {}>", c.name))
+                .build(),
+        );
+        let host = class.instance(Config {
+            name: "host".to_string(),
+            retries: 1.0,
+        });
+        lox.set_global("config", host.clone());
+
+        let eval = |lox: &mut Interpreter, source: &str| match lox.eval(source) {
+            Ok(value) => value.to_string(),
+            Err(error) => error.to_string(),
+        };
+        assert_eq!(eval(&mut lox, "config.retries = config.retries + 2"), "3");
+        assert_eq!(eval(&mut lox, "config.describe()"), "host x3");
+        assert_eq!(eval(&mut lox, "config"), "<config host>");
+        assert_eq!(eval(&mut lox, "config is Config"), "true");
+        assert_eq!(eval(&mut lox, "Config(\"a\") == Config(\"a\")"), "false");
+        assert_eq!(eval(&mut lox, "config == config"), "true");
+        assert_eq!(eval(&mut lox, "Config(\"made\").name"), "made");
+        assert_eq!(
+            eval(&mut lox, "config.name = \"x\""),
+            "Property 'name' is read-only.\n[line 1]"
+        );
+        assert_eq!(
+            eval(&mut lox, "config.missing"),
+            "Undefined property 'missing'.\n[line 1]"
+        );
+        assert_eq!(
+            eval(&mut lox, "config.retries = \"many\""),
+            "Argument 1 must be a number but got string.\n[line 1]"
+        );
+
+        // 脚本里的修改宿主能看到
+        let Value::Userdata(userdata) = host else {
+            panic!("expected userdata");
+        };
+        assert_eq!(userdata.borrow::<Config>().unwrap().retries, 3.0);
+        assert!(userdata.borrow::<String>().is_none());
+        let Some(Value::Userdata(made)) = lox.call_function("Config", &["b".into()]).ok() else {
+            panic!("expected userdata");
+        };
+        assert_eq!(made.borrow::<Config>().unwrap().name, "b");
+    }
+
+    #[test]
+    fn test_io() {
+        let mut lox = Interpreter::new();
+        lox.set_stdin(std::io::Cursor::new("one\r\ntwo"));
+        lox.set_args(vec!["x".to_string()]);
+        assert!(lox.eval("readLine()").is_err());
+        lox.set_capabilities(Capabilities {
+            stdin: true,
+            ..Capabilities::default()
+        });
+        assert_eq!(lox.eval("readLine()").unwrap(), "one".into());
+        assert_eq!(lox.eval("readLine()").unwrap(), "two".into());
+        assert_eq!(lox.eval("readLine()").unwrap(), Value::Nil);
+        assert_eq!(lox.eval("args()").unwrap().to_string(), "[\"x\"]");
+
+        let name = format!("lox_test_io_{}.txt", std::process::id());
+        let path = std::env::temp_dir().join(name);
+        lox.set_global("path", path.to_string_lossy().as_ref());
+        assert!(lox.eval("writeFile(path, \"hi\");").is_err());
+        assert!(!path.exists());
+        lox.set_capabilities(Capabilities {
+            fs: true,
+            env: false,
+            stdin: true,
+        });
+        lox.eval("writeFile(path, \"hi\");").unwrap();
+        assert_eq!(lox.eval("readFile(path)").unwrap(), "hi".into());
+        std::fs::remove_file(&path).unwrap();
+        assert!(lox.eval("env(\"PATH\")").is_err());
+
+        // exit 不是运行时错误，也不会在宿主里退出进程
+        let stdout = Buffer::default();
+        lox.set_stdout(stdout.clone());
+        let error = lox.eval("fun f() { exit(2); print 1; } f(); print 2;").unwrap_err();
+        assert!(matches!(error, LoxError::Exit(2)));
+        assert_eq!(stdout.contents(), "");
+        let error = lox.call_function("exit", &[7.0.into()]).unwrap_err();
+        assert!(matches!(error, LoxError::Exit(7)));
+    }
+
+    #[test]
+    fn test_modules() {
+        let dir = std::env::temp_dir().join(format!("lox_test_modules_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("util.lox"), "export var x = 1;").unwrap();
+        std::fs::write(dir.join("broken.lox"), "export var;").unwrap();
+
+        let mut lox = Interpreter::new();
+        assert!(lox.eval("import \"util\" as util;").is_err());
+        lox.set_module_path(vec![dir.clone()]);
+        lox.eval("import \"util\" as util;").unwrap();
+        assert_eq!(lox.eval("util.x").unwrap(), Value::Number(1.0));
+        let Value::Module(module) = lox.get_global("util").unwrap() else {
+            panic!("expected a module");
+        };
+        assert_eq!(module.name(), "util");
+        assert_eq!(module.get("missing"), None);
+
+        // 模块的语法错误是导入处的运行时错误
+        let error = lox.eval("import \"broken\" as broken;").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Could not compile module 'broken':\n\
+             [line 1] Error at ';': Expect variable name.\n[line 1]"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,19 +1,64 @@
-use std::{fs::File, io::{Read, Write}, path::Path, sync::atomic::AtomicBool};
-
-use crate::scanner::Scanner;
-
+use std::{
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+    sync::atomic::AtomicBool,
+};
+
+mod ast_printer;
+mod diagnostic;
+mod environment;
 mod scanner;
 mod expr;
+mod stmt;
 mod token;
+mod value;
+mod list;
+mod map;
+mod module;
+mod native;
+mod stdlib;
 mod generate_ast;
 mod parser;
+mod resolver;
+mod cst;
+mod interpreter;
+mod optimizer;
+mod formatter;
+mod lint;
+mod lsp;
+mod repl;
+
+pub use diagnostic::{Diagnostic, Severity};
+pub use formatter::{FormatConfig, format_source};
+pub use interpreter::{Interpreter, LoxError, RuntimeError};
+pub use lint::{LintConfig, RULES, lint_source};
+pub use lsp::run_lsp;
+pub use module::LoxModule;
+pub use native::{
+    FromValue, NativeClass, NativeClassBuilder, NativeFunction, NativeResult, Userdata, arg,
+};
+pub use optimizer::OptimizerConfig;
+pub use repl::run_prompt;
+pub use stdlib::Capabilities;
+pub use value::{LoxClass, LoxFunction, LoxInstance, LoxMap, Value};
 
 
 pub static HAD_ERROR: AtomicBool = AtomicBool::new(false);
+pub static HAD_RUNTIME_ERROR: AtomicBool = AtomicBool::new(false);
 ///扫描代码
 /// 扫描代码的入口函数
-
-pub fn  read_code_file(path: &str) {
+///
+/// `capabilities` 决定脚本能不能读写文件、读取环境变量和 stdin，`args` 是脚本里 `args()` 的返回值，
+/// `optimizer` 是编译时打开的优化，`module_path` 是 import 查找模块的目录。
+/// 返回进程的退出码：脚本调用 `exit` 时用它给出的退出码，编译错误是 65，运行时错误是 70。
+pub fn  read_code_file(
+    path: &str,
+    capabilities: Capabilities,
+    optimizer: OptimizerConfig,
+    args: Vec<String>,
+    module_path: Vec<PathBuf>,
+) -> i32 {
     // 这里可以添加代码扫描的逻辑
     let path = Path::new(path);
 
@@ -26,7 +71,7 @@ pub fn  read_code_file(path: &str) {
     if !path.exists() || !path.is_file() {
         // println!("Path does not exist: {}", path.display());
         eprintln!("Expected a file,path: {}", path.display());
-        return;
+        return 0;
     }
 
     // 读取文件内容
@@ -34,48 +79,51 @@ pub fn  read_code_file(path: &str) {
         Ok(file) => file,
         Err(e) => {
             eprintln!("Error opening file {}: {}", path.display(), e);
-            return;
+            return 0;
         }
     };
     let mut content = String::new();
     let _ = file.read_to_string(&mut content);
-    run(content);
+    let mut interpreter = Interpreter::new();
+    interpreter.set_capabilities(capabilities);
+    interpreter.set_optimizer(optimizer);
+    interpreter.set_args(args);
+    interpreter.set_module_path(module_path);
+    // 模块的相对路径从脚本所在的目录开始找，导入脚本自己算作循环导入
+    if let Ok(canonical) = path.canonicalize() {
+        interpreter.modules.enter(canonical, path.display().to_string());
+    }
+    if let Some(code) = run(content, &mut interpreter) {
+        return code;
+    }
 
     if HAD_ERROR.load(std::sync::atomic::Ordering::SeqCst) {
         // 如果有错误，退出
-        std::process::exit(65);
+        return 65;
     }
-}
-
-pub fn run_prompt() {
-    println!("Running in interactive mode...");
-    loop {
-        //这个宏不会自动刷新输出缓冲区，因此需要手动刷新
-        print!(">> ");
-        std::io::stdout().flush().unwrap();
-        let mut input = String::new();
-
-        if std::io::stdin().read_line(&mut input).is_err() {
-            break; // 如果读取失败，退出循环
-        }
-        run(input);
-        // 重置错误状态
-        HAD_ERROR.store(false, std::sync::atomic::Ordering::SeqCst);
+    if HAD_RUNTIME_ERROR.load(std::sync::atomic::Ordering::SeqCst) {
+        return 70;
     }
+    0
 }
 
-fn run(code: String) {
-    let mut scanner = Scanner::new(code);
-    let tokens = scanner.scan_tokens();
-
-    let mut parser = parser::Parse::new(scanner);
-    let expr = parser.parse();
-
-    println!("Parsed expression: {:?}", expr);
+/// 执行一段脚本，错误输出到 stderr 并记录在全局标志里；脚本调用了 `exit` 时返回退出码
+fn run(code: String, interpreter: &mut Interpreter) -> Option<i32> {
+    let error = interpreter.run(&code).err()?;
+    report_error(interpreter, &error)
+}
 
-    // for token in tokens.iter() {
-    //     println!("{:?}", token);
-    // }
+/// 输出错误，并按错误种类设置对应的全局标志；脚本调用了 `exit` 时返回它给出的退出码，
+/// 由调用方决定什么时候退出进程
+pub(crate) fn report_error(interpreter: &mut Interpreter, error: &LoxError) -> Option<i32> {
+    interpreter.report(error);
+    let flag = match error {
+        LoxError::Compile(_) => &HAD_ERROR,
+        LoxError::Runtime(_) => &HAD_RUNTIME_ERROR,
+        LoxError::Exit(code) => return Some(*code),
+    };
+    flag.store(true, std::sync::atomic::Ordering::SeqCst);
+    None
 }
 
 pub fn err(line: u32, msg: &str) {
@@ -84,7 +132,17 @@ pub fn err(line: u32, msg: &str) {
     report(line, "", msg);
 }
 
-fn report(line: u32, wher: &str, msg: &str) {
+/// 把扫描、解析时收集到的错误输出到 stderr
+pub(crate) fn report_diagnostics(diagnostics: &[Diagnostic]) {
+    for diagnostic in diagnostics {
+        report(diagnostic.line, &diagnostic.wher, &diagnostic.message);
+        if let Some(note) = &diagnostic.note {
+            eprintln!("{}", note);
+        }
+    }
+}
+
+pub(crate) fn report(line: u32, wher: &str, msg: &str) {
     eprintln!("[line {}] Error{}: {}", line, wher, msg);
     // Ordering::SeqCst：
 
@@ -101,7 +159,7 @@ mod tests {
     #[test]
     fn test_run() {
         let code = "(a==1)";
-        run(code.to_string());
+        run(code.to_string(), &mut Interpreter::new());
     }
 
 }
@@ -0,0 +1,761 @@
+// 静态检查 (lox lint)
+// 在 AST 上跑一组规则，结果和语法错误一样以 Diagnostic 返回，只是级别为 Warning。
+// 规则可以用文件里的注释关闭：`// lox-lint: disable=mixed-type-comparison`，
+// 也可以在配置文件里关闭：`disable = mixed-type-comparison`。
+use std::collections::{HashMap, HashSet};
+
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::expr::{Assignment, Binary, Expr, Set};
+use crate::parser::Parse;
+use crate::resolver::Resolver;
+use crate::scanner::Scanner;
+use crate::stmt::{Function, Imported, Stmt};
+use crate::token::{LiteralType, Token, TokenType, TriviaKind};
+
+/// 所有规则的 id 和说明
+pub const RULES: [(&str, &str); 7] = [
+    (
+        MIXED_TYPE_COMPARISON,
+        "`==`/`!=` between a literal and a value of a different type",
+    ),
+    (
+        UNUSED_VARIABLE,
+        "local variable or constant that is never read",
+    ),
+    (UNUSED_PARAMETER, "function parameter that is never read"),
+    (
+        UNREACHABLE_CODE,
+        "statements after `return`, `break` or `continue`",
+    ),
+    (
+        SHADOWED_VARIABLE,
+        "local declaration hiding one from an enclosing scope",
+    ),
+    (SELF_ASSIGNMENT, "assigning a variable or field to itself"),
+    (EMPTY_BLOCK, "block statement `{}` with nothing in it"),
+];
+
+const MIXED_TYPE_COMPARISON: &str = "mixed-type-comparison";
+const UNUSED_VARIABLE: &str = "unused-variable";
+const UNUSED_PARAMETER: &str = "unused-parameter";
+const UNREACHABLE_CODE: &str = "unreachable-code";
+const SHADOWED_VARIABLE: &str = "shadowed-variable";
+const SELF_ASSIGNMENT: &str = "self-assignment";
+const EMPTY_BLOCK: &str = "empty-block";
+
+/// lint 配置，记录被关闭的规则
+#[derive(Debug, Clone, Default)]
+pub struct LintConfig {
+    disabled: HashSet<String>,
+}
+
+impl LintConfig {
+    /// 解析配置文件，每行一条 `disable = rule-a, rule-b`，`#` 开头是注释
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut config = Self::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some(("disable", rules)) = line.split_once('=').map(|(k, v)| (k.trim(), v)) else {
+                return Err(format!("line {}: expected 'disable = <rules>'", i + 1));
+            };
+            for rule in rules.split(',').map(str::trim) {
+                if !is_rule(rule) {
+                    return Err(format!("line {}: unknown lint rule '{}'", i + 1, rule));
+                }
+                config.disabled.insert(rule.to_string());
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// 检查一段源码，返回语法错误和 lint 警告
+pub fn lint_source(source: &str, config: &LintConfig) -> Vec<Diagnostic> {
+    let mut scanner = Scanner::with_trivia(source.to_string());
+    let tokens = scanner.scan_tokens();
+    let mut diagnostics = std::mem::take(&mut scanner.diagnostics);
+    if tokens.len() == 1 {
+        return diagnostics;
+    }
+    let mut parser = Parse::new(scanner);
+    let statements = parser.parse();
+    diagnostics.append(&mut parser.diagnostics);
+    if !diagnostics.is_empty() {
+        return diagnostics;
+    }
+    let mut resolver = Resolver::new(&HashMap::new());
+    resolver.resolve(&statements);
+    if !resolver.diagnostics.is_empty() {
+        return resolver.diagnostics;
+    }
+
+    let mut linter = Linter {
+        disabled: config.disabled.clone(),
+        diagnostics,
+        scopes: vec![Vec::new()],
+        tokens: Vec::new(),
+    };
+    // 文件里的注释指令
+    for token in &tokens {
+        for trivia in token.leading.iter().chain(&token.trailing) {
+            if matches!(
+                trivia.kind,
+                TriviaKind::LineComment | TriviaKind::BlockComment
+            ) {
+                linter.pragma(&trivia.text, token);
+            }
+        }
+    }
+    linter.tokens = tokens;
+    linter.statements(&statements);
+    // 未使用的变量在作用域结束时才报，按位置排回源码顺序
+    linter.diagnostics.sort_by_key(|d| d.span.start);
+    linter.diagnostics
+}
+
+fn is_rule(id: &str) -> bool {
+    RULES.iter().any(|(rule, _)| *rule == id)
+}
+
+struct Linter {
+    disabled: HashSet<String>,
+    diagnostics: Vec<Diagnostic>,
+    /// 作用域栈，第一个是全局作用域
+    scopes: Vec<Vec<Local>>,
+    /// 整个文件的 token，语句本身不记录开头的 token，要靠它找
+    tokens: Vec<Token>,
+}
+
+/// 作用域里声明的一个名字
+struct Local {
+    name: Token,
+    kind: LocalKind,
+    used: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum LocalKind {
+    Variable,
+    Parameter,
+    /// 函数、类、循环变量和 import 进来的名字，不检查是否使用
+    Other,
+}
+
+impl Linter {
+    fn pragma(&mut self, comment: &str, token: &Token) {
+        let text = comment.trim_start_matches('/').trim_start_matches('*');
+        let text = text.trim_end_matches('/').trim_end_matches('*').trim();
+        let Some(rules) = text
+            .strip_prefix("lox-lint:")
+            .and_then(|rest| rest.trim().strip_prefix("disable="))
+        else {
+            return;
+        };
+        for rule in rules.split(',').map(str::trim) {
+            if is_rule(rule) {
+                self.disabled.insert(rule.to_string());
+            } else {
+                let mut diagnostic = Diagnostic::new(
+                    token.line,
+                    token.offset..token.offset,
+                    &format!("Unknown lint rule '{}'.", rule),
+                );
+                diagnostic.severity = Severity::Warning;
+                self.diagnostics.push(diagnostic);
+            }
+        }
+    }
+
+    fn warn(&mut self, rule: &'static str, mut diagnostic: Diagnostic) {
+        if self.disabled.contains(rule) {
+            return;
+        }
+        diagnostic.severity = Severity::Warning;
+        diagnostic.rule = Some(rule);
+        self.diagnostics.push(diagnostic);
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    /// 离开局部作用域，报告没有被读过的变量和参数；`_` 开头的名字表示故意不用
+    fn end_scope(&mut self) {
+        let scope = self.scopes.pop().expect("local scope");
+        for local in scope {
+            if local.used || local.name.lexeme.starts_with('_') {
+                continue;
+            }
+            let (rule, message) = match local.kind {
+                LocalKind::Variable => (
+                    UNUSED_VARIABLE,
+                    format!("Local variable '{}' is never used.", local.name.lexeme),
+                ),
+                LocalKind::Parameter => (
+                    UNUSED_PARAMETER,
+                    format!("Parameter '{}' is never used.", local.name.lexeme),
+                ),
+                LocalKind::Other => continue,
+            };
+            self.warn(rule, Diagnostic::at_token(&local.name, &message));
+        }
+    }
+
+    fn declare(&mut self, name: &Token, kind: LocalKind) {
+        if self.scopes.len() > 1 {
+            let (_, enclosing) = self.scopes.split_last().expect("local scope");
+            let outer = enclosing
+                .iter()
+                .rev()
+                .find_map(|scope| scope.iter().find(|local| local.name.lexeme == name.lexeme));
+            if let Some(outer) = outer {
+                let message = format!(
+                    "'{}' shadows a declaration in an enclosing scope.",
+                    name.lexeme
+                );
+                let note = format!("Outer '{}' is declared here.", outer.name.lexeme);
+                let diagnostic = Diagnostic::at_token(name, &message).with_note(&outer.name, &note);
+                self.warn(SHADOWED_VARIABLE, diagnostic);
+            }
+        }
+        self.scopes.last_mut().expect("scope").push(Local {
+            name: name.clone(),
+            kind,
+            used: false,
+        });
+    }
+
+    /// 读取了 `name`，标记最近的同名声明
+    fn use_name(&mut self, name: &Token) {
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(local) = scope
+                .iter_mut()
+                .rev()
+                .find(|l| l.name.lexeme == name.lexeme)
+            {
+                local.used = true;
+                return;
+            }
+        }
+    }
+
+    /// 一串语句，`return`、`break`、`continue` 后面还有语句时报不可达，位置是第一条执行不到的语句
+    fn statements(&mut self, statements: &[Stmt]) {
+        let exit = statements
+            .iter()
+            .position(|stmt| matches!(stmt, Stmt::Return(_) | Stmt::Break(_) | Stmt::Continue(_)));
+        if let Some(exit) = exit.filter(|&exit| exit + 1 < statements.len()) {
+            let keyword = match &statements[exit] {
+                Stmt::Return(stmt) => &stmt.keyword,
+                Stmt::Break(stmt) => &stmt.keyword,
+                Stmt::Continue(stmt) => &stmt.keyword,
+                _ => unreachable!(),
+            };
+            let message = format!("Code after '{}' is never executed.", keyword.lexeme);
+            let dead = self.next_statement(keyword).unwrap_or(keyword).clone();
+            self.warn(UNREACHABLE_CODE, Diagnostic::at_token(&dead, &message));
+        }
+        for stmt in statements {
+            self.stmt(stmt);
+        }
+    }
+
+    /// `keyword` 开头的语句结束之后的第一个 token，也就是下一条语句的开头
+    fn next_statement(&self, keyword: &Token) -> Option<&Token> {
+        let start = self
+            .tokens
+            .iter()
+            .position(|token| token.offset == keyword.offset)?;
+        // 返回值里的匿名函数也有分号，只认括号外面的
+        let mut depth = 0;
+        for (i, token) in self.tokens.iter().enumerate().skip(start) {
+            match token.token_type {
+                TokenType::LEFT_PAREN | TokenType::LEFT_BRACE | TokenType::LEFT_BRACKET => {
+                    depth += 1
+                }
+                TokenType::RIGHT_PAREN | TokenType::RIGHT_BRACE | TokenType::RIGHT_BRACKET => {
+                    depth -= 1
+                }
+                TokenType::SEMICOLON if depth == 0 => return self.tokens.get(i + 1),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Block(block) => {
+                if block.statements.is_empty() {
+                    self.warn(
+                        EMPTY_BLOCK,
+                        Diagnostic::at_token(&block.brace, "Empty block."),
+                    );
+                }
+                self.begin_scope();
+                self.statements(&block.statements);
+                self.end_scope();
+            }
+            Stmt::Class(class) => {
+                self.declare(&class.name, LocalKind::Other);
+                if let Some(superclass) = &class.superclass {
+                    self.use_name(&superclass.name);
+                }
+                for method in &class.methods {
+                    self.function(method);
+                }
+            }
+            Stmt::Break(_) | Stmt::Continue(_) => {}
+            Stmt::Const(constant) => {
+                self.expr(&constant.initializer);
+                self.declare(&constant.name, LocalKind::Variable);
+            }
+            Stmt::Export(export) => self.stmt(&export.declaration),
+            Stmt::Expression(expression) => self.expr(&expression.expr),
+            Stmt::Function(function) => {
+                self.declare(&function.name, LocalKind::Other);
+                self.function(function);
+            }
+            Stmt::If(stmt) => {
+                self.expr(&stmt.condition);
+                self.stmt(&stmt.then_branch);
+                if let Some(else_branch) = &stmt.else_branch {
+                    self.stmt(else_branch);
+                }
+            }
+            Stmt::Import(import) => match &import.imported {
+                Imported::Module(name) => self.declare(name, LocalKind::Other),
+                Imported::Names(names) => {
+                    for name in names {
+                        self.declare(name, LocalKind::Other);
+                    }
+                }
+            },
+            Stmt::Print(print) => self.expr(&print.expr),
+            Stmt::Return(stmt) => {
+                if let Some(value) = &stmt.value {
+                    self.expr(value);
+                }
+            }
+            Stmt::Var(var) => {
+                if let Some(initializer) = &var.initializer {
+                    self.expr(initializer);
+                }
+                self.declare(&var.name, LocalKind::Variable);
+            }
+            Stmt::While(stmt) => {
+                self.expr(&stmt.condition);
+                self.stmt(&stmt.body);
+            }
+            Stmt::For(stmt) => {
+                self.begin_scope();
+                if let Some(initializer) = &stmt.initializer {
+                    self.stmt(initializer);
+                }
+                for expr in stmt.condition.iter().chain(&stmt.increment) {
+                    self.expr(expr);
+                }
+                self.stmt(&stmt.body);
+                self.end_scope();
+            }
+            Stmt::ForIn(stmt) => {
+                self.expr(&stmt.iterable);
+                self.begin_scope();
+                self.declare(&stmt.name, LocalKind::Other);
+                self.stmt(&stmt.body);
+                self.end_scope();
+            }
+        }
+    }
+
+    /// 参数和函数体在同一个作用域里，默认值能看到前面的参数
+    fn function(&mut self, function: &Function) {
+        self.begin_scope();
+        for param in &function.params {
+            if let Some(default) = &param.default {
+                self.expr(default);
+            }
+            self.declare(&param.name, LocalKind::Parameter);
+        }
+        if let Some(rest) = &function.rest {
+            self.declare(rest, LocalKind::Parameter);
+        }
+        self.statements(&function.body);
+        self.end_scope();
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Assignment(assignment) => {
+                self.expr(&assignment.value);
+                self.self_assignment(assignment);
+            }
+            Expr::Binary(binary) => {
+                self.expr(&binary.left);
+                self.expr(&binary.right);
+                self.mixed_type_comparison(binary);
+            }
+            Expr::Call(call) => {
+                self.expr(&call.callee);
+                for argument in &call.arguments {
+                    self.expr(argument);
+                }
+                for (_, argument) in &call.named {
+                    self.expr(argument);
+                }
+            }
+            Expr::Comma(comma) => {
+                self.expr(&comma.left);
+                self.expr(&comma.right);
+            }
+            Expr::Compound(compound) => {
+                self.expr(&compound.target);
+                self.expr(&compound.value);
+            }
+            Expr::Conditional(conditional) => {
+                self.expr(&conditional.condition);
+                self.expr(&conditional.then_branch);
+                self.expr(&conditional.else_branch);
+            }
+            Expr::Get(get) => self.expr(&get.object),
+            Expr::Set(set) => {
+                self.expr(&set.object);
+                self.expr(&set.value);
+                self.self_set(set);
+            }
+            Expr::Grouping(grouping) => self.expr(&grouping.expr),
+            Expr::Lambda(lambda) => self.function(&lambda.function),
+            Expr::Optional(optional) => self.expr(&optional.expr),
+            Expr::Index(index) => {
+                self.expr(&index.object);
+                self.expr(&index.index);
+            }
+            Expr::IndexSet(index_set) => {
+                self.expr(&index_set.object);
+                self.expr(&index_set.index);
+                self.expr(&index_set.value);
+            }
+            Expr::List(list) => {
+                for element in &list.elements {
+                    self.expr(element);
+                }
+            }
+            Expr::Map(map) => {
+                for (key, value) in &map.entries {
+                    self.expr(key);
+                    self.expr(value);
+                }
+            }
+            Expr::Logical(logical) => {
+                self.expr(&logical.left);
+                self.expr(&logical.right);
+            }
+            Expr::Unary(unary) => self.expr(&unary.right),
+            Expr::Update(update) => self.expr(&update.target),
+            Expr::Variable(variable) => self.use_name(&variable.name),
+            Expr::Literal(_) | Expr::Super(_) | Expr::This(_) => {}
+        }
+    }
+
+    /// `a = a;`
+    fn self_assignment(&mut self, assignment: &Assignment) {
+        if let Expr::Variable(value) = ungroup(&assignment.value)
+            && value.name.lexeme == assignment.name.lexeme
+        {
+            let message = format!("'{}' is assigned to itself.", assignment.name.lexeme);
+            self.warn(
+                SELF_ASSIGNMENT,
+                Diagnostic::at_token(&assignment.name, &message),
+            );
+        }
+    }
+
+    /// `this.x = this.x;`、`p.x = p.x;`
+    fn self_set(&mut self, set: &Set) {
+        let Expr::Get(get) = ungroup(&set.value) else {
+            return;
+        };
+        let same_object = match (ungroup(&set.object), ungroup(&get.object)) {
+            (Expr::This(_), Expr::This(_)) => true,
+            (Expr::Variable(a), Expr::Variable(b)) => a.name.lexeme == b.name.lexeme,
+            _ => false,
+        };
+        if same_object && !get.optional && get.name.lexeme == set.name.lexeme {
+            let message = format!("Field '{}' is assigned to itself.", set.name.lexeme);
+            self.warn(SELF_ASSIGNMENT, Diagnostic::at_token(&set.name, &message));
+        }
+    }
+
+    /// `"1" == 1` 这样的比较结果总是固定的；和 nil 比较是常见写法，不算
+    fn mixed_type_comparison(&mut self, binary: &Binary) {
+        let op = binary.operator.token_type;
+        if op != TokenType::EQUAL_EQUAL && op != TokenType::BANG_EQUAL {
+            return;
+        }
+        if !is_literal(&binary.left) && !is_literal(&binary.right) {
+            return;
+        }
+        let (Some(left), Some(right)) = (static_type(&binary.left), static_type(&binary.right))
+        else {
+            return;
+        };
+        if left == right || left == "nil" || right == "nil" {
+            return;
+        }
+        let always = if op == TokenType::EQUAL_EQUAL {
+            "false"
+        } else {
+            "true"
+        };
+        let message = format!("Comparing {} with {} is always {}.", left, right, always);
+        self.warn(
+            MIXED_TYPE_COMPARISON,
+            Diagnostic::at_token(&binary.operator, &message),
+        );
+    }
+}
+
+fn ungroup(expr: &Expr) -> &Expr {
+    match expr {
+        Expr::Grouping(grouping) => ungroup(&grouping.expr),
+        _ => expr,
+    }
+}
+
+fn is_literal(expr: &Expr) -> bool {
+    match expr {
+        Expr::Grouping(grouping) => is_literal(&grouping.expr),
+        Expr::Literal(_) => true,
+        _ => false,
+    }
+}
+
+/// 不用运行就能确定的值类型
+pub(crate) fn static_type(expr: &Expr) -> Option<&'static str> {
+    match expr {
+        Expr::Literal(literal) => Some(match literal.value {
+            LiteralType::Number(_) => "number",
+            LiteralType::String(_) => "string",
+            LiteralType::Bool(_) => "bool",
+            LiteralType::Nil => "nil",
+        }),
+        Expr::Grouping(grouping) => static_type(&grouping.expr),
+        Expr::Unary(unary) => match unary.operator.token_type {
+            TokenType::BANG => Some("bool"),
+            _ => Some("number"),
+        },
+        Expr::Binary(binary) => match binary.operator.token_type {
+            TokenType::PLUS => {
+                let left = static_type(&binary.left)?;
+                (left == static_type(&binary.right)? && left != "bool" && left != "nil")
+                    .then_some(left)
+            }
+            TokenType::EQUAL_EQUAL
+            | TokenType::BANG_EQUAL
+            | TokenType::GREATER
+            | TokenType::GREATER_EQUAL
+            | TokenType::LESS
+            | TokenType::LESS_EQUAL
+            | TokenType::IS => Some("bool"),
+            _ => Some("number"),
+        },
+        Expr::Assignment(assignment) => static_type(&assignment.value),
+        Expr::Set(set) => static_type(&set.value),
+        Expr::IndexSet(index_set) => static_type(&index_set.value),
+        Expr::List(_) => Some("list"),
+        Expr::Map(_) => Some("map"),
+        Expr::Lambda(_) => Some("function"),
+        Expr::Comma(comma) => static_type(&comma.right),
+        // `+=` 也可以拼接字符串
+        Expr::Compound(compound) => match compound.operator.token_type {
+            TokenType::PLUS_EQUAL => None,
+            _ => Some("number"),
+        },
+        Expr::Update(_) => Some("number"),
+        Expr::Conditional(conditional) => {
+            let then_type = static_type(&conditional.then_branch)?;
+            (then_type == static_type(&conditional.else_branch)?).then_some(then_type)
+        }
+        // and/or 的结果是某一边的操作数
+        Expr::Logical(logical) => {
+            let left = static_type(&logical.left)?;
+            (left == static_type(&logical.right)?).then_some(left)
+        }
+        Expr::Call(_)
+        | Expr::Get(_)
+        | Expr::Index(_)
+        | Expr::Optional(_)
+        | Expr::Super(_)
+        | Expr::This(_)
+        | Expr::Variable(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn warnings(source: &str, config: &LintConfig) -> Vec<String> {
+        lint_source(source, config)
+            .iter()
+            .map(|d| d.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_mixed_type_comparison() {
+        let config = LintConfig::default();
+        assert_eq!(
+            warnings("\"1\" == 1;", &config),
+            [
+                "[line 1] Warning at '==': Comparing string with number is always false. (mixed-type-comparison)"
+            ]
+        );
+        assert_eq!(
+            warnings("(1 + 2) != (\"a\");", &config),
+            [
+                "[line 1] Warning at '!=': Comparing number with string is always true. (mixed-type-comparison)"
+            ]
+        );
+        assert!(warnings("1 == 2;", &config).is_empty());
+        assert!(warnings("1 == nil;", &config).is_empty());
+        // 两边都不是字面量时不报
+        assert!(warnings("-1 == !true;", &config).is_empty());
+    }
+
+    #[test]
+    fn test_disable() {
+        let config = LintConfig::parse("# comment\ndisable = mixed-type-comparison\n").unwrap();
+        assert!(warnings("\"1\" == 1;", &config).is_empty());
+
+        let source = "// lox-lint: disable=mixed-type-comparison\n\"1\" == 1;";
+        assert!(warnings(source, &LintConfig::default()).is_empty());
+
+        assert_eq!(
+            warnings("/* lox-lint: disable=nope */ 1;", &LintConfig::default()),
+            ["[line 1] Warning: Unknown lint rule 'nope'."]
+        );
+        assert!(LintConfig::parse("disable = nope").is_err());
+        assert!(LintConfig::parse("enable = mixed-type-comparison").is_err());
+    }
+
+    #[test]
+    fn test_unused() {
+        let config = LintConfig::default();
+        assert_eq!(
+            warnings("fun f(a, b) { var c = 1; const d = 2; return b; }", &config),
+            [
+                "[line 1] Warning at 'a': Parameter 'a' is never used. (unused-parameter)",
+                "[line 1] Warning at 'c': Local variable 'c' is never used. (unused-variable)",
+                "[line 1] Warning at 'd': Local variable 'd' is never used. (unused-variable)",
+            ]
+        );
+        // 全局变量可能被别的模块用到，`_` 开头、只赋值不读的情况
+        assert!(warnings("var a = 1;", &config).is_empty());
+        assert!(warnings("fun f(_a, ...rest) { return rest; }", &config).is_empty());
+        assert_eq!(
+            warnings("{ var a; a = 1; }", &config),
+            ["[line 1] Warning at 'a': Local variable 'a' is never used. (unused-variable)"]
+        );
+        // 闭包里读取外层变量也算使用
+        assert!(warnings("fun f(x) { return fun() { return x; }; }", &config).is_empty());
+        assert!(warnings("{ var i = 0; i += 1; }", &config).is_empty());
+
+        let source = "// lox-lint: disable=unused-variable, unused-parameter\n\
+                      fun f(a) { var b; }";
+        assert!(warnings(source, &config).is_empty());
+    }
+
+    #[test]
+    fn test_unreachable_code() {
+        let config = LintConfig::default();
+        assert_eq!(
+            warnings("fun f() { return 1; print 2; print 3; }", &config),
+            [
+                "[line 1] Warning at 'print': Code after 'return' is never executed. (unreachable-code)"
+            ]
+        );
+        assert_eq!(
+            warnings("while (true) { break;\n  x = 1; }", &config),
+            ["[line 2] Warning at 'x': Code after 'break' is never executed. (unreachable-code)"]
+        );
+        assert_eq!(
+            warnings("fun f() { return fun() { return; }; print 1; }", &config),
+            [
+                "[line 1] Warning at 'print': Code after 'return' is never executed. (unreachable-code)"
+            ]
+        );
+        assert!(warnings("fun f(x) { if (x) return 1; return 2; }", &config).is_empty());
+
+        let source = "// lox-lint: disable=unreachable-code\nfun f() { return; f(); }";
+        assert!(warnings(source, &config).is_empty());
+    }
+
+    #[test]
+    fn test_shadowed_variable() {
+        let config = LintConfig::default();
+        let diagnostics = lint_source("var a = 1;\n{ var a = 2; print a; }", &config);
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "[line 2] Warning at 'a': 'a' shadows a declaration in an enclosing scope. \
+             (shadowed-variable)\n[line 1] Note: Outer 'a' is declared here."
+        );
+        assert_eq!(
+            warnings("{ var x = 1; fun f(x) { return x; } f(x); }", &config),
+            [
+                "[line 1] Warning at 'x': 'x' shadows a declaration in an enclosing scope. \
+                 (shadowed-variable)\n[line 1] Note: Outer 'x' is declared here."
+            ]
+        );
+        // 同一作用域重新声明不是遮蔽
+        assert!(warnings("var a = 1; var a = 2;", &config).is_empty());
+
+        let source = "// lox-lint: disable=shadowed-variable\nvar a;\n{ var a; print a; }";
+        assert!(warnings(source, &config).is_empty());
+    }
+
+    #[test]
+    fn test_self_assignment() {
+        let config = LintConfig::default();
+        assert_eq!(
+            warnings("var a = 1; a = (a);", &config),
+            ["[line 1] Warning at 'a': 'a' is assigned to itself. (self-assignment)"]
+        );
+        assert_eq!(
+            warnings("class P { init(x) { this.x = this.x; } }", &config),
+            [
+                "[line 1] Warning at 'x': Parameter 'x' is never used. (unused-parameter)",
+                "[line 1] Warning at 'x': Field 'x' is assigned to itself. (self-assignment)",
+            ]
+        );
+        assert!(warnings("var a; var b; a = b; a.x = b.x; a.x = a.y;", &config).is_empty());
+
+        let source = "// lox-lint: disable=self-assignment\nvar a; a = a;";
+        assert!(warnings(source, &config).is_empty());
+    }
+
+    #[test]
+    fn test_empty_block() {
+        let config = LintConfig::default();
+        assert_eq!(
+            warnings("if (true) {} else { print 1; }", &config),
+            ["[line 1] Warning at '{': Empty block. (empty-block)"]
+        );
+        // 空函数体不算
+        assert!(warnings("fun f() {}", &config).is_empty());
+
+        let config = LintConfig::parse("disable = empty-block").unwrap();
+        assert!(warnings("while (false) {}", &config).is_empty());
+        let source = "/* lox-lint: disable=empty-block */ {}";
+        assert!(warnings(source, &LintConfig::default()).is_empty());
+    }
+
+    #[test]
+    fn test_syntax_error() {
+        let diagnostics = lint_source("1 +", &LintConfig::default());
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+    }
+}
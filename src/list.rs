@@ -0,0 +1,126 @@
+// 列表的下标和内置方法
+//
+// 下标可以是负数，-1 是最后一个元素。方法通过属性访问取得，已经绑定到列表上：
+//   xs.push(v)              追加到末尾
+//   xs.pop()                删除并返回最后一个元素
+//   xs.len()                元素个数
+//   xs.insert(i, v)         插入到下标 i 之前，i 可以等于长度
+//   xs.remove(i)            删除并返回下标 i 的元素
+//   xs.slice(start, end)    [start, end) 之间的元素组成的新列表，超出范围的部分被截掉
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::native::{NativeFn, NativeFunction, arg};
+use crate::value::Value;
+
+type Elements = Rc<RefCell<Vec<Value>>>;
+
+/// 把可能为负的下标换成 `0..len` 里的位置，越界时报错
+pub(crate) fn position(index: &Value, len: usize) -> Result<usize, String> {
+    let Value::Number(n) = index else {
+        return Err(format!(
+            "List index must be a number but got {}.",
+            index.type_name()
+        ));
+    };
+    if n.fract() != 0.0 {
+        return Err("List index must be an integer.".to_string());
+    }
+    let position = if *n < 0.0 { *n + len as f64 } else { *n };
+    if position < 0.0 || position >= len as f64 {
+        return Err(format!(
+            "Index {} is out of bounds for list of length {}.",
+            n, len
+        ));
+    }
+    Ok(position as usize)
+}
+
+/// 列表上名为 `name` 的方法，没有时返回 `None`
+pub(crate) fn method(list: &Elements, name: &str) -> Option<Value> {
+    let list = list.clone();
+    let (arity, function): (usize, Box<NativeFn>) = match name {
+        "push" => (
+            1,
+            Box::new(move |args| {
+                list.borrow_mut().push(args[0].clone());
+                Ok(Value::Nil)
+            }),
+        ),
+        "pop" => (
+            0,
+            Box::new(move |_| {
+                let value = list.borrow_mut().pop();
+                value.ok_or_else(|| "Can't pop from an empty list.".to_string())
+            }),
+        ),
+        "len" => (
+            0,
+            Box::new(move |_| Ok((list.borrow().len() as f64).into())),
+        ),
+        "insert" => (
+            2,
+            Box::new(move |args| {
+                let len = list.borrow().len();
+                // 插入位置可以等于长度，相当于 push
+                let index = match &args[0] {
+                    Value::Number(n) if *n == len as f64 => len,
+                    index => position(index, len)?,
+                };
+                list.borrow_mut().insert(index, args[1].clone());
+                Ok(Value::Nil)
+            }),
+        ),
+        "remove" => (
+            1,
+            Box::new(move |args| {
+                let len = list.borrow().len();
+                let index = position(&args[0], len)?;
+                Ok(list.borrow_mut().remove(index))
+            }),
+        ),
+        "slice" => (
+            2,
+            Box::new(move |args| {
+                let values = list.borrow();
+                let start = clamp(arg(args, 0)?, values.len());
+                let end = clamp(arg(args, 1)?, values.len()).max(start);
+                Ok(Value::list(values[start..end].to_vec()))
+            }),
+        ),
+        _ => return None,
+    };
+    Some(Value::Native(Rc::new(NativeFunction {
+        name: name.to_string(),
+        arity,
+        function,
+    })))
+}
+
+/// 切片的边界：负数从末尾算，超出范围的截到两端
+fn clamp(index: i64, len: usize) -> usize {
+    let len = len as i64;
+    let index = if index < 0 { index + len } else { index };
+    index.clamp(0, len) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_position() {
+        assert_eq!(position(&Value::Number(0.0), 3), Ok(0));
+        assert_eq!(position(&Value::Number(-1.0), 3), Ok(2));
+        assert_eq!(
+            position(&Value::Number(3.0), 3),
+            Err("Index 3 is out of bounds for list of length 3.".to_string())
+        );
+        assert!(position(&Value::Number(-4.0), 3).is_err());
+        assert!(position(&Value::Number(0.5), 3).is_err());
+        assert!(position(&"0".into(), 3).is_err());
+        assert_eq!(clamp(-10, 3), 0);
+        assert_eq!(clamp(10, 3), 3);
+        assert_eq!(clamp(-1, 3), 2);
+    }
+}
@@ -0,0 +1,645 @@
+// 语言服务器 (LSP)，通过 stdin/stdout 收发 JSON-RPC 消息
+// 文档按全量同步，每次变化都重新扫描、解析、lint，把结果作为 diagnostics 推给编辑器。
+// 跳转定义、查找引用、文档符号和悬停都用 resolver 记下的声明和引用，
+// 和运行时一样按词法作用域把每个名字对应到它的声明。
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use serde_json::{Value, json};
+
+use crate::diagnostic::Severity;
+use crate::lint::{LintConfig, lint_source};
+use crate::parser::Parse;
+use crate::resolver::{Resolver, Symbol, SymbolKind};
+use crate::scanner::Scanner;
+use crate::token::{Token, TokenType, TriviaKind};
+
+/// 语义高亮的 token 类型，顺序就是编号
+const TOKEN_TYPES: [&str; 6] = [
+    "keyword", "string", "number", "operator", "comment", "variable",
+];
+
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// 在 stdin/stdout 上运行语言服务器，直到收到 `exit`
+pub fn run_lsp() {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    if let Err(e) = serve(stdin.lock(), stdout.lock()) {
+        eprintln!("lsp: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn serve(mut reader: impl BufRead, mut writer: impl Write) -> io::Result<()> {
+    let mut server = Server::default();
+    while let Some(message) = read_message(&mut reader)? {
+        for reply in server.handle(&message) {
+            write_message(&mut writer, &reply)?;
+        }
+        if server.exit {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// 读一条 `Content-Length` 分帧的消息，输入结束时返回 `None`
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let Some(length) = length else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "missing Content-Length",
+        ));
+    };
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(io::Error::from)
+}
+
+fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+#[derive(Default)]
+struct Server {
+    /// 打开的文档，uri -> 全文
+    documents: HashMap<String, String>,
+    exit: bool,
+}
+
+impl Server {
+    /// 处理一条消息，返回要发给客户端的响应和通知
+    fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "documentSymbolProvider": true,
+                    "semanticTokensProvider": {
+                        "legend": { "tokenTypes": TOKEN_TYPES, "tokenModifiers": [] },
+                        "full": true,
+                    },
+                },
+                "serverInfo": { "name": "lox-r" },
+            }),
+            "shutdown" => Value::Null,
+            "exit" => {
+                self.exit = true;
+                return Vec::new();
+            }
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents.insert(uri.to_string(), text.to_string());
+                return vec![self.diagnostics(uri)];
+            }
+            "textDocument/didChange" => {
+                // 全量同步，最后一次变化就是全文
+                if let Some(change) = params["contentChanges"].as_array().and_then(|c| c.last()) {
+                    let text = change["text"].as_str().unwrap_or_default();
+                    self.documents.insert(uri.to_string(), text.to_string());
+                }
+                return vec![self.diagnostics(uri)];
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                return vec![publish_diagnostics(uri, Vec::new())];
+            }
+            "textDocument/hover" => self.hover(uri, &params["position"]),
+            "textDocument/definition" => self.definition(uri, &params["position"]),
+            "textDocument/references" => {
+                let declaration = params["context"]["includeDeclaration"].as_bool();
+                self.references(uri, &params["position"], declaration.unwrap_or(false))
+            }
+            "textDocument/documentSymbol" => self.document_symbols(uri),
+            "textDocument/semanticTokens/full" => self.semantic_tokens(uri),
+            _ => {
+                // 不认识的通知直接忽略
+                if message.get("id").is_none() {
+                    return Vec::new();
+                }
+                return vec![json!({
+                    "jsonrpc": "2.0",
+                    "id": message["id"],
+                    "error": {
+                        "code": METHOD_NOT_FOUND,
+                        "message": format!("method not found: {}", method),
+                    },
+                })];
+            }
+        };
+        vec![json!({ "jsonrpc": "2.0", "id": message["id"], "result": result })]
+    }
+
+    fn document(&self, uri: &str) -> &str {
+        self.documents
+            .get(uri)
+            .map(String::as_str)
+            .unwrap_or_default()
+    }
+
+    fn diagnostics(&self, uri: &str) -> Value {
+        let text = self.document(uri);
+        let index = LineIndex::new(text);
+        // 语法错误和 lint 警告一起报，lint 配置只认文件里的注释指令
+        let diagnostics = lint_source(text, &LintConfig::default())
+            .iter()
+            .map(|diagnostic| {
+                let severity = match diagnostic.severity {
+                    Severity::Error => 1,
+                    Severity::Warning => 2,
+                };
+                let mut value = json!({
+                    "range": index.range(diagnostic.span.start, diagnostic.span.end),
+                    "severity": severity,
+                    "code": diagnostic.rule,
+                    "source": "lox",
+                    "message": diagnostic.message,
+                });
+                if let Some(note) = &diagnostic.note {
+                    value["relatedInformation"] = json!([{
+                        "location": {
+                            "uri": uri,
+                            "range": index.range(note.span.start, note.span.end),
+                        },
+                        "message": note.message,
+                    }]);
+                }
+                value
+            })
+            .collect();
+        publish_diagnostics(uri, diagnostics)
+    }
+
+    fn hover(&self, uri: &str, position: &Value) -> Value {
+        let text = self.document(uri);
+        let index = LineIndex::new(text);
+        let offset = index.offset(position);
+        let token = scan(text).into_iter().find(|t| {
+            t.token_type != TokenType::EOF
+                && (t.offset..t.offset + t.lexeme.len()).contains(&offset)
+        });
+        let Some(token) = token else {
+            return Value::Null;
+        };
+        let symbols = symbols(text);
+        let kind = match token.token_type {
+            TokenType::IDENTIFIER => symbol_at(&symbols, offset).map(|symbol| symbol.kind),
+            _ => None,
+        };
+        json!({
+            "contents": { "kind": "markdown", "value": describe(&token, kind) },
+            "range": index.range(token.offset, token.offset + token.lexeme.len()),
+        })
+    }
+
+    fn definition(&self, uri: &str, position: &Value) -> Value {
+        let text = self.document(uri);
+        let index = LineIndex::new(text);
+        let symbols = symbols(text);
+        match symbol_at(&symbols, index.offset(position)) {
+            Some(symbol) => location(uri, &index, &symbol.name),
+            None => Value::Null,
+        }
+    }
+
+    fn references(&self, uri: &str, position: &Value, include_declaration: bool) -> Value {
+        let text = self.document(uri);
+        let index = LineIndex::new(text);
+        let symbols = symbols(text);
+        let Some(symbol) = symbol_at(&symbols, index.offset(position)) else {
+            return json!([]);
+        };
+        let mut tokens: Vec<&Token> = symbol.references.iter().collect();
+        if include_declaration {
+            tokens.push(&symbol.name);
+        }
+        tokens.sort_by_key(|token| token.offset);
+        tokens
+            .into_iter()
+            .map(|token| location(uri, &index, token))
+            .collect()
+    }
+
+    /// 文档里的类、函数、方法、变量和常量，参数不列出
+    fn document_symbols(&self, uri: &str) -> Value {
+        let text = self.document(uri);
+        let index = LineIndex::new(text);
+        symbols(text)
+            .iter()
+            .filter_map(|symbol| {
+                // LSP 的 SymbolKind 编号
+                let kind = match symbol.kind {
+                    SymbolKind::Class => 5,
+                    SymbolKind::Method => 6,
+                    SymbolKind::Function => 12,
+                    SymbolKind::Variable => 13,
+                    SymbolKind::Constant => 14,
+                    SymbolKind::Parameter => return None,
+                };
+                Some(json!({
+                    "name": symbol.name.lexeme,
+                    "kind": kind,
+                    "location": location(uri, &index, &symbol.name),
+                }))
+            })
+            .collect()
+    }
+
+    fn semantic_tokens(&self, uri: &str) -> Value {
+        let text = self.document(uri);
+        let index = LineIndex::new(text);
+
+        // 先按源码顺序收集 (起始偏移, 结束偏移, 类型)
+        let mut spans = Vec::new();
+        let mut offset = 0;
+        for token in scan(text) {
+            for trivia in token.leading.iter() {
+                if matches!(
+                    trivia.kind,
+                    TriviaKind::LineComment | TriviaKind::BlockComment
+                ) {
+                    spans.push((offset, offset + trivia.text.len(), 4));
+                }
+                offset += trivia.text.len();
+            }
+            if let Some(kind) = semantic_type(token.token_type) {
+                spans.push((token.offset, token.offset + token.lexeme.len(), kind));
+            }
+            offset = token.offset + token.lexeme.len();
+            for trivia in token.trailing.iter() {
+                if matches!(
+                    trivia.kind,
+                    TriviaKind::LineComment | TriviaKind::BlockComment
+                ) {
+                    spans.push((offset, offset + trivia.text.len(), 4));
+                }
+                offset += trivia.text.len();
+            }
+        }
+
+        // 编码成相对位置，跨行的字符串和注释按行拆开
+        let mut data = Vec::new();
+        let (mut prev_line, mut prev_start) = (0, 0);
+        for (start, end, kind) in spans {
+            let mut piece_start = start;
+            for (i, line) in text[start..end].split('\n').enumerate() {
+                let line = line.trim_end_matches('\r');
+                if i > 0 {
+                    piece_start = index.line_starts[index.line_of(piece_start) + 1];
+                }
+                let (line_no, character) = index.position(piece_start);
+                let length = line.encode_utf16().count();
+                if length > 0 {
+                    let delta_start = if line_no == prev_line {
+                        character - prev_start
+                    } else {
+                        character
+                    };
+                    data.extend([line_no - prev_line, delta_start, length, kind, 0]);
+                    (prev_line, prev_start) = (line_no, character);
+                }
+            }
+        }
+        json!({ "data": data })
+    }
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Value>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    })
+}
+
+/// 带 trivia 扫描，用来定位 token 和注释
+fn scan(text: &str) -> Vec<Token> {
+    Scanner::with_trivia(text.to_string()).scan_tokens()
+}
+
+/// 文档里的所有声明和引用，有语法错误时用解析器恢复之后的部分
+fn symbols(text: &str) -> Vec<Symbol> {
+    let mut scanner = Scanner::new(text.to_string());
+    scanner.scan_tokens();
+    let statements = Parse::new(scanner).parse();
+    let mut resolver = Resolver::new(&HashMap::new());
+    resolver.resolve(&statements);
+    resolver.symbols
+}
+
+/// 声明或引用位置包含 `offset` 的符号
+fn symbol_at(symbols: &[Symbol], offset: usize) -> Option<&Symbol> {
+    symbols.iter().find(|symbol| {
+        std::iter::once(&symbol.name)
+            .chain(&symbol.references)
+            .any(|token| (token.offset..token.offset + token.lexeme.len()).contains(&offset))
+    })
+}
+
+fn location(uri: &str, index: &LineIndex, token: &Token) -> Value {
+    json!({
+        "uri": uri,
+        "range": index.range(token.offset, token.offset + token.lexeme.len()),
+    })
+}
+
+fn semantic_type(token_type: TokenType) -> Option<usize> {
+    use TokenType::*;
+    match token_type {
+        AND | BREAK | CLASS | CONST | CONTINUE | ELSE | EXPORT | FALSE | FUN | FOR | IF | IMPORT
+        | IN | IS | NIL | OR | PRINT | RETURN | SUPER | THIS | TRUE | VAR | WHILE => Some(0),
+        STRING => Some(1),
+        NUMBER => Some(2),
+        MINUS | PLUS | SLASH | STAR | STAR_STAR | PERCENT | TILDE_SLASH | AMPERSAND | PIPE
+        | CARET | TILDE | LESS_LESS | GREATER_GREATER | QUESTION | BANG | BANG_EQUAL | EQUAL
+        | EQUAL_EQUAL | GREATER | GREATER_EQUAL | LESS | LESS_EQUAL | PLUS_EQUAL | MINUS_EQUAL
+        | STAR_EQUAL | SLASH_EQUAL | PERCENT_EQUAL | PLUS_PLUS | MINUS_MINUS | QUESTION_DOT
+        | QUESTION_QUESTION | ARROW | DOT_DOT_DOT => Some(3),
+        IDENTIFIER => Some(5),
+        _ => None,
+    }
+}
+
+/// 悬停时显示的说明，`kind` 是名字声明时的种类
+fn describe(token: &Token, kind: Option<SymbolKind>) -> String {
+    use TokenType::*;
+    if let Some(kind) = kind {
+        return format!("{} `{}`", kind.name(), token.lexeme);
+    }
+    match token.token_type {
+        NUMBER => format!("number `{}`", token.lexeme),
+        STRING => "string".to_string(),
+        TRUE | FALSE => format!("bool `{}`", token.lexeme),
+        NIL => "nil".to_string(),
+        IDENTIFIER => format!("identifier `{}`", token.lexeme),
+        token_type if semantic_type(token_type) == Some(0) => format!("keyword `{}`", token.lexeme),
+        token_type if semantic_type(token_type) == Some(3) => {
+            format!("operator `{}`", token.lexeme)
+        }
+        _ => format!("`{}`", token.lexeme),
+    }
+}
+
+/// 字节偏移和 LSP 位置（行号，UTF-16 列号）之间的转换
+struct LineIndex<'a> {
+    text: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    fn new(text: &'a str) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+        Self { text, line_starts }
+    }
+
+    fn line_of(&self, offset: usize) -> usize {
+        self.line_starts.partition_point(|&start| start <= offset) - 1
+    }
+
+    fn position(&self, offset: usize) -> (usize, usize) {
+        let line = self.line_of(offset);
+        let character = self.text[self.line_starts[line]..offset]
+            .encode_utf16()
+            .count();
+        (line, character)
+    }
+
+    fn range(&self, start: usize, end: usize) -> Value {
+        let (start_line, start_character) = self.position(start);
+        let (end_line, end_character) = self.position(end);
+        json!({
+            "start": { "line": start_line, "character": start_character },
+            "end": { "line": end_line, "character": end_character },
+        })
+    }
+
+    fn offset(&self, position: &Value) -> usize {
+        let line = position["line"].as_u64().unwrap_or(0) as usize;
+        let character = position["character"].as_u64().unwrap_or(0) as usize;
+        let Some(&start) = self.line_starts.get(line) else {
+            return self.text.len();
+        };
+        let mut units = 0;
+        for (i, c) in self.text[start..].char_indices() {
+            if units >= character || c == '\n' {
+                return start + i;
+            }
+            units += c.len_utf16();
+        }
+        self.text.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 进程内的假客户端：依次发送消息，收集服务器的全部输出
+    fn exchange(messages: &[Value]) -> Vec<Value> {
+        let mut input = Vec::new();
+        for message in messages {
+            write_message(&mut input, message).unwrap();
+        }
+        let mut output = Vec::new();
+        serve(io::Cursor::new(input), &mut output).unwrap();
+
+        let mut reader = io::Cursor::new(output);
+        let mut replies = Vec::new();
+        while let Some(reply) = read_message(&mut reader).unwrap() {
+            replies.push(reply);
+        }
+        replies
+    }
+
+    fn open(text: &str) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": "file:///a.lox", "languageId": "lox", "version": 1, "text": text } },
+        })
+    }
+
+    fn request(id: i64, method: &str, params: Value) -> Value {
+        json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+    }
+
+    #[test]
+    fn test_initialize_and_exit() {
+        let replies = exchange(&[
+            request(1, "initialize", json!({})),
+            request(2, "shutdown", Value::Null),
+            json!({ "jsonrpc": "2.0", "method": "exit" }),
+            request(3, "shutdown", Value::Null),
+        ]);
+        assert_eq!(replies.len(), 2);
+        assert_eq!(replies[0]["id"], 1);
+        assert_eq!(replies[0]["result"]["capabilities"]["textDocumentSync"], 1);
+        assert_eq!(replies[1]["result"], Value::Null);
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let change = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didChange",
+            "params": {
+                "textDocument": { "uri": "file:///a.lox", "version": 2 },
+                "contentChanges": [{ "text": "1 + 2;" }],
+            },
+        });
+        let replies = exchange(&[open("// 注释\n(1 + );"), change]);
+        let diagnostics = &replies[0]["params"]["diagnostics"];
+        assert_eq!(diagnostics.as_array().unwrap().len(), 1);
+        assert_eq!(diagnostics[0]["message"], "Expect expression.");
+        assert_eq!(
+            diagnostics[0]["range"],
+            json!({ "start": { "line": 1, "character": 5 }, "end": { "line": 1, "character": 6 } })
+        );
+        assert_eq!(replies[1]["params"]["diagnostics"], json!([]));
+    }
+
+    #[test]
+    fn test_hover() {
+        let position = json!({ "textDocument": { "uri": "file:///a.lox" }, "position": { "line": 0, "character": 5 } });
+        let replies = exchange(&[
+            open("1 + \"abc\""),
+            request(1, "textDocument/hover", position),
+        ]);
+        assert_eq!(replies[1]["result"]["contents"]["value"], "string");
+    }
+
+    /// 光标放在第 `line` 行第 `character` 列的请求参数
+    fn at(line: u32, character: u32) -> Value {
+        json!({
+            "textDocument": { "uri": "file:///a.lox" },
+            "position": { "line": line, "character": character },
+        })
+    }
+
+    const SOURCE: &str =
+        "var a = 1;\nconst K = 2;\nclass C {}\nfun f(p) {\n  return p + a;\n}\nf(a);";
+
+    #[test]
+    fn test_hover_kinds() {
+        let replies = exchange(&[
+            open(SOURCE),
+            request(1, "textDocument/hover", at(0, 4)),
+            request(2, "textDocument/hover", at(1, 6)),
+            request(3, "textDocument/hover", at(2, 6)),
+            request(4, "textDocument/hover", at(6, 0)),
+            request(5, "textDocument/hover", at(4, 9)),
+        ]);
+        let hovers: Vec<_> = replies[1..]
+            .iter()
+            .map(|r| &r["result"]["contents"]["value"])
+            .collect();
+        assert_eq!(
+            hovers,
+            [
+                "variable `a`",
+                "const `K`",
+                "class `C`",
+                "function `f`",
+                "parameter `p`"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_definition() {
+        let mut params = at(4, 13);
+        params["context"] = json!({ "includeDeclaration": true });
+        let replies = exchange(&[
+            open(SOURCE),
+            request(1, "textDocument/definition", at(6, 2)),
+            request(2, "textDocument/references", params),
+            request(3, "textDocument/definition", at(4, 2)),
+        ]);
+        let definition = &replies[1]["result"];
+        assert_eq!(definition["uri"], "file:///a.lox");
+        assert_eq!(
+            definition["range"]["start"],
+            json!({ "line": 0, "character": 4 })
+        );
+
+        let references = replies[2]["result"].as_array().unwrap();
+        let lines: Vec<_> = references
+            .iter()
+            .map(|r| &r["range"]["start"]["line"])
+            .collect();
+        assert_eq!(lines, [0, 4, 6]);
+        // 关键字不是名字
+        assert_eq!(replies[3]["result"], Value::Null);
+    }
+
+    #[test]
+    fn test_document_symbols() {
+        let params = json!({ "textDocument": { "uri": "file:///a.lox" } });
+        let replies = exchange(&[
+            open(SOURCE),
+            request(1, "textDocument/documentSymbol", params),
+        ]);
+        let symbols = replies[1]["result"].as_array().unwrap();
+        let names: Vec<_> = symbols
+            .iter()
+            .map(|s| json!([s["name"], s["kind"]]))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                json!(["a", 13]),
+                json!(["K", 14]),
+                json!(["C", 5]),
+                json!(["f", 12])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_semantic_tokens() {
+        let params = json!({ "textDocument": { "uri": "file:///a.lox" } });
+        let replies = exchange(&[
+            open("// c\n!true == 1"),
+            request(1, "textDocument/semanticTokens/full", params),
+        ]);
+        assert_eq!(
+            replies[1]["result"]["data"],
+            json!([
+                0, 0, 4, 4, 0, 1, 0, 1, 3, 0, 0, 1, 4, 0, 0, 0, 5, 2, 3, 0, 0, 3, 1, 2, 0
+            ])
+        );
+    }
+
+    #[test]
+    fn test_unknown_method() {
+        let replies = exchange(&[request(7, "workspace/symbol", json!({}))]);
+        assert_eq!(replies[0]["error"]["code"], METHOD_NOT_FOUND);
+    }
+}
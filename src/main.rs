@@ -1,22 +1,209 @@
-use std::{
-    env,
-    fs::File,
-    io::{Read, Write},
-    os::windows::process,
-    path::Path,
-    sync::atomic::AtomicBool,
-};
+use std::env;
+use std::path::PathBuf;
 
-use lox_r::{HAD_ERROR, read_code_file, run_prompt};
+use lox_r::{
+    Capabilities, FormatConfig, LintConfig, OptimizerConfig, Severity, format_source, lint_source,
+    read_code_file, run_lsp, run_prompt,
+};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() > 2 {
-        println!("Usage: jlox [script]");
-        std::process::exit(64);
-    } else if args.len() == 2 {
-        read_code_file(&args[1]);
+    if args.len() > 1 && args[1] == "fmt" {
+        fmt(&args[2..]);
+    } else if args.len() > 1 && args[1] == "lint" {
+        lint(&args[2..]);
+    } else if args.len() == 2 && args[1] == "lsp" {
+        run_lsp();
     } else {
-        run_prompt();
+        script(&args[1..]);
+    }
+}
+
+/// 运行脚本或进入 REPL，脚本路径之后的参数都交给脚本的 `args()`
+///
+/// 模块搜索路径先是 `--module-path` 给出的目录，然后是环境变量 `LOX_PATH` 里的目录。
+/// 环境变量 `LOX_OPTIMIZE=0` 时关掉所有优化，golden 测试用它对比优化前后的运行结果。
+fn script(args: &[String]) {
+    let mut capabilities = Capabilities::default();
+    let mut module_path = Vec::new();
+    let mut iter = args.iter();
+    let path = loop {
+        match iter.next().map(String::as_str) {
+            Some("--allow-fs") => capabilities.fs = true,
+            Some("--allow-env") => capabilities.env = true,
+            Some("--allow-stdin") => capabilities.stdin = true,
+            Some("--module-path") => match iter.next() {
+                Some(dir) => module_path.push(PathBuf::from(dir)),
+                None => usage(),
+            },
+            Some(flag) if flag.starts_with('-') => usage(),
+            path => break path,
+        }
+    };
+    if let Some(dirs) = env::var_os("LOX_PATH") {
+        module_path.extend(env::split_paths(&dirs));
+    }
+    let optimizer = match env::var("LOX_OPTIMIZE") {
+        Ok(value) if value == "0" => OptimizerConfig::NONE,
+        _ => OptimizerConfig::default(),
+    };
+    match path {
+        Some(path) => {
+            let args = iter.cloned().collect();
+            let code = read_code_file(path, capabilities, optimizer, args, module_path);
+            std::process::exit(code);
+        }
+        // 脚本在 REPL 里调用了 exit
+        None => {
+            if let Some(code) = run_prompt(capabilities, optimizer, module_path) {
+                std::process::exit(code);
+            }
+        }
+    }
+}
+
+fn usage() -> ! {
+    println!(
+        "Usage: jlox [--allow-fs] [--allow-env] [--allow-stdin] [--module-path DIR]... \
+         [script [args...]]"
+    );
+    println!("       jlox fmt [--check] [--indent N] [--line-length N] <file>...");
+    println!("       jlox lint [--config FILE] <file>...");
+    println!("       jlox lsp");
+    std::process::exit(64);
+}
+
+/// `fmt` 子命令：格式化文件，`--check` 时只检查不写回
+fn fmt(args: &[String]) {
+    let mut config = FormatConfig::default();
+    let mut check = false;
+    let mut files = Vec::new();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--check" => check = true,
+            "--indent" => config.indent_width = number_arg(arg, iter.next()),
+            "--line-length" => config.max_width = number_arg(arg, iter.next()),
+            _ => files.push(arg),
+        }
+    }
+    if files.is_empty() {
+        println!("Usage: jlox fmt [--check] [--indent N] [--line-length N] <file>...");
+        std::process::exit(64);
+    }
+
+    let mut had_error = false;
+    let mut unformatted = false;
+    for file in files {
+        let source = match std::fs::read_to_string(file) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("Error reading file {}: {}", file, e);
+                had_error = true;
+                continue;
+            }
+        };
+        let formatted = match format_source(&source, &config) {
+            Ok(formatted) => formatted,
+            Err(diagnostics) => {
+                for diagnostic in diagnostics {
+                    for line in diagnostic.to_string().lines() {
+                        eprintln!("{}: {}", file, line);
+                    }
+                }
+                had_error = true;
+                continue;
+            }
+        };
+        if formatted == source {
+            continue;
+        }
+        if check {
+            println!("{} is not formatted", file);
+            unformatted = true;
+        } else if let Err(e) = std::fs::write(file, formatted) {
+            eprintln!("Error writing file {}: {}", file, e);
+            had_error = true;
+        }
+    }
+
+    if had_error {
+        std::process::exit(65);
+    }
+    if unformatted {
+        std::process::exit(1);
+    }
+}
+
+/// `lint` 子命令：有警告时退出码为 1，有语法错误时为 65
+///
+/// 没有指定 `--config` 时，读取当前目录下的 `.loxlint`（如果存在）。
+fn lint(args: &[String]) {
+    let mut config_path = None;
+    let mut files = Vec::new();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--config" => config_path = iter.next(),
+            _ => files.push(arg),
+        }
+    }
+    if files.is_empty() {
+        println!("Usage: jlox lint [--config FILE] <file>...");
+        std::process::exit(64);
+    }
+
+    let config_text = match config_path {
+        Some(path) => std::fs::read_to_string(path).unwrap_or_else(|e| {
+            eprintln!("Error reading config {}: {}", path, e);
+            std::process::exit(64);
+        }),
+        None => std::fs::read_to_string(".loxlint").unwrap_or_default(),
+    };
+    let config = LintConfig::parse(&config_text).unwrap_or_else(|e| {
+        eprintln!("Invalid lint config: {}", e);
+        std::process::exit(64);
+    });
+
+    let mut had_error = false;
+    let mut had_warning = false;
+    for file in files {
+        let source = match std::fs::read_to_string(file) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("Error reading file {}: {}", file, e);
+                had_error = true;
+                continue;
+            }
+        };
+        for diagnostic in lint_source(&source, &config) {
+            match diagnostic.severity() {
+                Severity::Error => had_error = true,
+                Severity::Warning => had_warning = true,
+            }
+            // 附注另起一行，也带上文件名
+            for line in diagnostic.to_string().lines() {
+                println!("{}: {}", file, line);
+            }
+        }
+    }
+
+    if had_error {
+        std::process::exit(65);
+    }
+    if had_warning {
+        std::process::exit(1);
+    }
+}
+
+fn number_arg(flag: &str, value: Option<&String>) -> usize {
+    match value.and_then(|v| v.parse().ok()) {
+        Some(n) => n,
+        None => {
+            eprintln!("{} expects a number", flag);
+            std::process::exit(64);
+        }
     }
 }
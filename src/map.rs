@@ -0,0 +1,65 @@
+// 映射的内置方法
+//
+// 和列表一样通过属性访问取得，已经绑定到映射上：
+//   m.keys()                所有键组成的列表，按插入顺序
+//   m.values()              所有值组成的列表，按插入顺序
+//   m.has(k)                是否有键 k
+//   m.remove(k)             删除键 k 并返回它的值，没有这个键时返回 nil
+//   m.len()                 键的个数
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::native::{NativeFn, NativeFunction};
+use crate::value::{LoxMap, Value};
+
+/// 读取不存在的键时的错误，和 `print` 不同，字符串键带引号
+pub(crate) fn missing_key(key: &Value) -> String {
+    match key {
+        Value::String(s) => format!("Undefined key {:?}.", s),
+        Value::Bool(_) | Value::Number(_) => format!("Undefined key {}.", key),
+        key => format!(
+            "Map key must be a string, number or bool but got {}.",
+            key.type_name()
+        ),
+    }
+}
+
+/// 映射上名为 `name` 的方法，没有时返回 `None`
+pub(crate) fn method(map: &Rc<RefCell<LoxMap>>, name: &str) -> Option<Value> {
+    let map = map.clone();
+    let (arity, function): (usize, Box<NativeFn>) = match name {
+        "keys" => (
+            0,
+            Box::new(move |_| {
+                let keys = map.borrow().iter().map(|(key, _)| key).collect();
+                Ok(Value::list(keys))
+            }),
+        ),
+        "values" => (
+            0,
+            Box::new(move |_| {
+                let values = map
+                    .borrow()
+                    .iter()
+                    .map(|(_, value)| value.clone())
+                    .collect();
+                Ok(Value::list(values))
+            }),
+        ),
+        "has" => (
+            1,
+            Box::new(move |args| Ok(map.borrow().contains_key(&args[0]).into())),
+        ),
+        "remove" => (
+            1,
+            Box::new(move |args| Ok(map.borrow_mut().remove(&args[0]).unwrap_or(Value::Nil))),
+        ),
+        "len" => (0, Box::new(move |_| Ok((map.borrow().len() as f64).into()))),
+        _ => return None,
+    };
+    Some(Value::Native(Rc::new(NativeFunction {
+        name: name.to_string(),
+        arity,
+        function,
+    })))
+}
@@ -0,0 +1,115 @@
+// 模块
+//
+//   import "lib/geometry.lox" as geometry;   整个模块绑定到一个名字，用 geometry.area 读取导出的名字
+//   import { area, pi } from "lib/geometry";  把导出的名字复制到当前作用域
+//   export fun area(r) { ... }               export 只能加在模块顶层的 var、const、fun、class 上
+//
+// 每个模块有自己的全局作用域，外面一层是标准库。模块第一次被导入时执行，之后再导入直接用缓存，
+// 所以每个文件只执行一次。路径可以省略 `.lox`，先在导入它的文件所在的目录里找，再依次在搜索路径里找。
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::environment::Environment;
+use crate::stmt::Stmt;
+use crate::value::Value;
+
+/// 执行完的模块
+#[derive(Debug)]
+pub struct LoxModule {
+    /// import 语句里写的路径
+    pub(crate) name: String,
+    /// 模块自己的全局作用域，导出的名字按引用读取，模块里后来的修改也看得到
+    pub(crate) globals: Rc<RefCell<Environment>>,
+    pub(crate) exports: Vec<String>,
+}
+
+impl LoxModule {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 读取导出的名字，没有导出时返回 `None`
+    pub fn get(&self, name: &str) -> Option<Value> {
+        if !self.exports.iter().any(|export| export == name) {
+            return None;
+        }
+        self.globals.borrow().get(name)
+    }
+}
+
+/// 模块的加载状态
+#[derive(Debug, Default)]
+pub(crate) struct Modules {
+    /// 在导入文件的目录之后查找模块的目录
+    pub(crate) search_path: Vec<PathBuf>,
+    /// 按规范化路径缓存执行完的模块
+    cache: HashMap<PathBuf, Rc<LoxModule>>,
+    /// 正在执行的文件和它们的名字，最后一个是当前文件
+    loading: Vec<(PathBuf, String)>,
+}
+
+impl Modules {
+    /// 找到 `name` 对应的文件，返回规范化的路径
+    pub(crate) fn find(&self, name: &str) -> Option<PathBuf> {
+        let mut file = PathBuf::from(name);
+        if file.extension().is_none() {
+            file.set_extension("lox");
+        }
+        // 不在文件里（REPL、嵌入）时相对于当前目录
+        let base = self
+            .loading
+            .last()
+            .and_then(|(path, _)| path.parent())
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        std::iter::once(&base)
+            .chain(&self.search_path)
+            .map(|dir| dir.join(&file))
+            .find(|path| path.is_file())
+            .and_then(|path| path.canonicalize().ok())
+    }
+
+    pub(crate) fn cached(&self, path: &Path) -> Option<Rc<LoxModule>> {
+        self.cache.get(path).cloned()
+    }
+
+    /// `path` 正在执行时再导入它会形成循环，返回循环经过的文件
+    pub(crate) fn cycle(&self, path: &Path) -> Option<String> {
+        let start = self
+            .loading
+            .iter()
+            .position(|(loading, _)| loading == path)?;
+        let mut names: Vec<&str> = self.loading[start..]
+            .iter()
+            .map(|(_, name)| name.as_str())
+            .collect();
+        names.push(&self.loading[start].1);
+        Some(names.join(" -> "))
+    }
+
+    /// 开始执行一个文件，入口脚本也要登记，这样模块才能按它的目录找相对路径
+    pub(crate) fn enter(&mut self, path: PathBuf, name: String) {
+        self.loading.push((path, name));
+    }
+
+    pub(crate) fn leave(&mut self) {
+        self.loading.pop();
+    }
+
+    pub(crate) fn insert(&mut self, path: PathBuf, module: Rc<LoxModule>) {
+        self.cache.insert(path, module);
+    }
+}
+
+/// 模块顶层导出的名字
+pub(crate) fn exports(statements: &[Stmt]) -> Vec<String> {
+    statements
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::Export(export) => Some(export.name().lexeme.clone()),
+            _ => None,
+        })
+        .collect()
+}
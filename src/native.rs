@@ -0,0 +1,406 @@
+// 用 Rust 实现的原生函数和原生类
+// 宿主通过 `Interpreter::register_native` 注册，脚本里和普通函数一样调用。
+// 原生函数返回的错误会变成调用处的运行时错误。
+// 原生类把一个 Rust 类型包装成 Lox 对象（userdata），由宿主声明它的属性和方法。
+use std::any::{Any, TypeId};
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::HashMap;
+use std::fmt::{Debug, Display};
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+use crate::value::Value;
+
+/// 原生函数的返回值，`Err` 里是运行时错误的消息
+pub type NativeResult = Result<Value, String>;
+
+pub(crate) type NativeFn = dyn Fn(&[Value]) -> NativeResult;
+
+pub struct NativeFunction {
+    pub(crate) name: String,
+    pub(crate) arity: usize,
+    pub(crate) function: Box<NativeFn>,
+}
+
+impl NativeFunction {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+}
+
+impl Debug for NativeFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NativeFunction")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .finish()
+    }
+}
+
+type Constructor = dyn Fn(&[Value]) -> Result<Box<dyn Any>, String>;
+type Getter = dyn Fn(&dyn Any) -> NativeResult;
+type Setter = dyn Fn(&mut dyn Any, Value) -> Result<(), String>;
+type Method = dyn Fn(&mut dyn Any, &[Value]) -> NativeResult;
+type Show = dyn Fn(&dyn Any) -> String;
+
+/// 宿主声明的类，实例里包装的是 Rust 值
+///
+/// 用 `NativeClass::builder` 声明，再交给 `Interpreter::register_class`：
+///
+/// ```
+/// use lox_r::{Interpreter, NativeClass, arg};
+///
+/// struct Counter {
+///     count: f64,
+/// }
+///
+/// let mut lox = Interpreter::new();
+/// let class = lox.register_class(
+///     NativeClass::builder::<Counter>("Counter")
+///         .constructor(0, |_| Ok(Counter { count: 0.0 }))
+///         .getter("count", |c| Ok(c.count.into()))
+///         .method("add", 1, |c, args| {
+///             c.count += arg::<f64>(args, 0)?;
+///             Ok(c.count.into())
+///         })
+///         .build(),
+/// );
+/// lox.set_global("shared", class.instance(Counter { count: 10.0 }));
+/// assert_eq!(lox.eval("shared.add(5); shared.count;").unwrap(), 15.0.into());
+/// assert_eq!(lox.eval("Counter().add(1)").unwrap(), 1.0.into());
+/// ```
+pub struct NativeClass {
+    name: String,
+    type_id: TypeId,
+    constructor: Option<(usize, Box<Constructor>)>,
+    getters: HashMap<String, Box<Getter>>,
+    setters: HashMap<String, Box<Setter>>,
+    methods: HashMap<String, (usize, Rc<Method>)>,
+    show: Option<Box<Show>>,
+}
+
+impl NativeClass {
+    /// 开始声明一个包装 `T` 的类
+    pub fn builder<T: Any>(name: &str) -> NativeClassBuilder<T> {
+        NativeClassBuilder {
+            class: NativeClass {
+                name: name.to_string(),
+                type_id: TypeId::of::<T>(),
+                constructor: None,
+                getters: HashMap::new(),
+                setters: HashMap::new(),
+                methods: HashMap::new(),
+                show: None,
+            },
+            marker: PhantomData,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 把 Rust 值包装成这个类的实例
+    ///
+    /// `data` 的类型必须和 `builder` 时声明的一致，否则 panic。
+    pub fn instance<T: Any>(self: &Rc<Self>, data: T) -> Value {
+        assert_eq!(
+            self.type_id,
+            TypeId::of::<T>(),
+            "native class {} wraps a different type",
+            self.name
+        );
+        Value::Userdata(Rc::new(Userdata {
+            class: self.clone(),
+            data: RefCell::new(Box::new(data)),
+        }))
+    }
+
+    /// 脚本里调用类时的参数个数，没有构造函数时为 `None`
+    pub(crate) fn arity(&self) -> Option<usize> {
+        self.constructor.as_ref().map(|(arity, _)| *arity)
+    }
+
+    pub(crate) fn construct(self: &Rc<Self>, args: &[Value]) -> NativeResult {
+        let Some((_, constructor)) = &self.constructor else {
+            return Err(format!("Native class '{}' has no constructor.", self.name));
+        };
+        let data = constructor(args)?;
+        Ok(Value::Userdata(Rc::new(Userdata {
+            class: self.clone(),
+            data: RefCell::new(data),
+        })))
+    }
+}
+
+impl Debug for NativeClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NativeClass")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+/// 按 Rust 类型声明原生类的属性和方法，闭包拿到的直接是 `&T`/`&mut T`
+pub struct NativeClassBuilder<T> {
+    class: NativeClass,
+    marker: PhantomData<T>,
+}
+
+impl<T: Any> NativeClassBuilder<T> {
+    /// 允许脚本用 `Name(args)` 创建实例
+    pub fn constructor(
+        mut self,
+        arity: usize,
+        constructor: impl Fn(&[Value]) -> Result<T, String> + 'static,
+    ) -> Self {
+        let constructor = move |args: &[Value]| -> Result<Box<dyn Any>, String> {
+            Ok(Box::new(constructor(args)?))
+        };
+        self.class.constructor = Some((arity, Box::new(constructor)));
+        self
+    }
+
+    /// 可读属性 `object.name`
+    pub fn getter(mut self, name: &str, getter: impl Fn(&T) -> NativeResult + 'static) -> Self {
+        let getter = move |data: &dyn Any| getter(downcast_ref(data));
+        self.class
+            .getters
+            .insert(name.to_string(), Box::new(getter));
+        self
+    }
+
+    /// 可写属性 `object.name = value`，没有 setter 的属性是只读的
+    pub fn setter(
+        mut self,
+        name: &str,
+        setter: impl Fn(&mut T, Value) -> Result<(), String> + 'static,
+    ) -> Self {
+        let setter = move |data: &mut dyn Any, value| setter(downcast_mut(data), value);
+        self.class
+            .setters
+            .insert(name.to_string(), Box::new(setter));
+        self
+    }
+
+    /// 方法 `object.name(args)`，参数个数在调用时检查
+    pub fn method(
+        mut self,
+        name: &str,
+        arity: usize,
+        method: impl Fn(&mut T, &[Value]) -> NativeResult + 'static,
+    ) -> Self {
+        let method = move |data: &mut dyn Any, args: &[Value]| method(downcast_mut(data), args);
+        self.class
+            .methods
+            .insert(name.to_string(), (arity, Rc::new(method)));
+        self
+    }
+
+    /// print 和字符串化时的输出，默认是 `Name instance`
+    pub fn display(mut self, show: impl Fn(&T) -> String + 'static) -> Self {
+        let show = move |data: &dyn Any| show(downcast_ref(data));
+        self.class.show = Some(Box::new(show));
+        self
+    }
+
+    pub fn build(self) -> NativeClass {
+        self.class
+    }
+}
+
+// 实例创建时已经检查过类型，这里不会失败
+fn downcast_ref<T: Any>(data: &dyn Any) -> &T {
+    data.downcast_ref()
+        .expect("userdata type checked on creation")
+}
+
+fn downcast_mut<T: Any>(data: &mut dyn Any) -> &mut T {
+    data.downcast_mut()
+        .expect("userdata type checked on creation")
+}
+
+/// 原生类的实例
+pub struct Userdata {
+    class: Rc<NativeClass>,
+    data: RefCell<Box<dyn Any>>,
+}
+
+impl Userdata {
+    pub fn class(&self) -> &Rc<NativeClass> {
+        &self.class
+    }
+
+    /// 按类型借出包装的 Rust 值，类型不对或正在被修改时返回 `None`
+    pub fn borrow<T: Any>(&self) -> Option<Ref<'_, T>> {
+        let data = self.data.try_borrow().ok()?;
+        Ref::filter_map(data, |data| data.downcast_ref()).ok()
+    }
+
+    pub fn borrow_mut<T: Any>(&self) -> Option<RefMut<'_, T>> {
+        let data = self.data.try_borrow_mut().ok()?;
+        RefMut::filter_map(data, |data| data.downcast_mut()).ok()
+    }
+
+    /// 读属性：先找 getter，再找方法；方法绑定到这个实例上
+    pub(crate) fn get(self: &Rc<Self>, name: &str) -> Result<Value, String> {
+        if let Some(getter) = self.class.getters.get(name) {
+            return getter(self.data.borrow().as_ref());
+        }
+        let Some((arity, method)) = self.class.methods.get(name) else {
+            return Err(format!("Undefined property '{}'.", name));
+        };
+        let this = self.clone();
+        let method = method.clone();
+        Ok(Value::Native(Rc::new(NativeFunction {
+            name: name.to_string(),
+            arity: *arity,
+            function: Box::new(move |args| {
+                let mut data = this
+                    .data
+                    .try_borrow_mut()
+                    .map_err(|_| format!("{} is already in use.", this.class.name))?;
+                method(data.as_mut(), args)
+            }),
+        })))
+    }
+
+    pub(crate) fn set(&self, name: &str, value: Value) -> Result<(), String> {
+        match self.class.setters.get(name) {
+            Some(setter) => setter(self.data.borrow_mut().as_mut(), value),
+            None if self.class.getters.contains_key(name) => {
+                Err(format!("Property '{}' is read-only.", name))
+            }
+            None => Err(format!("Undefined property '{}'.", name)),
+        }
+    }
+}
+
+impl Debug for Userdata {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Userdata")
+            .field("class", &self.class.name)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Display for Userdata {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.class.show, self.data.try_borrow()) {
+            (Some(show), Ok(data)) => write!(f, "{}", show(data.as_ref())),
+            _ => write!(f, "{} instance", self.class.name),
+        }
+    }
+}
+
+/// 能从 Lox 值里取出来的 Rust 类型
+pub trait FromValue: Sized {
+    /// 出错时消息里期望的类型，带冠词，比如 "a number"
+    const EXPECTED: &'static str;
+
+    fn from_value(value: &Value) -> Option<Self>;
+}
+
+impl FromValue for f64 {
+    const EXPECTED: &'static str = "a number";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+/// 整数必须是没有小数部分的数字
+impl FromValue for i64 {
+    const EXPECTED: &'static str = "an integer";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Number(n) if n.fract() == 0.0 && n.abs() < 2f64.powi(53) => Some(*n as i64),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for bool {
+    const EXPECTED: &'static str = "a bool";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for String {
+    const EXPECTED: &'static str = "a string";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::String(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for Value {
+    const EXPECTED: &'static str = "a value";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        Some(value.clone())
+    }
+}
+
+/// 取出第 `index` 个参数并转换成 `T`，类型不对时返回错误消息
+///
+/// ```
+/// use lox_r::{Interpreter, arg};
+///
+/// let mut lox = Interpreter::new();
+/// lox.register_native("double", 1, |args| Ok((arg::<f64>(args, 0)? * 2.0).into()));
+/// assert_eq!(lox.eval("double(21)").unwrap(), 42.0.into());
+/// ```
+pub fn arg<T: FromValue>(args: &[Value], index: usize) -> Result<T, String> {
+    let value = args.get(index).unwrap_or(&Value::Nil);
+    T::from_value(value).ok_or_else(|| {
+        format!(
+            "Argument {} must be {} but got {}.",
+            index + 1,
+            T::EXPECTED,
+            value.type_name()
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arg() {
+        let args = [Value::Number(1.0), "a".into(), Value::Nil];
+        assert_eq!(arg::<f64>(&args, 0), Ok(1.0));
+        assert_eq!(arg::<String>(&args, 1), Ok("a".to_string()));
+        assert_eq!(arg::<Value>(&args, 2), Ok(Value::Nil));
+        assert_eq!(
+            arg::<f64>(&args, 1),
+            Err("Argument 2 must be a number but got string.".to_string())
+        );
+        assert_eq!(arg::<i64>(&args, 0), Ok(1));
+        assert_eq!(
+            arg::<i64>(&[Value::Number(1.5)], 0),
+            Err("Argument 1 must be an integer but got number.".to_string())
+        );
+        assert_eq!(
+            arg::<bool>(&args, 2),
+            Err("Argument 3 must be a bool but got nil.".to_string())
+        );
+    }
+}
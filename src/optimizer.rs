@@ -0,0 +1,914 @@
+// AST 优化
+// 每个 pass 可以单独开关。折叠时直接用解释器求值，保证和运行时的语义一致；
+// 求值出错的节点原样保留，让错误照常在运行时报告。
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::expr::{
+    Assignment, Binary, Call, Comma, Compound, Conditional, Expr, Get, Grouping, Index, IndexSet,
+    Lambda, List, Literal, Logical, Map, Optional, Set, Unary, Update,
+};
+use crate::interpreter::Interpreter;
+use crate::lint::static_type;
+use crate::stmt::{
+    Block, Class, Const, Export, Expression, For, ForIn, Function, If, Imported, Parameter, Print,
+    Return, Stmt, Var, While,
+};
+use crate::token::{LiteralType, Token, TokenType};
+use crate::value::Value;
+
+/// 各个优化 pass 的开关
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OptimizerConfig {
+    /// 操作数都是字面量的算术、比较、逻辑运算直接算出结果
+    /// 条件是字面量的 if、while、for 和 `?:` 只留下会执行的部分
+    pub constant_folding: bool,
+    /// 两个字符串字面量相加直接拼接
+    pub string_concat: bool,
+    /// 去掉数字上的恒等运算，比如 `x * 1`、`x - 0`、`-(-x)`
+    pub algebraic: bool,
+    /// 初始值（折叠后）是字面量的常量，读取的地方直接换成字面量
+    pub constant_inlining: bool,
+}
+
+impl Default for OptimizerConfig {
+    fn default() -> Self {
+        Self {
+            constant_folding: true,
+            string_concat: true,
+            algebraic: true,
+            constant_inlining: true,
+        }
+    }
+}
+
+impl OptimizerConfig {
+    /// 所有 pass 都关掉
+    pub const NONE: Self = Self {
+        constant_folding: false,
+        string_concat: false,
+        algebraic: false,
+        constant_inlining: false,
+    };
+}
+
+/// 优化程序里的每个表达式
+pub(crate) fn optimize_program(statements: Vec<Stmt>, config: &OptimizerConfig) -> Vec<Stmt> {
+    Optimizer::new(config).program(statements)
+}
+
+pub(crate) fn optimize(expr: Expr, config: &OptimizerConfig) -> Expr {
+    Optimizer::new(config).expr(expr)
+}
+
+/// 一层作用域，可以内联的常量带着它的值，其余名字为 None
+#[derive(Default)]
+struct Scope {
+    names: HashMap<String, Option<LiteralType>>,
+}
+
+struct Optimizer<'a> {
+    config: &'a OptimizerConfig,
+    scopes: Vec<Scope>,
+}
+
+impl<'a> Optimizer<'a> {
+    fn new(config: &'a OptimizerConfig) -> Self {
+        Self {
+            config,
+            scopes: vec![Scope::default()],
+        }
+    }
+
+    fn program(&mut self, statements: Vec<Stmt>) -> Vec<Stmt> {
+        statements
+            .into_iter()
+            .map(|stmt| self.stmt(stmt))
+            .collect()
+    }
+
+    /// 在新的作用域里优化
+    fn scoped<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        self.scopes.push(Scope::default());
+        let result = f(self);
+        self.scopes.pop();
+        result
+    }
+
+    fn declare(&mut self, name: &Token, value: Option<LiteralType>) {
+        let scope = self.scopes.last_mut().expect("global scope");
+        scope.names.insert(name.lexeme.clone(), value);
+    }
+
+    /// 读取 `name` 时可以内联的常量值
+    ///
+    /// 和 resolver 一样按词法作用域找最近的声明，之后才声明的同名变量不影响这里。
+    fn constant(&self, name: &Token) -> Option<LiteralType> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.names.get(&name.lexeme))
+            .cloned()
+            .flatten()
+    }
+
+    fn stmt(&mut self, stmt: Stmt) -> Stmt {
+        match stmt {
+            Stmt::Block(block) => Stmt::Block(Block {
+                brace: block.brace,
+                statements: self.scoped(|optimizer| optimizer.program(block.statements)),
+            }),
+            Stmt::Break(_) | Stmt::Continue(_) => stmt,
+            Stmt::Expression(expression) => Stmt::Expression(Expression {
+                expr: self.expr(expression.expr),
+            }),
+            Stmt::Class(class) => {
+                self.declare(&class.name, None);
+                Stmt::Class(Class {
+                    name: class.name,
+                    superclass: class.superclass,
+                    methods: class
+                        .methods
+                        .into_iter()
+                        .map(|method| self.function(method))
+                        .collect(),
+                })
+            }
+            Stmt::Const(constant) => {
+                // 和 resolver 一样先声明，初始值里的闭包引用的是它自己
+                self.declare(&constant.name, None);
+                let initializer = self.expr(constant.initializer);
+                let value = match &initializer {
+                    Expr::Literal(literal) if self.config.constant_inlining => {
+                        Some(literal.value.clone())
+                    }
+                    _ => None,
+                };
+                self.declare(&constant.name, value);
+                Stmt::Const(Const {
+                    name: constant.name,
+                    initializer,
+                })
+            }
+            Stmt::Export(export) => Stmt::Export(Export {
+                declaration: Box::new(self.stmt(*export.declaration)),
+            }),
+            Stmt::Function(function) => {
+                self.declare(&function.name, None);
+                Stmt::Function(self.function(function))
+            }
+            Stmt::Import(import) => {
+                match &import.imported {
+                    Imported::Module(name) => self.declare(name, None),
+                    Imported::Names(names) => {
+                        for name in names {
+                            self.declare(name, None);
+                        }
+                    }
+                }
+                Stmt::Import(import)
+            }
+            Stmt::If(stmt) => {
+                let condition = self.expr(stmt.condition);
+                let then_branch = self.stmt(*stmt.then_branch);
+                let else_branch = stmt.else_branch.map(|stmt| self.stmt(*stmt));
+                // 条件是字面量时只留下会执行的分支
+                match self.truthy(&condition) {
+                    Some(true) => then_branch,
+                    Some(false) => else_branch.unwrap_or_else(empty),
+                    None => Stmt::If(If {
+                        condition,
+                        then_branch: Box::new(then_branch),
+                        else_branch: else_branch.map(Box::new),
+                    }),
+                }
+            }
+            Stmt::Print(print) => Stmt::Print(Print {
+                expr: self.expr(print.expr),
+            }),
+            Stmt::Return(stmt) => Stmt::Return(Return {
+                keyword: stmt.keyword,
+                value: stmt.value.map(|expr| self.expr(expr)),
+            }),
+            Stmt::Var(var) => {
+                self.declare(&var.name, None);
+                let initializer = var.initializer.map(|expr| self.expr(expr));
+                Stmt::Var(Var {
+                    name: var.name,
+                    initializer,
+                })
+            }
+            Stmt::While(stmt) => {
+                let condition = self.expr(stmt.condition);
+                let body = self.stmt(*stmt.body);
+                if self.truthy(&condition) == Some(false) {
+                    return empty();
+                }
+                Stmt::While(While {
+                    condition,
+                    body: Box::new(body),
+                })
+            }
+            Stmt::For(stmt) => self.scoped(|optimizer| {
+                let initializer = stmt.initializer.map(|stmt| optimizer.stmt(*stmt));
+                let condition = stmt.condition.map(|expr| optimizer.expr(expr));
+                let increment = stmt.increment.map(|expr| optimizer.expr(expr));
+                let body = optimizer.stmt(*stmt.body);
+                // 条件恒假时只剩初始化部分，放进代码块保留它的作用域；恒真等于没写条件
+                match condition.as_ref().and_then(|expr| optimizer.truthy(expr)) {
+                    Some(false) => Stmt::Block(block(initializer.into_iter().collect())),
+                    truthy => Stmt::For(For {
+                        initializer: initializer.map(Box::new),
+                        condition: condition.filter(|_| truthy.is_none()),
+                        increment,
+                        body: Box::new(body),
+                    }),
+                }
+            }),
+            Stmt::ForIn(stmt) => {
+                let iterable = self.expr(stmt.iterable);
+                self.scoped(|optimizer| {
+                    optimizer.declare(&stmt.name, None);
+                    Stmt::ForIn(ForIn {
+                        body: Box::new(optimizer.stmt(*stmt.body)),
+                        name: stmt.name,
+                        keyword: stmt.keyword,
+                        iterable,
+                    })
+                })
+            }
+        }
+    }
+
+    /// 参数和函数体共用一个作用域，和解释器一致
+    fn function(&mut self, function: Rc<Function>) -> Rc<Function> {
+        let function = Rc::unwrap_or_clone(function);
+        let (params, body) = self.scoped(|optimizer| {
+            // 默认值能看到前面的参数
+            let params = function
+                .params
+                .into_iter()
+                .map(|param| {
+                    let default = param.default.map(|expr| optimizer.expr(expr));
+                    optimizer.declare(&param.name, None);
+                    Parameter {
+                        name: param.name,
+                        default,
+                    }
+                })
+                .collect();
+            if let Some(rest) = &function.rest {
+                optimizer.declare(rest, None);
+            }
+            (params, optimizer.program(function.body))
+        });
+        Rc::new(Function {
+            name: function.name,
+            params,
+            rest: function.rest,
+            body,
+        })
+    }
+
+    fn expr(&mut self, expr: Expr) -> Expr {
+        let config = self.config;
+        let expr = match expr {
+            Expr::Assignment(assignment) => Expr::Assignment(Assignment {
+                name: assignment.name,
+                distance: assignment.distance,
+                value: Box::new(self.expr(*assignment.value)),
+            }),
+            Expr::Binary(binary) => Expr::Binary(Binary {
+                left: Box::new(self.expr(*binary.left)),
+                operator: binary.operator,
+                right: Box::new(self.expr(*binary.right)),
+            }),
+            Expr::Call(call) => Expr::Call(Call {
+                callee: Box::new(self.expr(*call.callee)),
+                paren: call.paren,
+                arguments: call
+                    .arguments
+                    .into_iter()
+                    .map(|expr| self.expr(expr))
+                    .collect(),
+                named: call
+                    .named
+                    .into_iter()
+                    .map(|(name, expr)| (name, self.expr(expr)))
+                    .collect(),
+            }),
+            Expr::Comma(comma) => {
+                let left = self.expr(*comma.left);
+                let right = self.expr(*comma.right);
+                // 字面量没有副作用，求值了也没用
+                if config.constant_folding && literal(&left).is_some() {
+                    return right;
+                }
+                Expr::Comma(Comma {
+                    left: Box::new(left),
+                    right: Box::new(right),
+                })
+            }
+            Expr::Compound(compound) => Expr::Compound(Compound {
+                target: Box::new(self.target(*compound.target)),
+                operator: compound.operator,
+                value: Box::new(self.expr(*compound.value)),
+            }),
+            Expr::Conditional(conditional) => {
+                let condition = self.expr(*conditional.condition);
+                let then_branch = self.expr(*conditional.then_branch);
+                let else_branch = self.expr(*conditional.else_branch);
+                // 条件是字面量时只留下会执行的分支
+                if let Some(truthy) = self.truthy(&condition) {
+                    return if truthy { then_branch } else { else_branch };
+                }
+                Expr::Conditional(Conditional {
+                    condition: Box::new(condition),
+                    then_branch: Box::new(then_branch),
+                    else_branch: Box::new(else_branch),
+                })
+            }
+            Expr::Get(get) => Expr::Get(Get {
+                object: Box::new(self.expr(*get.object)),
+                name: get.name,
+                optional: get.optional,
+            }),
+            Expr::Set(set) => Expr::Set(Set {
+                object: Box::new(self.expr(*set.object)),
+                name: set.name,
+                value: Box::new(self.expr(*set.value)),
+            }),
+            Expr::Index(index) => Expr::Index(Index {
+                object: Box::new(self.expr(*index.object)),
+                bracket: index.bracket,
+                index: Box::new(self.expr(*index.index)),
+            }),
+            Expr::IndexSet(index_set) => Expr::IndexSet(IndexSet {
+                object: Box::new(self.expr(*index_set.object)),
+                bracket: index_set.bracket,
+                index: Box::new(self.expr(*index_set.index)),
+                value: Box::new(self.expr(*index_set.value)),
+            }),
+            Expr::List(list) => Expr::List(List {
+                elements: list
+                    .elements
+                    .into_iter()
+                    .map(|expr| self.expr(expr))
+                    .collect(),
+            }),
+            Expr::Map(map) => Expr::Map(Map {
+                brace: map.brace,
+                entries: map
+                    .entries
+                    .into_iter()
+                    .map(|(key, value)| (self.expr(key), self.expr(value)))
+                    .collect(),
+            }),
+            Expr::Grouping(grouping) => {
+                let inner = self.expr(*grouping.expr);
+                if config.constant_folding && matches!(inner, Expr::Literal(_)) {
+                    return inner;
+                }
+                Expr::Grouping(Grouping {
+                    expr: Box::new(inner),
+                })
+            }
+            Expr::Unary(unary) => Expr::Unary(Unary {
+                operator: unary.operator,
+                right: Box::new(self.expr(*unary.right)),
+            }),
+            Expr::Update(update) => Expr::Update(Update {
+                target: Box::new(self.target(*update.target)),
+                operator: update.operator,
+                prefix: update.prefix,
+            }),
+            Expr::Logical(logical) => Expr::Logical(Logical {
+                left: Box::new(self.expr(*logical.left)),
+                operator: logical.operator,
+                right: Box::new(self.expr(*logical.right)),
+            }),
+            Expr::Lambda(lambda) => Expr::Lambda(Lambda {
+                keyword: lambda.keyword,
+                function: self.function(lambda.function),
+            }),
+            Expr::Optional(optional) => Expr::Optional(Optional {
+                expr: Box::new(self.expr(*optional.expr)),
+            }),
+            Expr::Variable(variable) => {
+                return match self.constant(&variable.name) {
+                    Some(value) => Expr::Literal(Literal { value }),
+                    None => Expr::Variable(variable),
+                };
+            }
+            Expr::Literal(_) | Expr::Super(_) | Expr::This(_) => return expr,
+        };
+
+        // 只有字面量参与运算，求值用不到变量环境
+        if foldable(&expr, config)
+            && let Some(value) = Interpreter::bare()
+                .evaluate(&expr)
+                .ok()
+                .and_then(|value| value.to_literal())
+        {
+            return Expr::Literal(Literal { value });
+        }
+        if config.algebraic {
+            return simplify(expr);
+        }
+        expr
+    }
+
+    /// 开启常量折叠时，字面量条件的真假
+    fn truthy(&self, condition: &Expr) -> Option<bool> {
+        if !self.config.constant_folding {
+            return None;
+        }
+        literal(condition).map(|value| Value::from(value.clone()).is_truthy())
+    }
+
+    /// 复合赋值和自增自减的目标：变量不能换成常量的值，只优化属性和下标里的子表达式
+    fn target(&mut self, target: Expr) -> Expr {
+        match target {
+            Expr::Get(get) => Expr::Get(Get {
+                object: Box::new(self.expr(*get.object)),
+                name: get.name,
+                optional: get.optional,
+            }),
+            Expr::Index(index) => Expr::Index(Index {
+                object: Box::new(self.expr(*index.object)),
+                bracket: index.bracket,
+                index: Box::new(self.expr(*index.index)),
+            }),
+            target => target,
+        }
+    }
+}
+
+fn foldable(expr: &Expr, config: &OptimizerConfig) -> bool {
+    match expr {
+        Expr::Binary(binary) => match (literal(&binary.left), literal(&binary.right)) {
+            (Some(LiteralType::String(_)), Some(LiteralType::String(_)))
+                if binary.operator.token_type == TokenType::PLUS =>
+            {
+                config.string_concat
+            }
+            (Some(_), Some(_)) => config.constant_folding,
+            _ => false,
+        },
+        Expr::Logical(logical) => {
+            config.constant_folding
+                && literal(&logical.left).is_some()
+                && literal(&logical.right).is_some()
+        }
+        Expr::Unary(unary) => config.constant_folding && literal(&unary.right).is_some(),
+        _ => false,
+    }
+}
+
+/// 去掉括号后的字面量值
+/// 什么都不做的语句，替换被删掉的分支和循环
+fn empty() -> Stmt {
+    Stmt::Block(block(Vec::new()))
+}
+
+/// 优化器生成的代码块，之后不会再报错，花括号的位置用不到
+fn block(statements: Vec<Stmt>) -> Block {
+    Block {
+        brace: Token::new(TokenType::LEFT_BRACE, "{".to_string(), None, 0),
+        statements,
+    }
+}
+
+fn literal(expr: &Expr) -> Option<&LiteralType> {
+    match expr {
+        Expr::Grouping(grouping) => literal(&grouping.expr),
+        Expr::Literal(literal) => Some(&literal.value),
+        _ => None,
+    }
+}
+
+fn is_number(expr: &Expr, n: f64) -> bool {
+    // 用位比较区分 0 和 -0
+    matches!(literal(expr), Some(LiteralType::Number(v)) if v.to_bits() == n.to_bits())
+}
+
+/// 代数化简，只处理对任意数字（包括 NaN、无穷、-0）都恒等的变换。
+/// `x + 0` 不能化简：`-0 + 0` 是 `0`。`x * 0` 也不行：NaN 和无穷乘 0 不是 0。
+fn simplify(expr: Expr) -> Expr {
+    match expr {
+        Expr::Binary(binary) => {
+            let op = binary.operator.token_type;
+            let left_number = static_type(&binary.left) == Some("number");
+            let right_number = static_type(&binary.right) == Some("number");
+            let keep_left = left_number
+                && match op {
+                    TokenType::STAR | TokenType::SLASH => is_number(&binary.right, 1.0),
+                    TokenType::MINUS => is_number(&binary.right, 0.0),
+                    TokenType::PLUS => is_number(&binary.right, -0.0),
+                    _ => false,
+                };
+            let keep_right = right_number
+                && match op {
+                    TokenType::STAR => is_number(&binary.left, 1.0),
+                    TokenType::PLUS => is_number(&binary.left, -0.0),
+                    _ => false,
+                };
+            if keep_left {
+                *binary.left
+            } else if keep_right {
+                *binary.right
+            } else {
+                Expr::Binary(binary)
+            }
+        }
+        Expr::Unary(unary) => {
+            // -(-x) 在 x 是数字时等于 x；!!x 在 x 是布尔值时等于 x
+            let same_type = match unary.operator.token_type {
+                TokenType::MINUS => "number",
+                _ => "bool",
+            };
+            // 看穿括号：-(-x)
+            let right = match *unary.right {
+                Expr::Grouping(grouping) if matches!(*grouping.expr, Expr::Unary(_)) => {
+                    *grouping.expr
+                }
+                right => right,
+            };
+            match right {
+                Expr::Unary(inner)
+                    if inner.operator.token_type == unary.operator.token_type
+                        && static_type(&inner.right) == Some(same_type) =>
+                {
+                    *inner.right
+                }
+                right => Expr::Unary(Unary {
+                    operator: unary.operator,
+                    right: Box::new(right),
+                }),
+            }
+        }
+        _ => expr,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast_printer::AstPrinter;
+    use crate::parser::{Parse, parse_expr};
+    use crate::scanner::Scanner;
+    use std::cell::RefCell;
+    use std::io::Write;
+
+    const NONE: OptimizerConfig = OptimizerConfig::NONE;
+
+    /// 优化后的树用 Debug 输出，去掉 token 细节只看结构
+    fn shape(expr: &Expr) -> String {
+        match expr {
+            Expr::Binary(b) => format!(
+                "({} {} {})",
+                shape(&b.left),
+                b.operator.lexeme,
+                shape(&b.right)
+            ),
+            Expr::Grouping(g) => format!("(group {})", shape(&g.expr)),
+            Expr::Literal(l) => match &l.value {
+                LiteralType::String(s) => format!("{:?}", s),
+                value => value.to_string(),
+            },
+            Expr::Unary(u) => format!("({} {})", u.operator.lexeme, shape(&u.right)),
+            Expr::Logical(l) => format!(
+                "({} {} {})",
+                shape(&l.left),
+                l.operator.lexeme,
+                shape(&l.right)
+            ),
+            Expr::Variable(v) => v.name.lexeme.clone(),
+            Expr::Assignment(a) => format!("(= {} {})", a.name.lexeme, shape(&a.value)),
+            Expr::Get(g) if g.optional => format!("{}?.{}", shape(&g.object), g.name.lexeme),
+            Expr::Get(g) => format!("{}.{}", shape(&g.object), g.name.lexeme),
+            Expr::Optional(o) => format!("(optional {})", shape(&o.expr)),
+            Expr::Set(s) => format!(
+                "(= {}.{} {})",
+                shape(&s.object),
+                s.name.lexeme,
+                shape(&s.value)
+            ),
+            Expr::Super(s) => format!("super.{}", s.method.lexeme),
+            Expr::This(_) => "this".to_string(),
+            Expr::Comma(c) => format!("({}, {})", shape(&c.left), shape(&c.right)),
+            Expr::Compound(c) => format!(
+                "({} {} {})",
+                shape(&c.target),
+                c.operator.lexeme,
+                shape(&c.value)
+            ),
+            Expr::Update(u) if u.prefix => format!("({}{})", u.operator.lexeme, shape(&u.target)),
+            Expr::Update(u) => format!("({}{})", shape(&u.target), u.operator.lexeme),
+            Expr::Conditional(c) => format!(
+                "({} ? {} : {})",
+                shape(&c.condition),
+                shape(&c.then_branch),
+                shape(&c.else_branch)
+            ),
+            Expr::Call(c) => {
+                let arguments: Vec<_> = c.arguments.iter().map(shape).collect();
+                format!("({} {})", shape(&c.callee), arguments.join(" "))
+            }
+            Expr::Index(i) => format!("{}[{}]", shape(&i.object), shape(&i.index)),
+            Expr::Lambda(l) => format!("<fn {}>", l.function.name.lexeme),
+            Expr::IndexSet(i) => format!(
+                "(= {}[{}] {})",
+                shape(&i.object),
+                shape(&i.index),
+                shape(&i.value)
+            ),
+            Expr::List(l) => {
+                let elements: Vec<_> = l.elements.iter().map(shape).collect();
+                format!("[{}]", elements.join(" "))
+            }
+            Expr::Map(m) => {
+                let entries: Vec<_> = m
+                    .entries
+                    .iter()
+                    .map(|(k, v)| format!("{}: {}", shape(k), shape(v)))
+                    .collect();
+                format!("{{{}}}", entries.join(" "))
+            }
+        }
+    }
+
+    fn optimized(source: &str, config: OptimizerConfig) -> String {
+        shape(&optimize(parse_expr(source), &config))
+    }
+
+    #[test]
+    fn test_constant_folding() {
+        let config = OptimizerConfig {
+            constant_folding: true,
+            ..NONE
+        };
+        assert_eq!(optimized("(1 + 2) * 3", config), "9");
+        assert_eq!(optimized("!true", config), "false");
+        assert_eq!(optimized("[1 + 2, xs[0 - 1]]", config), "[3 xs[-1]]");
+        assert_eq!(optimized("{\"a\" + \"b\": !nil}", config), "{(\"a\" + \"b\"): true}");
+        assert_eq!(optimized("1 < 2 == !nil", config), "true");
+        // 运行时会出错的部分保留
+        assert_eq!(optimized("(1 + 2) * -\"a\"", config), "(3 * (- \"a\"))");
+        assert_eq!(optimized("nil or 1 + 1", config), "2");
+        assert_eq!(optimized("a = 1 + 1", config), "(= a 2)");
+        assert_eq!(optimized("x and 1 + 1", config), "(x and 2)");
+        assert_eq!(optimized("f(1 + 1, \"a\")", config), "(f 2 \"a\")");
+        assert_eq!(optimized("xs[1 + 1] += 2 * 3", config), "(xs[2] += 6)");
+        assert_eq!(optimized("a.b[-1]++", config), "(a.b[-1]++)");
+        assert_eq!(optimized("nil ? f() : 1 + 1", config), "2");
+        assert_eq!(optimized("x ? 1 : 2 * 3", config), "(x ? 1 : 6)");
+        assert_eq!(optimized("(1, f(), 2 + 2)", config), "(group ((f ), 4))");
+        // 字符串拼接是单独的 pass
+        assert_eq!(optimized("\"a\" + \"b\"", config), "(\"a\" + \"b\")");
+    }
+
+    #[test]
+    fn test_string_concat() {
+        let config = OptimizerConfig {
+            string_concat: true,
+            ..NONE
+        };
+        assert_eq!(optimized("\"a\" + (\"b\")", config), "\"ab\"");
+        assert_eq!(optimized("1 + 2", config), "(1 + 2)");
+    }
+
+    #[test]
+    fn test_algebraic() {
+        let config = OptimizerConfig {
+            algebraic: true,
+            ..NONE
+        };
+        assert_eq!(optimized("(1 - 2) * 1", config), "(group (1 - 2))");
+        assert_eq!(optimized("1 * (3 / 4)", config), "(group (3 / 4))");
+        assert_eq!(optimized("-(-(1 - 2))", config), "(group (1 - 2))");
+        assert_eq!(optimized("- -(1 - 2)", config), "(group (1 - 2))");
+        assert_eq!(optimized("!!(1 < 2)", config), "(group (1 < 2))");
+        // 不满足浮点语义或类型不确定时不化简
+        assert_eq!(optimized("(1 - 2) + 0", config), "((group (1 - 2)) + 0)");
+        assert_eq!(optimized("\"a\" * 1", config), "(\"a\" * 1)");
+        assert_eq!(optimized("!!1", config), "(! (! 1))");
+        // 变量的类型不确定
+        assert_eq!(optimized("x * 1", config), "(x * 1)");
+    }
+
+    #[test]
+    fn test_constant_inlining() {
+        let config = OptimizerConfig {
+            constant_folding: true,
+            constant_inlining: true,
+            ..NONE
+        };
+        let program = |source: &str| {
+            let mut scanner = Scanner::new(source.to_string());
+            scanner.scan_tokens();
+            let statements = Parse::new(scanner).parse();
+            optimize_program(statements, &config)
+                .iter()
+                .map(|stmt| AstPrinter.print_stmt(stmt))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            program("const n = 2 * 3; print n + 1; fun f() { return n; }"),
+            ["(const n 6)", "(print 7)", "(fun f () (return 6))"]
+        );
+        // 初始值不是字面量，或者被内层作用域遮蔽
+        assert_eq!(
+            program("const xs = []; print xs; const a = 1; { var a = 2; print a; }"),
+            [
+                "(const xs (list))",
+                "(print xs)",
+                "(const a 1)",
+                "(block (var a 2) (print a))"
+            ]
+        );
+        // 初始值里的闭包引用的是正在声明的变量
+        assert_eq!(
+            program("const a = 1; { var a = () => a; }"),
+            ["(const a 1)", "(block (var a (lambda () (return a))))"]
+        );
+        // 函数里的 a 在声明时就确定了，代码块后面声明的同名变量看不到
+        assert_eq!(
+            program("const a = 1; { fun f() { print a; } var a = 2; }"),
+            ["(const a 1)", "(block (fun f () (print 1)) (var a 2))"]
+        );
+    }
+
+    #[test]
+    fn test_dead_branches() {
+        let program = |source: &str, config: OptimizerConfig| {
+            let mut scanner = Scanner::new(source.to_string());
+            scanner.scan_tokens();
+            let statements = Parse::new(scanner).parse();
+            optimize_program(statements, &config)
+                .iter()
+                .map(|stmt| AstPrinter.print_stmt(stmt))
+                .collect::<Vec<_>>()
+        };
+        let config = OptimizerConfig {
+            constant_folding: true,
+            ..NONE
+        };
+        assert_eq!(
+            program(
+                "if (1 < 2) print 1; else print 2; if (nil) print 3;",
+                config
+            ),
+            ["(print 1)", "(block)"]
+        );
+        assert_eq!(
+            program("if (!true) { print 1; } else print 2;", config),
+            ["(print 2)"]
+        );
+        assert_eq!(
+            program("while (false) print 1; print x ? 1 : 2;", config),
+            ["(block)", "(print (?: x 1 2))"]
+        );
+        // 初始化部分还是会执行；恒真的条件可以省掉
+        assert_eq!(
+            program("for (var i = f(); 0 > 1; i++) print i;", config),
+            ["(block (var i (call f)))"]
+        );
+        assert_eq!(
+            program("for (;true;) break;", config),
+            ["(for _ _ _ (break))"]
+        );
+        // 条件不是字面量，或者没开常量折叠
+        assert_eq!(
+            program("if (x) print 1; while (x) print 2;", config),
+            ["(if x (print 1))", "(while x (print 2))"]
+        );
+        assert_eq!(
+            program("if (false) print 1;", NONE),
+            ["(if false (print 1))"]
+        );
+    }
+
+    /// 保存 print 输出
+    #[derive(Clone, Default)]
+    struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// 用给定的优化开关运行脚本，返回输出和错误
+    fn run(source: &str, config: OptimizerConfig) -> String {
+        let mut lox = Interpreter::new();
+        lox.set_optimizer(config);
+        let stdout = Buffer::default();
+        lox.set_stdout(stdout.clone());
+        let result = lox.run(source).map_err(|error| error.to_string());
+        let output = String::from_utf8(stdout.0.take()).unwrap();
+        format!("{}{:?}", output, result)
+    }
+
+    /// 同一批程序开关各个优化 pass 的结果必须一致，包括运行时错误
+    ///
+    /// 整个 tests/lox 语料在优化前后的对比由 golden 测试负责。
+    #[test]
+    fn test_same_result() {
+        let corpus = [
+            "const K = 2 * 3; fun f(x) { return x * K; } print f(7);",
+            "const K = 1; { var K = \"local\"; print K; } print K;",
+            "const S = \"a\" + \"b\"; print S + \"c\" + S;",
+            "if (1 > 2) print \"then\"; else print \"else\";",
+            "var n = 0; while (false) n = n + 1; print n;",
+            "for (var i = 0; false; i = i + 1) print i; print \"done\";",
+            "var i = 0; for (; true;) { i = i + 1; if (i > 2) break; } print i;",
+            "var x = 5; print x * 1 + 0 - -(-x);",
+            "var s = \"x\"; print s + 1;",
+            "var f = () => f; print f() == f;",
+            "fun g() { return -nil; } print \"before\"; g();",
+        ];
+        let configs = [
+            OptimizerConfig::default(),
+            OptimizerConfig {
+                constant_folding: true,
+                ..NONE
+            },
+            OptimizerConfig {
+                string_concat: true,
+                ..NONE
+            },
+            OptimizerConfig {
+                algebraic: true,
+                ..NONE
+            },
+            OptimizerConfig {
+                constant_inlining: true,
+                ..NONE
+            },
+        ];
+        for source in corpus {
+            let expected = run(source, NONE);
+            for config in configs {
+                assert_eq!(
+                    run(source, config),
+                    expected,
+                    "{} with {:?}",
+                    source,
+                    config
+                );
+            }
+        }
+    }
+
+    /// 同一批表达式开关优化的结果必须一致，包括运行时错误
+    #[test]
+    fn test_same_expression_result() {
+        let corpus = [
+            "(1 + 2) * 3 - 4 / 8",
+            "-(-(1 / 0)) * 1",
+            "-0 - 0",
+            "(0 / 0) * 1 == (0 / 0)",
+            "\"lox\" + (\"-\" + \"r\") == \"lox-r\"",
+            "!!nil",
+            "1 + \"a\"",
+            "-(\"a\" + \"b\")",
+            "(2 > 1) != !(3 <= 3)",
+            "0 ? \"a\" : \"b\"",
+            "(1, -nil)",
+        ];
+        let configs = [
+            OptimizerConfig::default(),
+            OptimizerConfig {
+                constant_folding: true,
+                ..NONE
+            },
+            OptimizerConfig {
+                string_concat: true,
+                ..NONE
+            },
+            OptimizerConfig {
+                algebraic: true,
+                ..NONE
+            },
+        ];
+        for source in corpus {
+            let expected = Interpreter::new().evaluate(&parse_expr(source)).map_err(|e| e.message);
+            for config in configs {
+                let expr = optimize(parse_expr(source), &config);
+                let actual = Interpreter::new().evaluate(&expr).map_err(|e| e.message);
+                assert_eq!(
+                    format!("{:?}", actual),
+                    format!("{:?}", expected),
+                    "{} with {:?}",
+                    source,
+                    config
+                );
+            }
+        }
+    }
+}
@@ -1,34 +1,716 @@
-use crate::err;
-use crate::expr::{Binary, Expr, Unary};
+use std::cell::Cell;
+use std::rc::Rc;
+
+use crate::diagnostic::Diagnostic;
+use crate::expr::{
+    Assignment, Binary, Call, Comma, Compound, Conditional, Expr, Get, Grouping, Index, IndexSet,
+    Lambda, List, Literal, Logical, Map, Optional, Set, Super, This, Unary, Update, Variable,
+};
 use crate::scanner::Scanner;
+use crate::stmt::{
+    Block, Break, Class, Const, Continue, Export, Expression, For, ForIn, Function, If, Import,
+    Imported, Parameter, Print, Return, Stmt, Var, While,
+};
 use crate::token::{LiteralType, Token, TokenType};
 
+/// 语法错误已经记录进 `diagnostics`，这里只用来中断当前语句
+#[derive(Debug)]
+struct ParseError;
+
+type ParseResult<T> = Result<T, ParseError>;
+
+/// 参数和实参个数的上限，和书中 jlox 一致
+const MAX_ARGUMENTS: usize = 255;
+
+/// 正在解析的函数体的种类，决定 return 是否合法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FunctionKind {
+    None,
+    Function,
+    Method,
+    /// `init` 方法，不能 return 值
+    Initializer,
+}
+
+/// 正在解析的类，决定 this 和 super 是否合法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ClassKind {
+    None,
+    Class,
+    Subclass,
+}
+
 pub(crate) struct Parse {
     // scanner: Scanner,
     tokens: Vec<Token>,
     current: usize,
+    function: FunctionKind,
+    class: ClassKind,
+    /// 当前函数里包着正在解析的语句的循环层数，决定 break 和 continue 是否合法
+    loop_depth: usize,
+    /// 嵌套在代码块（包括函数体）里的层数，export 只能出现在最外层
+    block_depth: usize,
+    pub(crate) diagnostics: Vec<Diagnostic>,
 }
 impl Parse {
     pub fn new(scanner: Scanner) -> Self {
+        Self::from_tokens(scanner.tokens)
+    }
+
+    pub(crate) fn from_tokens(tokens: Vec<Token>) -> Self {
         Self {
-            // scanner,
-            tokens: scanner.tokens,
+            tokens,
             current: 0,
+            function: FunctionKind::None,
+            class: ClassKind::None,
+            loop_depth: 0,
+            block_depth: 0,
+            diagnostics: Vec::new(),
+        }
+    }
+    /// 解析整个程序，出错的语句会被跳过，错误记录在 `diagnostics` 里
+    pub fn parse(&mut self) -> Vec<Stmt> {
+        let mut statements = Vec::new();
+        while !self.is_at_end() {
+            if let Some(stmt) = self.declaration() {
+                statements.push(stmt);
+            }
+        }
+        statements
+    }
+
+    /// 把整个输入当作一个表达式解析，REPL 用它识别不带分号的裸表达式
+    pub(crate) fn parse_expression(&mut self) -> Option<Expr> {
+        let expr = self.expression().ok()?;
+        if !self.is_at_end() {
+            self.error(self.peek(), "Expect end of expression.");
+            return None;
         }
+        Some(expr)
     }
-    pub fn parse(&mut self) -> Expr { 
-    
-        self.expression()
+
+    fn declaration(&mut self) -> Option<Stmt> {
+        let result = if self.match_token(&[TokenType::IMPORT]) {
+            self.import_declaration()
+        } else if self.match_token(&[TokenType::EXPORT]) {
+            self.export_declaration()
+        } else {
+            self.plain_declaration()
+        };
+        match result {
+            Ok(stmt) => Some(stmt),
+            Err(ParseError) => {
+                self.synchronize();
+                None
+            }
+        }
     }
-    fn expression(&mut self) -> Expr {
-        self.equality()
+
+    /// 可以被导出的声明，或者普通语句
+    fn plain_declaration(&mut self) -> ParseResult<Stmt> {
+        if self.match_token(&[TokenType::CLASS]) {
+            self.class_declaration()
+        } else if self.check(TokenType::FUN) && !self.check_next(TokenType::LEFT_PAREN) {
+            // `fun (` 开头的是匿名函数表达式
+            self.advance();
+            self.function(FunctionKind::Function).map(Stmt::Function)
+        } else if self.match_token(&[TokenType::VAR]) {
+            self.var_declaration()
+        } else if self.match_token(&[TokenType::CONST]) {
+            self.const_declaration()
+        } else {
+            self.statement()
+        }
+    }
+
+    /// `import "path" as name;` 或 `import { a, b } from "path";`，`as` 和 `from` 不是关键字
+    fn import_declaration(&mut self) -> ParseResult<Stmt> {
+        let keyword = self.previous();
+        let (path, imported) = if self.match_token(&[TokenType::LEFT_BRACE]) {
+            let mut names = Vec::new();
+            loop {
+                names.push(self.consume(TokenType::IDENTIFIER, "Expect name to import.")?);
+                if !self.match_token(&[TokenType::COMMA]) {
+                    break;
+                }
+            }
+            self.consume(TokenType::RIGHT_BRACE, "Expect '}' after imported names.")?;
+            self.contextual("from", "Expect 'from' after imported names.")?;
+            let path = self.consume(TokenType::STRING, "Expect module path.")?;
+            (path, Imported::Names(names))
+        } else {
+            let path = self.consume(TokenType::STRING, "Expect module path.")?;
+            self.contextual("as", "Expect 'as' after module path.")?;
+            let name = self.consume(TokenType::IDENTIFIER, "Expect module name.")?;
+            (path, Imported::Module(name))
+        };
+        self.consume(TokenType::SEMICOLON, "Expect ';' after import.")?;
+        Ok(Stmt::Import(Import {
+            keyword,
+            path,
+            imported,
+        }))
+    }
+
+    fn export_declaration(&mut self) -> ParseResult<Stmt> {
+        let keyword = self.previous();
+        if self.block_depth > 0 {
+            self.error(keyword, "Can only export top-level declarations.");
+        }
+        let declarations = [
+            TokenType::CLASS,
+            TokenType::FUN,
+            TokenType::VAR,
+            TokenType::CONST,
+        ];
+        // 匿名函数没有名字，不能导出
+        if !declarations
+            .iter()
+            .any(|&token_type| self.check(token_type))
+            || self.check(TokenType::FUN) && self.check_next(TokenType::LEFT_PAREN)
+        {
+            self.error(self.peek(), "Expect declaration after 'export'.");
+            return Err(ParseError);
+        }
+        Ok(Stmt::Export(Export {
+            declaration: Box::new(self.plain_declaration()?),
+        }))
+    }
+
+    fn class_declaration(&mut self) -> ParseResult<Stmt> {
+        let name = self.consume(TokenType::IDENTIFIER, "Expect class name.")?;
+        let superclass = if self.match_token(&[TokenType::LESS]) {
+            let superclass = self.consume(TokenType::IDENTIFIER, "Expect superclass name.")?;
+            if superclass.lexeme == name.lexeme {
+                self.error(superclass.clone(), "A class can't inherit from itself.");
+            }
+            Some(Variable::new(superclass))
+        } else {
+            None
+        };
+        self.consume(TokenType::LEFT_BRACE, "Expect '{' before class body.")?;
+
+        let enclosing = self.class;
+        self.class = if superclass.is_some() {
+            ClassKind::Subclass
+        } else {
+            ClassKind::Class
+        };
+        let mut methods = Vec::new();
+        let mut result = Ok(());
+        while !self.check(TokenType::RIGHT_BRACE) && !self.is_at_end() {
+            let kind = if self.peek().lexeme == "init" {
+                FunctionKind::Initializer
+            } else {
+                FunctionKind::Method
+            };
+            match self.function(kind) {
+                Ok(method) => methods.push(method),
+                Err(error) => {
+                    result = Err(error);
+                    break;
+                }
+            }
+        }
+        self.class = enclosing;
+        result?;
+
+        self.consume(TokenType::RIGHT_BRACE, "Expect '}' after class body.")?;
+        Ok(Stmt::Class(Class {
+            name,
+            superclass,
+            methods,
+        }))
+    }
+
+    /// 函数声明和方法，方法前面没有 fun 关键字
+    fn function(&mut self, kind: FunctionKind) -> ParseResult<Rc<Function>> {
+        let what = if kind == FunctionKind::Function {
+            "function"
+        } else {
+            "method"
+        };
+        let name = self.consume(TokenType::IDENTIFIER, &format!("Expect {} name.", what))?;
+        self.consume(
+            TokenType::LEFT_PAREN,
+            &format!("Expect '(' after {} name.", what),
+        )?;
+        let (params, rest) = self.parameters()?;
+        self.consume(
+            TokenType::LEFT_BRACE,
+            &format!("Expect '{{' before {} body.", what),
+        )?;
+        let body = self.function_body(kind, |parser| parser.block());
+        Ok(Rc::new(Function {
+            name,
+            params,
+            rest,
+            body: body?,
+        }))
+    }
+
+    /// 参数列表，左括号已经消费过了
+    fn parameters(&mut self) -> ParseResult<(Vec<Parameter>, Option<Token>)> {
+        let mut params: Vec<Parameter> = Vec::new();
+        let mut rest = None;
+        if !self.check(TokenType::RIGHT_PAREN) {
+            loop {
+                if params.len() >= MAX_ARGUMENTS {
+                    self.error(self.peek(), "Can't have more than 255 parameters.");
+                }
+                if self.match_token(&[TokenType::DOT_DOT_DOT]) {
+                    let message = "Expect rest parameter name.";
+                    rest = Some(self.consume(TokenType::IDENTIFIER, message)?);
+                    if self.check(TokenType::COMMA) {
+                        self.error(self.peek(), "Rest parameter must be last.");
+                        return Err(ParseError);
+                    }
+                    break;
+                }
+                let name = self.consume(TokenType::IDENTIFIER, "Expect parameter name.")?;
+                let default = if self.match_token(&[TokenType::EQUAL]) {
+                    Some(self.assignment()?)
+                } else {
+                    if params.last().is_some_and(|param| param.default.is_some()) {
+                        self.error(
+                            name.clone(),
+                            "Parameters after a default value must also have defaults.",
+                        );
+                    }
+                    None
+                };
+                params.push(Parameter { name, default });
+                if !self.match_token(&[TokenType::COMMA]) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after parameters.")?;
+        Ok((params, rest))
+    }
+
+    /// 在函数的上下文里解析函数体，解析完恢复外层的状态
+    fn function_body<T>(
+        &mut self,
+        kind: FunctionKind,
+        body: impl FnOnce(&mut Self) -> ParseResult<T>,
+    ) -> ParseResult<T> {
+        let enclosing = std::mem::replace(&mut self.function, kind);
+        // 函数体里的 break 不能跳出函数外面的循环
+        let loop_depth = std::mem::take(&mut self.loop_depth);
+        let body = body(self);
+        self.function = enclosing;
+        self.loop_depth = loop_depth;
+        body
+    }
+
+    /// `fun (params) { ... }`，`fun` 已经消费过了
+    fn lambda(&mut self) -> ParseResult<Expr> {
+        let keyword = self.previous();
+        self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'fun'.")?;
+        let (params, rest) = self.parameters()?;
+        self.consume(TokenType::LEFT_BRACE, "Expect '{' before function body.")?;
+        let body = self.function_body(FunctionKind::Function, |parser| parser.block())?;
+        Ok(Self::lambda_expr(keyword, params, rest, body))
+    }
+
+    /// `(params) => expression` 或 `(params) => { ... }`，左括号已经消费过了
+    fn arrow(&mut self) -> ParseResult<Expr> {
+        let (params, rest) = self.parameters()?;
+        let keyword = self.consume(TokenType::ARROW, "Expect '=>' after parameters.")?;
+        let body = self.function_body(FunctionKind::Function, |parser| {
+            if parser.match_token(&[TokenType::LEFT_BRACE]) {
+                parser.block()
+            } else {
+                let value = parser.assignment()?;
+                Ok(vec![Stmt::Return(Return {
+                    keyword: keyword.clone(),
+                    value: Some(value),
+                })])
+            }
+        })?;
+        Ok(Self::lambda_expr(keyword, params, rest, body))
+    }
+
+    fn lambda_expr(
+        keyword: Token,
+        params: Vec<Parameter>,
+        rest: Option<Token>,
+        body: Vec<Stmt>,
+    ) -> Expr {
+        let name = Token::new(
+            TokenType::IDENTIFIER,
+            "lambda".to_string(),
+            None,
+            keyword.line,
+        );
+        Expr::Lambda(Lambda {
+            keyword,
+            function: Rc::new(Function {
+                name,
+                params,
+                rest,
+                body,
+            }),
+        })
+    }
+
+    /// 当前的 `(` 是不是箭头函数的参数列表：匹配的 `)` 后面紧跟 `=>`
+    ///
+    /// 参数可以带默认值，所以要按括号嵌套找到匹配的右括号。
+    fn is_arrow(&self) -> bool {
+        let mut depth = 0;
+        for (i, token) in self.tokens[self.current..].iter().enumerate() {
+            match token.token_type {
+                TokenType::LEFT_PAREN | TokenType::LEFT_BRACKET | TokenType::LEFT_BRACE => {
+                    depth += 1
+                }
+                TokenType::RIGHT_PAREN | TokenType::RIGHT_BRACKET | TokenType::RIGHT_BRACE => {
+                    depth -= 1;
+                    if depth == 0 {
+                        let next = self.tokens.get(self.current + i + 1);
+                        return next.is_some_and(|token| token.token_type == TokenType::ARROW);
+                    }
+                }
+                TokenType::EOF => return false,
+                _ => {}
+            }
+        }
+        false
     }
-    fn equality(&mut self) -> Expr {
-        let mut expr: Expr = self.comparison();
+
+    fn var_declaration(&mut self) -> ParseResult<Stmt> {
+        let name = self.consume(TokenType::IDENTIFIER, "Expect variable name.")?;
+        let initializer = if self.match_token(&[TokenType::EQUAL]) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+        self.consume(
+            TokenType::SEMICOLON,
+            "Expect ';' after variable declaration.",
+        )?;
+        Ok(Stmt::Var(Var { name, initializer }))
+    }
+
+    /// 常量必须有初始值
+    fn const_declaration(&mut self) -> ParseResult<Stmt> {
+        let name = self.consume(TokenType::IDENTIFIER, "Expect constant name.")?;
+        self.consume(TokenType::EQUAL, "Expect '=' after constant name.")?;
+        let initializer = self.expression()?;
+        self.consume(
+            TokenType::SEMICOLON,
+            "Expect ';' after constant declaration.",
+        )?;
+        Ok(Stmt::Const(Const { name, initializer }))
+    }
+
+    fn statement(&mut self) -> ParseResult<Stmt> {
+        if self.match_token(&[TokenType::BREAK, TokenType::CONTINUE]) {
+            return self.jump_statement();
+        }
+        if self.match_token(&[TokenType::FOR]) {
+            return self.for_statement();
+        }
+        if self.match_token(&[TokenType::IF]) {
+            return self.if_statement();
+        }
+        if self.match_token(&[TokenType::PRINT]) {
+            let expr = self.expression()?;
+            self.consume(TokenType::SEMICOLON, "Expect ';' after value.")?;
+            return Ok(Stmt::Print(Print { expr }));
+        }
+        if self.match_token(&[TokenType::RETURN]) {
+            return self.return_statement();
+        }
+        if self.match_token(&[TokenType::WHILE]) {
+            return self.while_statement();
+        }
+        if self.match_token(&[TokenType::LEFT_BRACE]) {
+            let brace = self.previous();
+            return Ok(Stmt::Block(Block {
+                brace,
+                statements: self.block()?,
+            }));
+        }
+        self.expression_statement()
+    }
+
+    fn expression_statement(&mut self) -> ParseResult<Stmt> {
+        let expr = self.expression()?;
+        self.consume(TokenType::SEMICOLON, "Expect ';' after expression.")?;
+        Ok(Stmt::Expression(Expression { expr }))
+    }
+
+    /// 左花括号已经被吃掉
+    fn block(&mut self) -> ParseResult<Vec<Stmt>> {
+        let mut statements = Vec::new();
+        self.block_depth += 1;
+        while !self.check(TokenType::RIGHT_BRACE) && !self.is_at_end() {
+            if let Some(stmt) = self.declaration() {
+                statements.push(stmt);
+            }
+        }
+        self.block_depth -= 1;
+        self.consume(TokenType::RIGHT_BRACE, "Expect '}' after block.")?;
+        Ok(statements)
+    }
+
+    fn if_statement(&mut self) -> ParseResult<Stmt> {
+        self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'if'.")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after if condition.")?;
+        let then_branch = Box::new(self.statement()?);
+        // else 和最近的 if 配对
+        let else_branch = if self.match_token(&[TokenType::ELSE]) {
+            Some(Box::new(self.statement()?))
+        } else {
+            None
+        };
+        Ok(Stmt::If(If {
+            condition,
+            then_branch,
+            else_branch,
+        }))
+    }
+
+    fn return_statement(&mut self) -> ParseResult<Stmt> {
+        let keyword = self.previous();
+        if self.function == FunctionKind::None {
+            // 只报错不中断，后面的表达式照常解析
+            self.error(keyword.clone(), "Can't return from top-level code.");
+        }
+        let value = if self.check(TokenType::SEMICOLON) {
+            None
+        } else {
+            if self.function == FunctionKind::Initializer {
+                self.error(keyword.clone(), "Can't return a value from an initializer.");
+            }
+            Some(self.expression()?)
+        };
+        self.consume(TokenType::SEMICOLON, "Expect ';' after return value.")?;
+        Ok(Stmt::Return(Return { keyword, value }))
+    }
+
+    /// break 或 continue，关键字已经被吃掉
+    fn jump_statement(&mut self) -> ParseResult<Stmt> {
+        let keyword = self.previous();
+        if self.loop_depth == 0 {
+            let message = format!("Can't use '{}' outside of a loop.", keyword.lexeme);
+            self.error(keyword.clone(), &message);
+        }
+        let message = format!("Expect ';' after '{}'.", keyword.lexeme);
+        self.consume(TokenType::SEMICOLON, &message)?;
+        Ok(match keyword.token_type {
+            TokenType::BREAK => Stmt::Break(Break { keyword }),
+            _ => Stmt::Continue(Continue { keyword }),
+        })
+    }
+
+    /// 循环体，里面可以用 break 和 continue
+    fn loop_body(&mut self) -> ParseResult<Box<Stmt>> {
+        self.loop_depth += 1;
+        let body = self.statement();
+        self.loop_depth -= 1;
+        Ok(Box::new(body?))
+    }
+
+    fn while_statement(&mut self) -> ParseResult<Stmt> {
+        self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'while'.")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after condition.")?;
+        let body = self.loop_body()?;
+        Ok(Stmt::While(While { condition, body }))
+    }
+
+    fn for_statement(&mut self) -> ParseResult<Stmt> {
+        self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'for'.")?;
+        if self.check(TokenType::IDENTIFIER) && self.check_next(TokenType::IN) {
+            return self.for_in();
+        }
+        let initializer = if self.match_token(&[TokenType::SEMICOLON]) {
+            None
+        } else if self.match_token(&[TokenType::VAR]) {
+            Some(Box::new(self.var_declaration()?))
+        } else {
+            Some(Box::new(self.expression_statement()?))
+        };
+        let condition = if self.check(TokenType::SEMICOLON) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(TokenType::SEMICOLON, "Expect ';' after loop condition.")?;
+        let increment = if self.check(TokenType::RIGHT_PAREN) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after for clauses.")?;
+        let body = self.loop_body()?;
+        Ok(Stmt::For(For {
+            initializer,
+            condition,
+            increment,
+            body,
+        }))
+    }
+
+    /// `for (name in iterable) body`，左括号已经被吃掉
+    fn for_in(&mut self) -> ParseResult<Stmt> {
+        let name = self.advance();
+        let keyword = self.advance();
+        let iterable = self.expression()?;
+        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after for-in clause.")?;
+        let body = self.loop_body()?;
+        Ok(Stmt::ForIn(ForIn {
+            name,
+            keyword,
+            iterable,
+            body,
+        }))
+    }
+
+    fn expression(&mut self) -> ParseResult<Expr> {
+        self.comma()
+    }
+
+    /// 逗号优先级最低；参数、列表元素和映射的键值用逗号分隔，在那里从 `assignment` 开始解析
+    fn comma(&mut self) -> ParseResult<Expr> {
+        let mut expr = self.assignment()?;
+        while self.match_token(&[TokenType::COMMA]) {
+            let right = self.assignment()?;
+            expr = Expr::Comma(Comma {
+                left: Box::new(expr),
+                right: Box::new(right),
+            });
+        }
+        Ok(expr)
+    }
+
+    fn assignment(&mut self) -> ParseResult<Expr> {
+        let expr = self.conditional()?;
+        if self.match_token(&[
+            TokenType::PLUS_EQUAL,
+            TokenType::MINUS_EQUAL,
+            TokenType::STAR_EQUAL,
+            TokenType::SLASH_EQUAL,
+            TokenType::PERCENT_EQUAL,
+        ]) {
+            let operator = self.previous();
+            let value = self.assignment()?;
+            if !is_place(&expr) {
+                self.error(operator, "Invalid assignment target.");
+                return Ok(expr);
+            }
+            return Ok(Expr::Compound(Compound {
+                target: Box::new(expr),
+                operator,
+                value: Box::new(value),
+            }));
+        }
+        if self.match_token(&[TokenType::EQUAL]) {
+            let equals = self.previous();
+            let value = self.assignment()?;
+            match expr {
+                Expr::Variable(variable) => {
+                    return Ok(Expr::Assignment(Assignment {
+                        name: variable.name,
+                        distance: Cell::new(None),
+                        value: Box::new(value),
+                    }));
+                }
+                Expr::Get(get) => {
+                    return Ok(Expr::Set(Set {
+                        object: get.object,
+                        name: get.name,
+                        value: Box::new(value),
+                    }));
+                }
+                Expr::Index(index) => {
+                    return Ok(Expr::IndexSet(IndexSet {
+                        object: index.object,
+                        bracket: index.bracket,
+                        index: index.index,
+                        value: Box::new(value),
+                    }));
+                }
+                _ => {}
+            }
+            // 只报错不中断，左边已经完整解析过了
+            self.error(equals, "Invalid assignment target.");
+        }
+        Ok(expr)
+    }
+
+    /// `a ? b : c`，右结合；中间的分支在 `?` 和 `:` 之间，可以是任意表达式
+    fn conditional(&mut self) -> ParseResult<Expr> {
+        let condition = self.coalesce()?;
+        if !self.match_token(&[TokenType::QUESTION]) {
+            return Ok(condition);
+        }
+        let then_branch = self.expression()?;
+        self.consume(
+            TokenType::COLON,
+            "Expect ':' after then branch of conditional expression.",
+        )?;
+        let else_branch = self.conditional()?;
+        Ok(Expr::Conditional(Conditional {
+            condition: Box::new(condition),
+            then_branch: Box::new(then_branch),
+            else_branch: Box::new(else_branch),
+        }))
+    }
+
+    /// `a ?? b` 比 or 低，`a or b ?? c` 是 `(a or b) ?? c`
+    fn coalesce(&mut self) -> ParseResult<Expr> {
+        let mut expr = self.or()?;
+        while self.match_token(&[TokenType::QUESTION_QUESTION]) {
+            let operator = self.previous();
+            let right = self.or()?;
+            expr = Expr::Logical(Logical {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            });
+        }
+        Ok(expr)
+    }
+
+    fn or(&mut self) -> ParseResult<Expr> {
+        let mut expr = self.and()?;
+        while self.match_token(&[TokenType::OR]) {
+            let operator = self.previous();
+            let right = self.and()?;
+            expr = Expr::Logical(Logical {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            });
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> ParseResult<Expr> {
+        let mut expr = self.equality()?;
+        while self.match_token(&[TokenType::AND]) {
+            let operator = self.previous();
+            let right = self.equality()?;
+            expr = Expr::Logical(Logical {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            });
+        }
+        Ok(expr)
+    }
+
+    fn equality(&mut self) -> ParseResult<Expr> {
+        let mut expr: Expr = self.comparison()?;
 
         while self.match_token(&[TokenType::BANG_EQUAL, TokenType::EQUAL_EQUAL]) {
             let opr: Token = self.previous();
-            let right = self.comparison();
+            let right = self.comparison()?;
             let b = Binary {
                 left: Box::new(expr),
                 operator: opr,
@@ -36,7 +718,7 @@ impl Parse {
             };
             expr = Expr::Binary(b);
         }
-        return expr;
+        Ok(expr)
     }
     fn match_token(&mut self, token_types: &[TokenType]) -> bool {
         for token_type in token_types {
@@ -53,6 +735,13 @@ impl Parse {
         }
         self.peek().token_type == token_type
     }
+    /// 下一个 token 之后的那个是否是 `token_type`
+    fn check_next(&self, token_type: TokenType) -> bool {
+        self.tokens
+            .get(self.current + 1)
+            .is_some_and(|token| token.token_type == token_type)
+    }
+
     fn peek(&self) -> Token {
         self.tokens[self.current].clone()
     }
@@ -76,141 +765,380 @@ impl Parse {
         self.tokens[self.current - 1].clone()
     }
 
-    fn comparison(&mut self) -> Expr {
-        let mut expr: Expr = self.term();
+    fn comparison(&mut self) -> ParseResult<Expr> {
+        let mut expr: Expr = self.bit_or()?;
 
         while self.match_token(&[
             TokenType::GREATER,
             TokenType::GREATER_EQUAL,
             TokenType::LESS,
             TokenType::LESS_EQUAL,
+            TokenType::IS,
         ]) {
             let opr: Token = self.previous();
-            let right = self.term();
+            let right = self.bit_or()?;
             expr = Expr::Binary(Binary {
                 left: Box::new(expr),
                 operator: opr,
                 right: Box::new(right),
             })
         }
-        expr
+        Ok(expr)
+    }
+
+    /// 位运算比比较优先级高，和 Python 一样：`a & 1 == 0` 是 `(a & 1) == 0`
+    fn bit_or(&mut self) -> ParseResult<Expr> {
+        let mut expr: Expr = self.bit_xor()?;
+        while self.match_token(&[TokenType::PIPE]) {
+            let opr: Token = self.previous();
+            let right = self.bit_xor()?;
+            expr = Expr::Binary(Binary {
+                left: Box::new(expr),
+                operator: opr,
+                right: Box::new(right),
+            });
+        }
+        Ok(expr)
+    }
+
+    fn bit_xor(&mut self) -> ParseResult<Expr> {
+        let mut expr: Expr = self.bit_and()?;
+        while self.match_token(&[TokenType::CARET]) {
+            let opr: Token = self.previous();
+            let right = self.bit_and()?;
+            expr = Expr::Binary(Binary {
+                left: Box::new(expr),
+                operator: opr,
+                right: Box::new(right),
+            });
+        }
+        Ok(expr)
+    }
+
+    fn bit_and(&mut self) -> ParseResult<Expr> {
+        let mut expr: Expr = self.shift()?;
+        while self.match_token(&[TokenType::AMPERSAND]) {
+            let opr: Token = self.previous();
+            let right = self.shift()?;
+            expr = Expr::Binary(Binary {
+                left: Box::new(expr),
+                operator: opr,
+                right: Box::new(right),
+            });
+        }
+        Ok(expr)
+    }
+
+    fn shift(&mut self) -> ParseResult<Expr> {
+        let mut expr: Expr = self.term()?;
+        while self.match_token(&[TokenType::LESS_LESS, TokenType::GREATER_GREATER]) {
+            let opr: Token = self.previous();
+            let right = self.term()?;
+            expr = Expr::Binary(Binary {
+                left: Box::new(expr),
+                operator: opr,
+                right: Box::new(right),
+            });
+        }
+        Ok(expr)
     }
 
-    fn term(&mut self) -> Expr {
-        let mut expr: Expr = self.factor();
+    fn term(&mut self) -> ParseResult<Expr> {
+        let mut expr: Expr = self.factor()?;
         while self.match_token(&[TokenType::MINUS, TokenType::PLUS]) {
             let opr: Token = self.previous();
-            let right = self.factor();
+            let right = self.factor()?;
             expr = Expr::Binary(Binary {
                 left: Box::new(expr),
                 operator: opr,
                 right: Box::new(right),
             });
         }
-        expr
+        Ok(expr)
     }
 
-    fn factor(&mut self) -> Expr {
-        let mut expr: Expr = self.unary();
-        while self.match_token(&[TokenType::SLASH, TokenType::STAR]) {
+    fn factor(&mut self) -> ParseResult<Expr> {
+        let mut expr: Expr = self.unary()?;
+        while self.match_token(&[
+            TokenType::SLASH,
+            TokenType::STAR,
+            TokenType::PERCENT,
+            TokenType::TILDE_SLASH,
+        ]) {
             let opr: Token = self.previous();
-            let right = self.unary();
+            let right = self.unary()?;
             expr = Expr::Binary(Binary {
                 left: Box::new(expr),
                 operator: opr,
                 right: Box::new(right),
             });
         }
-        expr
+        Ok(expr)
     }
 
-    fn unary(&mut self) -> Expr {
-        if self.match_token(&[TokenType::BANG, TokenType::MINUS]) {
+    fn unary(&mut self) -> ParseResult<Expr> {
+        if self.match_token(&[TokenType::BANG, TokenType::MINUS, TokenType::TILDE]) {
             let operator: Token = self.previous();
-            let right = self.unary();
-            return Expr::Unary(Unary {
+            let right = self.unary()?;
+            return Ok(Expr::Unary(Unary {
                 operator,
                 right: Box::new(right),
-            });
+            }));
         }
-        self.primary()
-    }
-
-    fn primary(&mut self) -> Expr {
-        /*   match self.peek().token_type {
-        TokenType::FALSE => Expr::Literal(crate::expr::Literal {
-            value: LiteralType::Bool(false),
-        }),
-        TokenType::TRUE => Expr::Literal(crate::expr::Literal {
-            value: LiteralType::Bool(true),
-        }),
-        TokenType::NIL => Expr::Literal(crate::expr::Literal {
-            value: LiteralType::Nil,
-        }),
-        TokenType::NUMBER | TokenType::STRING => {
-            let literal = self.advance();
-            Expr::Literal(crate::expr::Literal {
-                value: self.previous().literal.clone().unwrap_or(LiteralType::Nil),
-            })
+        self.power()
+    }
+
+    /// `**` 右结合，比一元运算符优先级高：`-2 ** 2` 是 `-(2 ** 2)`，右边可以是 `-1`
+    fn power(&mut self) -> ParseResult<Expr> {
+        let expr = self.update()?;
+        if self.match_token(&[TokenType::STAR_STAR]) {
+            let operator = self.previous();
+            let right = self.unary()?;
+            return Ok(Expr::Binary(Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            }));
         }
-        TokenType::LEFT_PAREN => {
-            self.advance(); // consume '('
-            let expr = self.expression();
-            if !self.match_token(&[TokenType::RIGHT_PAREN]) {
-                panic!("Expected ')' after expression");
+        Ok(expr)
+    }
+
+    /// 前缀和后缀的 `++`、`--`
+    fn update(&mut self) -> ParseResult<Expr> {
+        if self.match_token(&[TokenType::PLUS_PLUS, TokenType::MINUS_MINUS]) {
+            let operator = self.previous();
+            let target = self.call()?;
+            return Ok(self.finish_update(target, operator, true));
+        }
+        let expr = self.call()?;
+        if self.match_token(&[TokenType::PLUS_PLUS, TokenType::MINUS_MINUS]) {
+            let operator = self.previous();
+            return Ok(self.finish_update(expr, operator, false));
+        }
+        Ok(expr)
+    }
+
+    fn finish_update(&mut self, target: Expr, operator: Token, prefix: bool) -> Expr {
+        if !is_place(&target) {
+            let message = match operator.token_type {
+                TokenType::PLUS_PLUS => "Invalid increment target.",
+                _ => "Invalid decrement target.",
+            };
+            // 和赋值一样只报错不中断
+            self.error(operator, message);
+            return target;
+        }
+        Expr::Update(Update {
+            target: Box::new(target),
+            operator,
+            prefix,
+        })
+    }
+
+    fn call(&mut self) -> ParseResult<Expr> {
+        let mut expr = self.primary()?;
+        // 链上出现过 `?.`
+        let mut optional = false;
+        loop {
+            if self.match_token(&[TokenType::LEFT_PAREN]) {
+                expr = self.finish_call(expr)?;
+            } else if self.match_token(&[TokenType::DOT, TokenType::QUESTION_DOT]) {
+                let dot = self.previous();
+                let message = format!("Expect property name after '{}'.", dot.lexeme);
+                let name = self.consume(TokenType::IDENTIFIER, &message)?;
+                let is_optional = dot.token_type == TokenType::QUESTION_DOT;
+                optional |= is_optional;
+                expr = Expr::Get(Get {
+                    object: Box::new(expr),
+                    name,
+                    optional: is_optional,
+                });
+            } else if self.match_token(&[TokenType::LEFT_BRACKET]) {
+                let index = self.expression()?;
+                let bracket = self.consume(TokenType::RIGHT_BRACKET, "Expect ']' after index.")?;
+                expr = Expr::Index(Index {
+                    object: Box::new(expr),
+                    bracket,
+                    index: Box::new(index),
+                });
+            } else {
+                break;
             }
-            Expr::Grouping(crate::expr::Grouping {
+        }
+        if optional {
+            return Ok(Expr::Optional(Optional {
                 expr: Box::new(expr),
-            })
-        } */
+            }));
+        }
+        Ok(expr)
+    }
+
+    /// 左括号已经被吃掉，命名实参 `name: value` 只能跟在位置实参后面
+    fn finish_call(&mut self, callee: Expr) -> ParseResult<Expr> {
+        let mut arguments = Vec::new();
+        let mut named: Vec<(Token, Expr)> = Vec::new();
+        if !self.check(TokenType::RIGHT_PAREN) {
+            loop {
+                if arguments.len() + named.len() >= MAX_ARGUMENTS {
+                    self.error(self.peek(), "Can't have more than 255 arguments.");
+                }
+                if self.check(TokenType::IDENTIFIER) && self.check_next(TokenType::COLON) {
+                    let name = self.advance();
+                    self.advance();
+                    if named.iter().any(|(other, _)| other.lexeme == name.lexeme) {
+                        let message = format!("Duplicate named argument '{}'.", name.lexeme);
+                        self.error(name.clone(), &message);
+                    }
+                    named.push((name, self.assignment()?));
+                } else {
+                    if !named.is_empty() {
+                        self.error(
+                            self.peek(),
+                            "Positional arguments must come before named arguments.",
+                        );
+                    }
+                    arguments.push(self.assignment()?);
+                }
+                if !self.match_token(&[TokenType::COMMA]) {
+                    break;
+                }
+            }
+        }
+        let paren = self.consume(TokenType::RIGHT_PAREN, "Expect ')' after arguments.")?;
+        Ok(Expr::Call(Call {
+            callee: Box::new(callee),
+            paren,
+            arguments,
+            named,
+        }))
+    }
+
+    fn primary(&mut self) -> ParseResult<Expr> {
         if self.match_token(&[TokenType::FALSE]) {
-            let literal = self.previous();
+            return Ok(Expr::Literal(Literal {
+                value: LiteralType::Bool(false),
+            }));
         }
         if self.match_token(&[TokenType::TRUE]) {
-            let literal = self.previous();
-            return Expr::Literal(crate::expr::Literal {
+            return Ok(Expr::Literal(Literal {
                 value: LiteralType::Bool(true),
-            });
+            }));
         }
         if self.match_token(&[TokenType::NIL]) {
-            let literal = self.previous();
-            return Expr::Literal(crate::expr::Literal {
+            return Ok(Expr::Literal(Literal {
                 value: LiteralType::Nil,
-            });
+            }));
         }
         if self.match_token(&[TokenType::NUMBER, TokenType::STRING]) {
             let literal = self.previous();
-            return Expr::Literal(crate::expr::Literal {
+            return Ok(Expr::Literal(Literal {
                 value: literal.literal.clone().unwrap_or(LiteralType::Nil),
-            });
+            }));
+        }
+        if self.match_token(&[TokenType::THIS]) {
+            let keyword = self.previous();
+            if self.class == ClassKind::None {
+                self.error(keyword.clone(), "Can't use 'this' outside of a class.");
+            }
+            return Ok(Expr::This(This {
+                keyword,
+                distance: Cell::new(None),
+            }));
+        }
+        if self.match_token(&[TokenType::SUPER]) {
+            let keyword = self.previous();
+            match self.class {
+                ClassKind::None => {
+                    self.error(keyword.clone(), "Can't use 'super' outside of a class.")
+                }
+                ClassKind::Class => self.error(
+                    keyword.clone(),
+                    "Can't use 'super' in a class with no superclass.",
+                ),
+                ClassKind::Subclass => {}
+            }
+            self.consume(TokenType::DOT, "Expect '.' after 'super'.")?;
+            let method = self.consume(TokenType::IDENTIFIER, "Expect superclass method name.")?;
+            return Ok(Expr::Super(Super {
+                keyword,
+                method,
+                distance: Cell::new(None),
+            }));
+        }
+        if self.match_token(&[TokenType::IDENTIFIER]) {
+            return Ok(Expr::Variable(Variable::new(self.previous())));
         }
 
+        if self.match_token(&[TokenType::FUN]) {
+            return self.lambda();
+        }
+        if self.check(TokenType::LEFT_PAREN) && self.is_arrow() {
+            self.advance();
+            return self.arrow();
+        }
         if self.match_token(&[TokenType::LEFT_PAREN]) {
-            let expr = self.expression();
-            if !self.match_token(&[TokenType::RIGHT_PAREN]) {
-                panic!("Expected ')' after expression");
-            }
-            return Expr::Grouping(crate::expr::Grouping {
+            let expr = self.expression()?;
+            self.consume(TokenType::RIGHT_PAREN, "Expect ')' after expression.")?;
+            return Ok(Expr::Grouping(Grouping {
                 expr: Box::new(expr),
-            });
+            }));
+        }
+        if self.match_token(&[TokenType::LEFT_BRACKET]) {
+            let mut elements = Vec::new();
+            if !self.check(TokenType::RIGHT_BRACKET) {
+                loop {
+                    elements.push(self.assignment()?);
+                    if !self.match_token(&[TokenType::COMMA]) {
+                        break;
+                    }
+                }
+            }
+            self.consume(TokenType::RIGHT_BRACKET, "Expect ']' after list elements.")?;
+            return Ok(Expr::List(List { elements }));
+        }
+        // 语句开头的 `{` 是代码块，能走到这里的都在表达式位置上
+        if self.match_token(&[TokenType::LEFT_BRACE]) {
+            let mut entries = Vec::new();
+            if !self.check(TokenType::RIGHT_BRACE) {
+                loop {
+                    let key = self.assignment()?;
+                    self.consume(TokenType::COLON, "Expect ':' after map key.")?;
+                    entries.push((key, self.assignment()?));
+                    if !self.match_token(&[TokenType::COMMA]) {
+                        break;
+                    }
+                }
+            }
+            let brace = self.consume(TokenType::RIGHT_BRACE, "Expect '}' after map entries.")?;
+            return Ok(Expr::Map(Map { brace, entries }));
+        }
+        self.error(self.peek(), "Expect expression.");
+        Err(ParseError)
+    }
+
+    fn consume(&mut self, token_type: TokenType, error_message: &str) -> ParseResult<Token> {
+        if self.check(token_type) {
+            return Ok(self.advance());
         }
-    //   return Expr::Literal(crate::expr::Literal { value: LiteralType::Nil });
-    // todo!("Primary expression parsing not implemented yet");
-        err(self.peek().line, "Expected expression");
-        return Expr::Literal(crate::expr::Literal {
-            value: LiteralType::Nil,
-        });
+        self.error(self.peek(), error_message);
+        Err(ParseError)
     }
-    
-    fn consume(&mut self, token: Token, error_message: &str) -> Token {
-        if self.check(token.token_type) {
-            return self.advance();
+
+    /// 当作关键字用的标识符，比如 import 里的 `as` 和 `from`
+    fn contextual(&mut self, word: &str, error_message: &str) -> ParseResult<Token> {
+        if self.check(TokenType::IDENTIFIER) && self.peek().lexeme == word {
+            return Ok(self.advance());
         }
-        err(token.line, error_message);
-        panic!("{}", error_message);
+        self.error(self.peek(), error_message);
+        Err(ParseError)
+    }
 
+    /// 记录一个语法错误
+    fn error(&mut self, token: Token, msg: &str) {
+        self.diagnostics.push(Diagnostic::at_token(&token, msg));
     }
 
+    /// 出错后跳到下一条语句的开头
     fn synchronize(&mut self) {
         self.advance();
         while !self.is_at_end() {
@@ -221,14 +1149,35 @@ impl Parse {
                 TokenType::CLASS
                 | TokenType::FUN
                 | TokenType::VAR
+                | TokenType::CONST
+                | TokenType::IMPORT
+                | TokenType::EXPORT
                 | TokenType::FOR
                 | TokenType::IF
                 | TokenType::WHILE
                 | TokenType::PRINT
-                | TokenType::RETURN => return,
+                | TokenType::RETURN
+                | TokenType::BREAK
+                | TokenType::CONTINUE => return,
                 _ => {}
             }
             self.advance();
         }
     }
 }
+
+/// 能被赋值的表达式：变量、属性访问和下标访问
+fn is_place(expr: &Expr) -> bool {
+    matches!(expr, Expr::Variable(_) | Expr::Get(_) | Expr::Index(_))
+}
+
+/// 测试用：把单个表达式解析成 AST
+#[cfg(test)]
+pub(crate) fn parse_expr(source: &str) -> Expr {
+    let mut scanner = Scanner::new(source.to_string());
+    scanner.scan_tokens();
+    let mut parser = Parse::new(scanner);
+    let expr = parser.parse_expression();
+    assert!(parser.diagnostics.is_empty(), "{:?}", parser.diagnostics);
+    expr.unwrap()
+}
@@ -0,0 +1,343 @@
+// 交互式 REPL
+// 用 rustyline 提供行编辑和历史记录，历史保存在 `~/.lox_history`。
+// 括号、花括号、方括号没有配对或字符串没有结束时自动续行，Ctrl-C 放弃当前输入，Ctrl-D 退出；
+// 退出时还有没写完的输入就照常执行一遍，让解析器报告哪里不完整。
+// 输入里调用了 `exit` 时先保存历史再退出。
+// 所有输入共用一个解释器，前面定义的变量后面还能用。
+// 不带分号的单个表达式直接输出它的值；以 `:` 开头的是 REPL 命令，见 `:help`。
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::time::Instant;
+
+use rustyline::DefaultEditor;
+use rustyline::error::ReadlineError;
+
+use crate::ast_printer::AstPrinter;
+use crate::interpreter::{Interpreter, parse_bare_expression};
+use crate::optimizer::OptimizerConfig;
+use crate::parser::Parse;
+use crate::scanner::Scanner;
+use crate::stdlib::Capabilities;
+use crate::token::{TokenType, TriviaKind};
+use crate::{HAD_ERROR, HAD_RUNTIME_ERROR, report_diagnostics, report_error, run};
+
+const HISTORY_FILE: &str = ".lox_history";
+
+const HELP: &str = "\
+:tokens <code>  show the tokens of <code>
+:ast <code>     show the syntax tree of <code>
+:env            list global variables and their values
+:load <file>    run a file in this session
+:reset          forget all global variables
+:time <code>    run <code> and show how long it took
+:help           show this help
+Enter an expression without ';' to print its value. Ctrl-D exits.";
+
+/// `capabilities`、优化开关 `optimizer` 和模块搜索路径 `module_path` 同时用于 `:reset`
+/// 之后的新解释器
+///
+/// 输入里调用了 `exit` 时返回它给出的退出码，由调用方退出进程。
+pub fn run_prompt(
+    capabilities: Capabilities,
+    optimizer: OptimizerConfig,
+    module_path: Vec<PathBuf>,
+) -> Option<i32> {
+    println!("Running in interactive mode...");
+    let mut editor = match DefaultEditor::new() {
+        Ok(editor) => editor,
+        Err(e) => {
+            eprintln!("Error starting line editor: {}", e);
+            return None;
+        }
+    };
+    let history = history_path();
+    if let Some(path) = &history {
+        // 第一次运行时历史文件还不存在
+        let _ = editor.load_history(path);
+    }
+
+    let mut interpreter = new_interpreter(capabilities, optimizer, &module_path);
+    let mut input = String::new();
+    let exit = loop {
+        let prompt = if input.is_empty() { ">> " } else { ".. " };
+        match editor.readline(prompt) {
+            Ok(line) => {
+                // 命令只占一行
+                if input.is_empty() && line.trim_start().starts_with(':') {
+                    let _ = editor.add_history_entry(line.trim());
+                    let exit = command(line.trim(), &mut interpreter, capabilities, &module_path);
+                    if exit.is_some() {
+                        break exit;
+                    }
+                    continue;
+                }
+                input.push_str(&line);
+                input.push('\n');
+                if needs_continuation(&input) {
+                    continue;
+                }
+                let entry = std::mem::take(&mut input);
+                if entry.trim().is_empty() {
+                    continue;
+                }
+                let _ = editor.add_history_entry(entry.trim_end());
+                let exit = eval_entry(entry, &mut interpreter);
+                if exit.is_some() {
+                    break exit;
+                }
+            }
+            Err(ReadlineError::Interrupted) => input.clear(),
+            Err(ReadlineError::Eof) if input.trim().is_empty() => break None,
+            Err(ReadlineError::Eof) => {
+                let entry = std::mem::take(&mut input);
+                let _ = editor.add_history_entry(entry.trim_end());
+                break eval_entry(entry, &mut interpreter);
+            }
+            Err(e) => {
+                eprintln!("Error reading input: {}", e);
+                break None;
+            }
+        }
+    };
+
+    if let Some(path) = &history
+        && let Err(e) = editor.save_history(path)
+    {
+        eprintln!("Error saving history to {}: {}", path.display(), e);
+    }
+    exit
+}
+
+fn new_interpreter(
+    capabilities: Capabilities,
+    optimizer: OptimizerConfig,
+    module_path: &[PathBuf],
+) -> Interpreter {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_capabilities(capabilities);
+    interpreter.set_optimizer(optimizer);
+    interpreter.set_module_path(module_path.to_vec());
+    interpreter
+}
+
+fn history_path() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?;
+    Some(PathBuf::from(home).join(HISTORY_FILE))
+}
+
+/// 输入是否还没写完：有没关闭的括号、花括号，或者字符串、块注释没有结束
+fn needs_continuation(input: &str) -> bool {
+    let mut scanner = Scanner::with_trivia(input.to_string());
+    let tokens = scanner.scan_tokens();
+    if scanner
+        .diagnostics
+        .iter()
+        .any(|d| d.message == "Unterminated string.")
+    {
+        return true;
+    }
+    // 没有结束的块注释一直延伸到输入末尾，`/*/` 也还没有结束
+    let open_comment = tokens
+        .iter()
+        .flat_map(|token| token.leading.iter().chain(&token.trailing))
+        .any(|trivia| {
+            trivia.kind == TriviaKind::BlockComment
+                && (trivia.text.len() < 4 || !trivia.text.ends_with("*/"))
+        });
+    if open_comment {
+        return true;
+    }
+    let mut depth = 0;
+    for token in &tokens {
+        match token.token_type {
+            TokenType::LEFT_PAREN | TokenType::LEFT_BRACE | TokenType::LEFT_BRACKET => depth += 1,
+            TokenType::RIGHT_PAREN | TokenType::RIGHT_BRACE | TokenType::RIGHT_BRACKET => {
+                depth -= 1
+            }
+            _ => {}
+        }
+    }
+    depth > 0
+}
+
+/// 执行一次输入：裸表达式输出它的值，其余当作语句执行；调用了 `exit` 时返回退出码
+fn eval_entry(entry: String, interpreter: &mut Interpreter) -> Option<i32> {
+    let exit = if parse_bare_expression(&entry).is_some() {
+        match interpreter.eval(&entry) {
+            Ok(value) => {
+                println!("{}", value);
+                None
+            }
+            Err(error) => report_error(interpreter, &error),
+        }
+    } else {
+        run(entry, interpreter)
+    };
+    // 重置错误状态
+    HAD_ERROR.store(false, Ordering::SeqCst);
+    HAD_RUNTIME_ERROR.store(false, Ordering::SeqCst);
+    exit
+}
+
+/// 执行一条 REPL 命令，`:load`、`:time` 执行的代码调用了 `exit` 时返回退出码
+fn command(
+    line: &str,
+    interpreter: &mut Interpreter,
+    capabilities: Capabilities,
+    module_path: &[PathBuf],
+) -> Option<i32> {
+    let (name, arg) = line.split_once(' ').unwrap_or((line, ""));
+    let arg = arg.trim();
+    match name {
+        ":tokens" => {
+            for line in tokens(arg) {
+                println!("{}", line);
+            }
+        }
+        ":ast" => {
+            for line in ast(arg) {
+                println!("{}", line);
+            }
+        }
+        ":env" => {
+            for (name, value) in interpreter.globals() {
+                println!("{} = {}", name, value);
+            }
+        }
+        ":load" => match std::fs::read_to_string(arg) {
+            Ok(source) => {
+                // 文件里的 import 从文件所在的目录开始找
+                let path = Path::new(arg).canonicalize().unwrap_or_default();
+                interpreter.modules.enter(path, arg.to_string());
+                let exit = eval_entry(source, interpreter);
+                interpreter.modules.leave();
+                return exit;
+            }
+            Err(e) => eprintln!("Error reading file {}: {}", arg, e),
+        },
+        ":reset" => {
+            let optimizer = interpreter.optimizer;
+            *interpreter = new_interpreter(capabilities, optimizer, module_path);
+        }
+        ":time" => {
+            let start = Instant::now();
+            let exit = eval_entry(arg.to_string(), interpreter);
+            println!("time: {:.3?}", start.elapsed());
+            return exit;
+        }
+        ":help" => println!("{}", HELP),
+        _ => eprintln!("Unknown command '{}'. Type :help for a list.", name),
+    }
+    None
+}
+
+/// `:tokens` 的输出，每行一个 token：行号、类型、原文
+fn tokens(source: &str) -> Vec<String> {
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens = scanner.scan_tokens();
+    report_diagnostics(&scanner.diagnostics);
+    HAD_ERROR.store(false, Ordering::SeqCst);
+    tokens
+        .iter()
+        .map(|token| match &token.literal {
+            Some(literal) => format!(
+                "{:>4} {:?} {} {}",
+                token.line, token.token_type, token.lexeme, literal
+            ),
+            None => format!("{:>4} {:?} {}", token.line, token.token_type, token.lexeme)
+                .trim_end()
+                .to_string(),
+        })
+        .collect()
+}
+
+/// `:ast` 的输出，裸表达式打印表达式树，否则每条语句一行
+fn ast(source: &str) -> Vec<String> {
+    if let Some(expr) = parse_bare_expression(source) {
+        return vec![AstPrinter.print_expr(&expr)];
+    }
+    let mut scanner = Scanner::new(source.to_string());
+    scanner.scan_tokens();
+    let diagnostics = std::mem::take(&mut scanner.diagnostics);
+    let mut parser = Parse::new(scanner);
+    let statements = parser.parse();
+    report_diagnostics(&diagnostics);
+    report_diagnostics(&parser.diagnostics);
+    HAD_ERROR.store(false, Ordering::SeqCst);
+    statements
+        .iter()
+        .map(|stmt| AstPrinter.print_stmt(stmt))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_needs_continuation() {
+        assert!(!needs_continuation("print 1;\n"));
+        assert!(needs_continuation("while (true) {\n"));
+        assert!(needs_continuation("print (1 +\n"));
+        assert!(needs_continuation("print \"multi\nline\n"));
+        assert!(!needs_continuation("{ print \"}\"; }\n"));
+        // 多余的右括号交给解析器报错，不再等待输入
+        assert!(!needs_continuation("}\n"));
+        assert!(!needs_continuation("// (\n"));
+        assert!(needs_continuation("/* note\n"));
+        assert!(needs_continuation("print 1; /*/\n"));
+        assert!(!needs_continuation("/* ( */ print 1;\n"));
+        assert!(!needs_continuation("/**/\n"));
+    }
+
+    #[test]
+    fn test_bare_expression() {
+        assert!(parse_bare_expression("1 + 2\n").is_some());
+        assert!(parse_bare_expression("a = 3").is_some());
+        assert!(parse_bare_expression("1 + 2;").is_none());
+        assert!(parse_bare_expression("print 1;").is_none());
+        assert!(parse_bare_expression("1 2").is_none());
+    }
+
+    #[test]
+    fn test_tokens_and_ast() {
+        assert_eq!(
+            tokens("var a = \"s\";"),
+            [
+                "   1 VAR var",
+                "   1 IDENTIFIER a",
+                "   1 EQUAL =",
+                "   1 STRING \"s\" s",
+                "   1 SEMICOLON ;",
+                "   1 EOF",
+            ]
+        );
+        assert_eq!(ast("(1 + 2) * 3"), ["(* (group (+ 1 2)) 3)"]);
+        assert_eq!(ast("var a = 1; print a;"), ["(var a 1)", "(print a)"]);
+    }
+
+    #[test]
+    fn test_commands() {
+        let mut interpreter = Interpreter::new();
+        eval_entry("var b = 2; var a = \"x\";".to_string(), &mut interpreter);
+        let globals: Vec<_> = interpreter
+            .globals()
+            .into_iter()
+            .map(|(name, value)| format!("{} = {}", name, value))
+            .collect();
+        assert_eq!(globals, ["a = x", "b = 2"]);
+        command(":reset", &mut interpreter, Capabilities::default(), &[]);
+        assert!(interpreter.globals().is_empty());
+    }
+
+    #[test]
+    fn test_exit() {
+        // exit 只交出退出码，由 run_prompt 保存历史之后再退出进程
+        let lox = &mut Interpreter::new();
+        assert_eq!(eval_entry("var a = 1;".to_string(), lox), None);
+        assert_eq!(eval_entry("exit(2);".to_string(), lox), Some(2));
+        assert_eq!(eval_entry("exit(a)".to_string(), lox), Some(1));
+        let exit = command(":time exit(3);", lox, Capabilities::default(), &[]);
+        assert_eq!(exit, Some(3));
+    }
+}
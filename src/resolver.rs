@@ -0,0 +1,523 @@
+// 变量解析和常量检查
+// 解析之后、执行之前按词法作用域走一遍 AST，和书中 jlox 一样给每个变量、this、super
+// 记下到声明它的作用域的距离，运行时直接按距离查找。当时还没有声明的名字都指向全局作用域。
+// 局部变量不能在自己的初始值里读取自己，同一个局部作用域里也不能重复声明。
+//
+// 同时找出对常量的赋值和对常量的重复声明。这两种都是编译错误，诊断指向赋值（或重复声明）
+// 的地方，附注指向常量的声明。全局变量可以在使用它的函数之后才声明：
+//
+//   fun reset() { limit = 0; }
+//   const limit = 10;
+//
+// 所以函数里对未声明全局变量的赋值先记下来，等后面出现同名全局常量时再报错。
+use std::cell::Cell;
+use std::collections::HashMap;
+
+use crate::diagnostic::Diagnostic;
+use crate::expr::{Expr, Variable};
+use crate::stmt::{Function, Imported, Stmt};
+use crate::token::Token;
+
+/// 一层作用域
+#[derive(Default)]
+struct Scope {
+    /// 作用域里声明的名字，常量带着声明它的 token
+    names: HashMap<String, Option<Token>>,
+    /// 名字在 `Resolver::symbols` 里的下标，this、super 没有
+    symbols: HashMap<String, usize>,
+    /// 正在求初始值的变量，这时读取它是编译错误
+    initializing: Option<String>,
+}
+
+/// 一个声明和所有引用它的地方，语言服务器用来跳转定义、查找引用
+#[derive(Debug, Clone)]
+pub(crate) struct Symbol {
+    pub(crate) name: Token,
+    pub(crate) kind: SymbolKind,
+    /// 读取和赋值的位置，按源码顺序
+    pub(crate) references: Vec<Token>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SymbolKind {
+    Variable,
+    Constant,
+    Function,
+    Class,
+    Parameter,
+    /// 方法按属性查找，没有引用
+    Method,
+}
+
+impl SymbolKind {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            SymbolKind::Variable => "variable",
+            SymbolKind::Constant => "const",
+            SymbolKind::Function => "function",
+            SymbolKind::Class => "class",
+            SymbolKind::Parameter => "parameter",
+            SymbolKind::Method => "method",
+        }
+    }
+}
+
+pub(crate) struct Resolver {
+    /// 作用域栈，第一个是全局作用域，和运行时的环境链一一对应
+    scopes: Vec<Scope>,
+    function_depth: usize,
+    /// 函数里赋值、但当时还没有声明的全局变量
+    pending: Vec<Token>,
+    /// 程序里的所有声明，按声明顺序
+    pub(crate) symbols: Vec<Symbol>,
+    /// 引用时还没有声明的全局名字，之后声明了再记到对应的符号上
+    unresolved: Vec<Token>,
+    pub(crate) diagnostics: Vec<Diagnostic>,
+}
+
+impl Resolver {
+    /// `constants` 是之前执行过的代码里声明的全局常量，REPL 每次输入单独检查
+    pub(crate) fn new(constants: &HashMap<String, Token>) -> Self {
+        let names = constants
+            .iter()
+            .map(|(name, declaration)| (name.clone(), Some(declaration.clone())))
+            .collect();
+        Self {
+            scopes: vec![Scope {
+                names,
+                ..Scope::default()
+            }],
+            function_depth: 0,
+            pending: Vec::new(),
+            symbols: Vec::new(),
+            unresolved: Vec::new(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    pub(crate) fn resolve(&mut self, statements: &[Stmt]) {
+        for stmt in statements {
+            self.stmt(stmt);
+        }
+    }
+
+    pub(crate) fn resolve_expr(&mut self, expr: &Expr) {
+        self.expr(expr);
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(Scope::default());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    /// 在当前作用域声明 `name`
+    fn declare(&mut self, name: &Token, kind: SymbolKind) {
+        let local = self.scopes.len() > 1;
+        let scope = self.scopes.last_mut().expect("global scope");
+        match scope.names.get(&name.lexeme) {
+            Some(Some(declaration)) => {
+                let diagnostic = Diagnostic::at_token(name, "Already a constant with this name.")
+                    .with_note(declaration, &declared_here(declaration));
+                self.diagnostics.push(diagnostic);
+                return;
+            }
+            // 全局变量可以重新声明
+            Some(None) if local => {
+                let message = "Already a variable with this name in this scope.";
+                self.diagnostics.push(Diagnostic::at_token(name, message));
+                return;
+            }
+            _ => {}
+        }
+        let constant = kind == SymbolKind::Constant;
+        scope
+            .names
+            .insert(name.lexeme.clone(), constant.then(|| name.clone()));
+        scope
+            .symbols
+            .insert(name.lexeme.clone(), self.symbols.len());
+        let references = if local {
+            Vec::new()
+        } else {
+            take_named(&mut self.unresolved, name)
+        };
+        self.symbols.push(Symbol {
+            name: name.clone(),
+            kind,
+            references,
+        });
+        if local || !constant {
+            return;
+        }
+        for token in take_named(&mut self.pending, name) {
+            self.assign_error(&token, name);
+        }
+    }
+
+    /// 记下对 `name` 的一次引用
+    fn reference(&mut self, name: &Token) {
+        let scope = self
+            .scopes
+            .iter()
+            .rev()
+            .find(|scope| scope.names.contains_key(&name.lexeme));
+        match scope {
+            Some(scope) => {
+                if let Some(&symbol) = scope.symbols.get(&name.lexeme) {
+                    self.symbols[symbol].references.push(name.clone());
+                }
+            }
+            None => self.unresolved.push(name.clone()),
+        }
+    }
+
+    /// 变量和常量先声明再解析初始值，初始值里的闭包能引用它自己
+    fn initializer(&mut self, name: &Token, initializer: &Expr) {
+        self.scopes.last_mut().expect("global scope").initializing = Some(name.lexeme.clone());
+        self.expr(initializer);
+        self.scopes.last_mut().expect("global scope").initializing = None;
+    }
+
+    fn variable(&mut self, variable: &Variable) {
+        let scope = self.scopes.last().expect("global scope");
+        if self.scopes.len() > 1 && scope.initializing.as_ref() == Some(&variable.name.lexeme) {
+            let message = "Can't read local variable in its own initializer.";
+            self.diagnostics
+                .push(Diagnostic::at_token(&variable.name, message));
+        }
+        self.resolve_local(&variable.name, &variable.distance);
+    }
+
+    /// 在当前作用域定义 this、super 这样的隐含名字
+    fn define(&mut self, name: &str) {
+        let scope = self.scopes.last_mut().expect("global scope");
+        scope.names.insert(name.to_string(), None);
+    }
+
+    /// 从当前作用域往外数到声明 `name` 的作用域，还没有声明的算全局作用域
+    fn resolve_local(&mut self, name: &Token, distance: &Cell<Option<usize>>) {
+        self.reference(name);
+        let found = self
+            .scopes
+            .iter()
+            .rev()
+            .position(|scope| scope.names.contains_key(&name.lexeme));
+        distance.set(Some(found.unwrap_or(self.scopes.len() - 1)));
+    }
+
+    /// 检查对 `name` 的赋值并记下距离
+    fn assign(&mut self, name: &Token, distance: &Cell<Option<usize>>) {
+        self.resolve_local(name, distance);
+        let scope = self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.names.get(&name.lexeme));
+        match scope {
+            Some(Some(declaration)) => {
+                let declaration = declaration.clone();
+                self.assign_error(name, &declaration);
+            }
+            Some(None) => {}
+            None if self.function_depth > 0 => self.pending.push(name.clone()),
+            None => {}
+        }
+    }
+
+    fn assign_error(&mut self, name: &Token, declaration: &Token) {
+        let diagnostic = Diagnostic::at_token(name, "Can't assign to a constant.")
+            .with_note(declaration, &declared_here(declaration));
+        self.diagnostics.push(diagnostic);
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Block(block) => {
+                self.begin_scope();
+                self.resolve(&block.statements);
+                self.end_scope();
+            }
+            Stmt::Break(_) | Stmt::Continue(_) => {}
+            Stmt::Class(class) => {
+                self.declare(&class.name, SymbolKind::Class);
+                // 和运行时一样：有父类时方法外面套一层 super，绑定时再套一层 this
+                if let Some(superclass) = &class.superclass {
+                    self.resolve_local(&superclass.name, &superclass.distance);
+                    self.begin_scope();
+                    self.define("super");
+                }
+                for method in &class.methods {
+                    self.symbols.push(Symbol {
+                        name: method.name.clone(),
+                        kind: SymbolKind::Method,
+                        references: Vec::new(),
+                    });
+                    self.begin_scope();
+                    self.define("this");
+                    self.function(method);
+                    self.end_scope();
+                }
+                if class.superclass.is_some() {
+                    self.end_scope();
+                }
+            }
+            Stmt::Const(constant) => {
+                self.declare(&constant.name, SymbolKind::Constant);
+                self.initializer(&constant.name, &constant.initializer);
+            }
+            Stmt::Export(export) => self.stmt(&export.declaration),
+            Stmt::Expression(expression) => self.expr(&expression.expr),
+            Stmt::Function(function) => {
+                self.declare(&function.name, SymbolKind::Function);
+                self.function(function);
+            }
+            Stmt::If(stmt) => {
+                self.expr(&stmt.condition);
+                self.stmt(&stmt.then_branch);
+                if let Some(else_branch) = &stmt.else_branch {
+                    self.stmt(else_branch);
+                }
+            }
+            Stmt::Import(import) => match &import.imported {
+                Imported::Module(name) => self.declare(name, SymbolKind::Variable),
+                Imported::Names(names) => {
+                    for name in names {
+                        self.declare(name, SymbolKind::Variable);
+                    }
+                }
+            },
+            Stmt::Print(print) => self.expr(&print.expr),
+            Stmt::Return(stmt) => {
+                if let Some(value) = &stmt.value {
+                    self.expr(value);
+                }
+            }
+            Stmt::Var(var) => {
+                self.declare(&var.name, SymbolKind::Variable);
+                if let Some(initializer) = &var.initializer {
+                    self.initializer(&var.name, initializer);
+                }
+            }
+            Stmt::While(stmt) => {
+                self.expr(&stmt.condition);
+                self.stmt(&stmt.body);
+            }
+            Stmt::For(stmt) => {
+                self.begin_scope();
+                if let Some(initializer) = &stmt.initializer {
+                    self.stmt(initializer);
+                }
+                for expr in stmt.condition.iter().chain(&stmt.increment) {
+                    self.expr(expr);
+                }
+                self.stmt(&stmt.body);
+                self.end_scope();
+            }
+            Stmt::ForIn(stmt) => {
+                self.expr(&stmt.iterable);
+                self.begin_scope();
+                self.declare(&stmt.name, SymbolKind::Variable);
+                self.stmt(&stmt.body);
+                self.end_scope();
+            }
+        }
+    }
+
+    /// 参数和函数体共用一个作用域，和解释器一致
+    fn function(&mut self, function: &Function) {
+        self.function_depth += 1;
+        self.begin_scope();
+        for param in &function.params {
+            // 默认值在参数作用域里求值，能看到前面的参数
+            if let Some(default) = &param.default {
+                self.expr(default);
+            }
+            self.declare(&param.name, SymbolKind::Parameter);
+        }
+        if let Some(rest) = &function.rest {
+            self.declare(rest, SymbolKind::Parameter);
+        }
+        self.resolve(&function.body);
+        self.end_scope();
+        self.function_depth -= 1;
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Assignment(assignment) => {
+                self.expr(&assignment.value);
+                self.assign(&assignment.name, &assignment.distance);
+            }
+            Expr::Binary(binary) => {
+                self.expr(&binary.left);
+                self.expr(&binary.right);
+            }
+            Expr::Call(call) => {
+                self.expr(&call.callee);
+                for argument in &call.arguments {
+                    self.expr(argument);
+                }
+                for (_, argument) in &call.named {
+                    self.expr(argument);
+                }
+            }
+            Expr::Comma(comma) => {
+                self.expr(&comma.left);
+                self.expr(&comma.right);
+            }
+            Expr::Compound(compound) => {
+                self.expr(&compound.value);
+                self.target(&compound.target);
+            }
+            Expr::Conditional(conditional) => {
+                self.expr(&conditional.condition);
+                self.expr(&conditional.then_branch);
+                self.expr(&conditional.else_branch);
+            }
+            Expr::Get(get) => self.expr(&get.object),
+            Expr::Set(set) => {
+                self.expr(&set.object);
+                self.expr(&set.value);
+            }
+            Expr::Index(index) => {
+                self.expr(&index.object);
+                self.expr(&index.index);
+            }
+            Expr::IndexSet(index_set) => {
+                self.expr(&index_set.object);
+                self.expr(&index_set.index);
+                self.expr(&index_set.value);
+            }
+            Expr::List(list) => {
+                for element in &list.elements {
+                    self.expr(element);
+                }
+            }
+            Expr::Map(map) => {
+                for (key, value) in &map.entries {
+                    self.expr(key);
+                    self.expr(value);
+                }
+            }
+            Expr::Grouping(grouping) => self.expr(&grouping.expr),
+            Expr::Lambda(lambda) => self.function(&lambda.function),
+            Expr::Optional(optional) => self.expr(&optional.expr),
+            Expr::Unary(unary) => self.expr(&unary.right),
+            Expr::Update(update) => self.target(&update.target),
+            Expr::Logical(logical) => {
+                self.expr(&logical.left);
+                self.expr(&logical.right);
+            }
+            Expr::Super(sup) => self.resolve_local(&sup.keyword, &sup.distance),
+            Expr::This(this) => self.resolve_local(&this.keyword, &this.distance),
+            Expr::Variable(variable) => self.variable(variable),
+            Expr::Literal(_) => {}
+        }
+    }
+
+    /// 复合赋值和自增自减的目标，变量按赋值检查
+    fn target(&mut self, target: &Expr) {
+        match target {
+            Expr::Variable(variable) => self.assign(&variable.name, &variable.distance),
+            target => self.expr(target),
+        }
+    }
+}
+
+/// 从 `tokens` 里取出和 `name` 同名的
+fn take_named(tokens: &mut Vec<Token>, name: &Token) -> Vec<Token> {
+    let (named, rest) = std::mem::take(tokens)
+        .into_iter()
+        .partition(|token| token.lexeme == name.lexeme);
+    *tokens = rest;
+    named
+}
+
+fn declared_here(declaration: &Token) -> String {
+    format!("Constant '{}' is declared here.", declaration.lexeme)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parse;
+    use crate::scanner::Scanner;
+
+    fn errors(source: &str) -> Vec<String> {
+        let mut scanner = Scanner::new(source.to_string());
+        scanner.scan_tokens();
+        let statements = Parse::new(scanner).parse();
+        let mut resolver = Resolver::new(&HashMap::new());
+        resolver.resolve(&statements);
+        resolver
+            .diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_distances() {
+        let mut scanner = Scanner::new("var a; { var b; { print b; print a; print c; } }".into());
+        scanner.scan_tokens();
+        let statements = Parse::new(scanner).parse();
+        Resolver::new(&HashMap::new()).resolve(&statements);
+        let Stmt::Block(outer) = &statements[1] else {
+            panic!("expected block");
+        };
+        let Stmt::Block(inner) = &outer.statements[1] else {
+            panic!("expected block");
+        };
+        let distances: Vec<_> = inner
+            .statements
+            .iter()
+            .map(|stmt| match stmt {
+                Stmt::Print(print) => match &print.expr {
+                    Expr::Variable(variable) => variable.distance.get(),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect();
+        // 全局变量和还没有声明的名字都指向全局作用域
+        assert_eq!(distances, [Some(1), Some(2), Some(2)]);
+    }
+
+    #[test]
+    fn test_constants() {
+        assert_eq!(
+            errors("const a = 1;\na = 2;"),
+            ["[line 2] Error at 'a': Can't assign to a constant.\n\
+              [line 1] Note: Constant 'a' is declared here."]
+        );
+        assert!(errors("const a = 1; { var a = 2; a = 3; } fun f(a) { a = 1; }").is_empty());
+        assert!(errors("var a = 1; { a = 2; const a = 3; }").is_empty());
+        // 函数在常量之前声明
+        assert_eq!(errors("fun f() { a = 1; }\nconst a = 2;").len(), 1);
+        // 函数里的 a 是全局变量，不是后面声明的局部常量
+        assert!(errors("var a; { fun f() { a = 1; } const a = 2; }").is_empty());
+        assert_eq!(errors("const a = 1; var a = 2; fun a() {}").len(), 2);
+        assert_eq!(errors("{ const a = 1; for (x in a) { a = x; } }").len(), 1);
+        assert_eq!(errors("const a = 1; a += 1; a++; --a; a.b++;").len(), 3);
+    }
+
+    #[test]
+    fn test_locals() {
+        assert_eq!(
+            errors("var a;\n{ var a = a; }"),
+            ["[line 2] Error at 'a': Can't read local variable in its own initializer."]
+        );
+        assert_eq!(
+            errors("{ var a; const a = 1; }\nfun f(b, b) {}"),
+            [
+                "[line 1] Error at 'a': Already a variable with this name in this scope.",
+                "[line 2] Error at 'b': Already a variable with this name in this scope.",
+            ]
+        );
+        // 全局变量可以重新声明，也可以在初始值里读取同名的全局变量；闭包可以引用自己
+        assert!(errors("var a = 1; var a = a; { var f = () => f(); }").is_empty());
+    }
+}
@@ -1,8 +1,8 @@
-use std::{cell::RefCell, collections::HashMap, iter::Map, rc::Rc, sync::OnceLock, u32};
+use std::{collections::HashMap, sync::OnceLock};
 
 use crate::{
-    err,
-    token::{LiteralType, Token, TokenType},
+    diagnostic::Diagnostic,
+    token::{LiteralType, Token, TokenType, Trivia, TriviaKind},
 };
 
 pub static KEYWORDS: OnceLock<HashMap<&'static str, TokenType>> = OnceLock::new();
@@ -12,12 +12,19 @@ fn get_keywords() -> &'static HashMap<&'static str, TokenType> {
     KEYWORDS.get_or_init(|| {
         HashMap::from([
             ("and", TokenType::AND),
+            ("break", TokenType::BREAK),
             ("class", TokenType::CLASS),
+            ("const", TokenType::CONST),
+            ("continue", TokenType::CONTINUE),
             ("else", TokenType::ELSE),
+            ("export", TokenType::EXPORT),
             ("false", TokenType::FALSE),
             ("for", TokenType::FOR),
             ("fun", TokenType::FUN),
             ("if", TokenType::IF),
+            ("import", TokenType::IMPORT),
+            ("in", TokenType::IN),
+            ("is", TokenType::IS),
             ("nil", TokenType::NIL),
             ("or", TokenType::OR),
             ("print", TokenType::PRINT),
@@ -27,10 +34,6 @@ fn get_keywords() -> &'static HashMap<&'static str, TokenType> {
             ("true", TokenType::TRUE),
             ("var", TokenType::VAR),
             ("while", TokenType::WHILE),
-            // ("break", TokenType::BREAK),
-            // ("continue", TokenType::CONTINUE),
-            // ("const", TokenType::CONST),
-            // ("export", TokenType::EXPORT),
         ])
     })
 }
@@ -40,6 +43,13 @@ pub struct Scanner {
     start: u32,
     current: u32,
     line: u32,
+    /// 是否保留空白和注释，格式化等工具需要无损还原源码
+    keep_trivia: bool,
+    /// 还没有归属的前导 trivia，会挂到下一个 token 上
+    leading: Vec<Trivia>,
+    /// 上一个 token 之后还没换行，新的 trivia 算作它的尾随 trivia
+    trailing_open: bool,
+    pub(crate) diagnostics: Vec<Diagnostic>,
 }
 
 impl Scanner {
@@ -52,6 +62,22 @@ impl Scanner {
             start: 0,
             current: 0,
             line: 1,
+            keep_trivia: false,
+            leading: Vec::new(),
+            trailing_open: false,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// 创建一个保留 trivia 的扫描器
+    ///
+    /// 空白、换行和注释挂在相邻 token 的 `leading`/`trailing` 上：
+    /// 同一行内跟在 token 后面的（包括行尾换行）算尾随，其余算下一个 token 的前导，
+    /// 文件末尾剩下的挂在 EOF 上。把所有 token 依次拼起来就是原文。
+    pub fn with_trivia(source: String) -> Self {
+        Self {
+            keep_trivia: true,
+            ..Self::new(source)
         }
     }
 
@@ -61,10 +87,12 @@ impl Scanner {
             self.scan_token();
         }
 
-        self.tokens
-            .push(Token::new(TokenType::EOF, "".to_string(), None, self.line));
+        let mut eof = Token::new(TokenType::EOF, "".to_string(), None, self.line);
+        eof.offset = self.source.len();
+        eof.leading = std::mem::take(&mut self.leading);
+        self.tokens.push(eof);
         // 返回扫描到的所有token
-        return self.tokens.clone();
+        self.tokens.clone()
     }
 
     fn scan_token(&mut self) {
@@ -74,12 +102,81 @@ impl Scanner {
             ')' => self.add_token_no_literal(TokenType::RIGHT_PAREN),
             '{' => self.add_token_no_literal(TokenType::LEFT_BRACE),
             '}' => self.add_token_no_literal(TokenType::RIGHT_BRACE),
+            '[' => self.add_token_no_literal(TokenType::LEFT_BRACKET),
+            ']' => self.add_token_no_literal(TokenType::RIGHT_BRACKET),
+            ':' => self.add_token_no_literal(TokenType::COLON),
             ',' => self.add_token_no_literal(TokenType::COMMA),
-            '.' => self.add_token_no_literal(TokenType::DOT),
-            '-' => self.add_token_no_literal(TokenType::MINUS),
-            '+' => self.add_token_no_literal(TokenType::PLUS),
+            '.' => {
+                if self.peek() == '.' && self.peek_next() == '.' {
+                    self.advance();
+                    self.advance();
+                    self.add_token_no_literal(TokenType::DOT_DOT_DOT);
+                } else {
+                    self.add_token_no_literal(TokenType::DOT);
+                }
+            }
             ';' => self.add_token_no_literal(TokenType::SEMICOLON),
-            '*' => self.add_token_no_literal(TokenType::STAR),
+            '&' => self.add_token_no_literal(TokenType::AMPERSAND),
+            '|' => self.add_token_no_literal(TokenType::PIPE),
+            '^' => self.add_token_no_literal(TokenType::CARET),
+            '-' => {
+                let token_type = if self.match_char('=') {
+                    TokenType::MINUS_EQUAL
+                } else if self.match_char('-') {
+                    TokenType::MINUS_MINUS
+                } else {
+                    TokenType::MINUS
+                };
+                self.add_token_no_literal(token_type);
+            }
+            '+' => {
+                let token_type = if self.match_char('=') {
+                    TokenType::PLUS_EQUAL
+                } else if self.match_char('+') {
+                    TokenType::PLUS_PLUS
+                } else {
+                    TokenType::PLUS
+                };
+                self.add_token_no_literal(token_type);
+            }
+            '?' => {
+                let token_type = if self.match_char('.') {
+                    TokenType::QUESTION_DOT
+                } else if self.match_char('?') {
+                    TokenType::QUESTION_QUESTION
+                } else {
+                    TokenType::QUESTION
+                };
+                self.add_token_no_literal(token_type);
+            }
+            '%' => {
+                let token_type = if self.match_char('=') {
+                    TokenType::PERCENT_EQUAL
+                } else {
+                    TokenType::PERCENT
+                };
+                self.add_token_no_literal(token_type);
+            }
+            '*' => {
+                let token_type = if self.match_char('*') {
+                    TokenType::STAR_STAR
+                } else if self.match_char('=') {
+                    TokenType::STAR_EQUAL
+                } else {
+                    TokenType::STAR
+                };
+                self.add_token_no_literal(token_type);
+            }
+            '~' => {
+                // `~//` 和 `~/*` 是按位取反后面跟注释
+                let token_type = if self.peek() == '/' && !matches!(self.peek_next(), '/' | '*') {
+                    self.advance();
+                    TokenType::TILDE_SLASH
+                } else {
+                    TokenType::TILDE
+                };
+                self.add_token_no_literal(token_type);
+            }
             '!' => {
                 let token_type = if self.match_char('=') {
                     TokenType::BANG_EQUAL
@@ -91,6 +188,8 @@ impl Scanner {
             '=' => {
                 let token_type = if self.match_char('=') {
                     TokenType::EQUAL_EQUAL
+                } else if self.match_char('>') {
+                    TokenType::ARROW
                 } else {
                     TokenType::EQUAL
                 };
@@ -99,6 +198,8 @@ impl Scanner {
             '<' => {
                 let token_type = if self.match_char('=') {
                     TokenType::LESS_EQUAL
+                } else if self.match_char('<') {
+                    TokenType::LESS_LESS
                 } else {
                     TokenType::LESS
                 };
@@ -107,6 +208,8 @@ impl Scanner {
             '>' => {
                 let token_type = if self.match_char('=') {
                     TokenType::GREATER_EQUAL
+                } else if self.match_char('>') {
+                    TokenType::GREATER_GREATER
                 } else {
                     TokenType::GREATER
                 };
@@ -114,23 +217,34 @@ impl Scanner {
             }
             '/' => {
                 if self.match_char('/') {
-                    // 处理单行注释
-                    while !self.is_at_end() && self.advance() != '\n' {}
+                    // 处理单行注释，换行符留给下一轮扫描去计行号
+                    while !self.is_at_end() && self.peek() != '\n' {
+                        self.advance();
+                    }
+                    self.add_trivia(TriviaKind::LineComment);
                 } else if self.match_char('*') {
                     // 处理多行注释
                     while !self.is_at_end() {
-                        if self.advance() == '*' && self.peek() == '/' {
+                        let c = self.advance();
+                        if c == '\n' {
+                            self.line += 1;
+                        }
+                        if c == '*' && self.peek() == '/' {
                             self.advance(); // 跳过 '/'
                             break;
                         }
                     }
+                    self.add_trivia(TriviaKind::BlockComment);
+                } else if self.match_char('=') {
+                    self.add_token_no_literal(TokenType::SLASH_EQUAL);
                 } else {
                     self.add_token_no_literal(TokenType::SLASH);
                 }
             }
-            ' ' | '\r' | '\t' => {}
+            ' ' | '\r' | '\t' => self.add_trivia(TriviaKind::Whitespace),
             '\n' => {
                 self.line += 1;
+                self.add_trivia(TriviaKind::Newline);
             }
             '"' => {
                 self.string();
@@ -142,7 +256,8 @@ impl Scanner {
                 if Scanner::is_alpha(c) {
                     self.identifier();
                 } else {
-                    err(self.line, &format!("Unexpected character: '{}'", c));
+                    self.error(&format!("Unexpected character: '{}'", c));
+                    self.add_trivia(TriviaKind::Skipped);
                 }
             }
         }
@@ -152,33 +267,23 @@ impl Scanner {
         self.current >= self.source.len() as u32
     }
 
+    // start/current 是字节下标，按 UTF-8 字符前进，这样注释和字符串里可以有中文
     fn advance(&mut self) -> char {
-        let c = self.source.chars().nth(self.current as usize).unwrap();
-        self.current += 1;
+        let c = self.source[self.current as usize..].chars().next().unwrap();
+        self.current += c.len_utf8() as u32;
         c
     }
     fn peek(&self) -> char {
-        if self.is_at_end() {
-            '\0'
-        } else {
-            self.source.chars().nth(self.current as usize).unwrap()
-        }
+        self.source[self.current as usize..].chars().next().unwrap_or('\0')
     }
 
     fn peek_next(&self) -> char {
-        if self.current + 1 >= self.source.len() as u32 {
-            '\0'
-        } else {
-            self.source
-                .chars()
-                .nth((self.current + 1) as usize)
-                .unwrap()
-        }
+        self.source[self.current as usize..].chars().nth(1).unwrap_or('\0')
     }
 
     fn match_char(&mut self, expected: char) -> bool {
-        if !self.is_at_end() && self.source.chars().nth(self.current as usize) == Some(expected) {
-            self.current += 1;
+        if self.peek() == expected {
+            self.advance();
             return true;
         }
         false
@@ -187,13 +292,45 @@ impl Scanner {
     fn add_token(&mut self, token_type: TokenType, literal: Option<LiteralType>) {
         let text = &self.source[self.start as usize..self.current as usize];
         // self.source.substring(self.start as usize, self.current as usize);
-        self.tokens
-            .push(Token::new(token_type, text.to_string(), literal, self.line));
+        let mut token = Token::new(token_type, text.to_string(), literal, self.line);
+        token.offset = self.start as usize;
+        token.leading = std::mem::take(&mut self.leading);
+        self.tokens.push(token);
+        self.trailing_open = true;
     }
     fn add_token_no_literal(&mut self, token_type: TokenType) {
         self.add_token(token_type, None);
     }
 
+    fn add_trivia(&mut self, kind: TriviaKind) {
+        if !self.keep_trivia {
+            return;
+        }
+        let text = &self.source[self.start as usize..self.current as usize];
+        let trivia = match self.tokens.last_mut() {
+            Some(token) if self.trailing_open => &mut token.trailing,
+            _ => &mut self.leading,
+        };
+        // 连续的空白合并成一段
+        match trivia.last_mut() {
+            Some(last) if kind == TriviaKind::Whitespace && last.kind == kind => {
+                last.text.push_str(text)
+            }
+            _ => trivia.push(Trivia {
+                kind,
+                text: text.to_string(),
+            }),
+        }
+        if kind == TriviaKind::Newline {
+            self.trailing_open = false;
+        }
+    }
+
+    fn error(&mut self, msg: &str) {
+        let span = self.start as usize..self.current as usize;
+        self.diagnostics.push(Diagnostic::new(self.line, span, msg));
+    }
+
     fn string(&mut self) {
         while self.peek() != '"' && !self.is_at_end() {
             if self.peek() == '\n' {
@@ -203,7 +340,8 @@ impl Scanner {
         }
 
         if self.is_at_end() {
-            err(self.line, "Unterminated string.");
+            self.error("Unterminated string.");
+            self.add_trivia(TriviaKind::Skipped);
             return;
         }
         self.advance(); // 跳过结束的引号
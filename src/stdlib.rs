@@ -0,0 +1,337 @@
+// 标准库：每个解释器创建时自动注册的原生函数
+//
+// 时间
+//   clock()                 从 Unix 纪元开始的秒数
+// 数学
+//   sqrt(x) floor(x) abs(x) pow(x, y) min(a, b) max(a, b)
+//   random()                [0, 1) 之间的伪随机数
+//   seed(n)                 用整数 n 重置随机数生成器，之后的序列可以复现
+// 字符串（下标按字符计算）
+//   len(s)                  字符个数，也可以求列表和映射的长度
+//   substr(s, start, count) 从 start 开始最多 count 个字符，超出范围的部分被截掉
+//   upper(s) lower(s) trim(s)
+//   split(s, sep)           按 sep 切分成列表，sep 为空串时拆成单个字符
+//   indexOf(s, sub)         sub 第一次出现的位置，找不到返回 -1
+//   toString(v)             和 print 输出一致的字符串
+//   parseNumber(s)          把数字字面量形式的字符串转成数字，格式不对返回 nil
+// 迭代
+//   range(start, end)       从 start 到 end（不含）的整数，用在 for-in 里，按需生成
+// 类型
+//   typeOf(v)               "nil" "bool" "number" "string" "function" "class" "instance" "list"
+//                           "map" "module"
+// 输入输出
+//   readLine()              从 stdin 读一行，不含换行符，读完返回 nil，需要输入权限（--allow-stdin）
+//   args()                  脚本参数组成的列表
+//   exit(code)              停止执行，命令行下用 code 作为退出码
+//   readFile(path)          读出整个文件，需要文件权限（--allow-fs）
+//   writeFile(path, s)      用 s 覆盖文件，需要文件权限（--allow-fs）
+//   env(name)               环境变量的值，没有时返回 nil，需要环境变量权限（--allow-env）
+use std::cell::{Cell, RefCell};
+use std::io::BufRead;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::interpreter::Interpreter;
+use crate::native::{NativeClass, arg};
+use crate::value::Value;
+
+/// 脚本能使用哪些受限函数，默认全部禁止，不可信的脚本也可以放心运行
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    /// `readFile`、`writeFile`
+    pub fs: bool,
+    /// `env`
+    pub env: bool,
+    /// `readLine`，宿主用 `set_stdin` 换掉的输入也一样
+    pub stdin: bool,
+}
+
+/// 输入输出函数共享的宿主状态，由 `Interpreter` 的 setter 修改
+#[derive(Default)]
+pub(crate) struct Host {
+    pub(crate) capabilities: Cell<Capabilities>,
+    pub(crate) args: RefCell<Vec<String>>,
+    /// `readLine` 的输入，`None` 时读进程的 stdin
+    pub(crate) stdin: RefCell<Option<Box<dyn BufRead>>>,
+    /// `exit` 要求的退出码，原生函数返回后由解释器取走
+    pub(crate) exit: Cell<Option<i32>>,
+}
+
+
+pub(crate) fn install(interpreter: &mut Interpreter) {
+    interpreter.register_native("clock", 0, |_| {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Ok(now.as_secs_f64().into())
+    });
+
+    install_math(interpreter);
+    install_strings(interpreter);
+    install_io(interpreter);
+    install_range(interpreter);
+
+    interpreter.register_native("typeOf", 1, |args| Ok(args[0].type_name().into()));
+}
+
+fn install_math(interpreter: &mut Interpreter) {
+    let unary = |interpreter: &mut Interpreter, name, function: fn(f64) -> f64| {
+        interpreter.register_native(name, 1, move |args| Ok(function(arg(args, 0)?).into()));
+    };
+    unary(interpreter, "sqrt", f64::sqrt);
+    unary(interpreter, "floor", f64::floor);
+    unary(interpreter, "abs", f64::abs);
+    let binary = |interpreter: &mut Interpreter, name, function: fn(f64, f64) -> f64| {
+        interpreter.register_native(name, 2, move |args| {
+            Ok(function(arg(args, 0)?, arg(args, 1)?).into())
+        });
+    };
+    binary(interpreter, "pow", f64::powf);
+    binary(interpreter, "min", f64::min);
+    binary(interpreter, "max", f64::max);
+
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_nanos() as u64);
+    let rng = Rc::new(Rng::new(seed));
+    let state = rng.clone();
+    interpreter.register_native("random", 0, move |_| Ok(state.next().into()));
+    interpreter.register_native("seed", 1, move |args| {
+        rng.reseed(arg::<i64>(args, 0)? as u64);
+        Ok(Value::Nil)
+    });
+}
+
+fn install_strings(interpreter: &mut Interpreter) {
+    interpreter.register_native("len", 1, |args| match &args[0] {
+        Value::String(s) => Ok((s.chars().count() as f64).into()),
+        Value::List(values) => Ok((values.borrow().len() as f64).into()),
+        Value::Map(map) => Ok((map.borrow().len() as f64).into()),
+        value => Err(format!(
+            "Argument 1 must be a string, list or map but got {}.",
+            value.type_name()
+        )),
+    });
+    interpreter.register_native("substr", 3, |args| {
+        let s: String = arg(args, 0)?;
+        let start = non_negative(args, 1)?;
+        let count = non_negative(args, 2)?;
+        Ok(s.chars().skip(start).take(count).collect::<String>().into())
+    });
+    let transform = |interpreter: &mut Interpreter, name, function: fn(&str) -> String| {
+        interpreter.register_native(name, 1, move |args| {
+            Ok(function(&arg::<String>(args, 0)?).into())
+        });
+    };
+    transform(interpreter, "upper", str::to_uppercase);
+    transform(interpreter, "lower", str::to_lowercase);
+    transform(interpreter, "trim", |s| s.trim().to_string());
+    interpreter.register_native("split", 2, |args| {
+        let s: String = arg(args, 0)?;
+        let separator: String = arg(args, 1)?;
+        let parts: Vec<Value> = if separator.is_empty() {
+            s.chars().map(|c| c.to_string().into()).collect()
+        } else {
+            s.split(separator.as_str()).map(Value::from).collect()
+        };
+        Ok(Value::list(parts))
+    });
+    interpreter.register_native("indexOf", 2, |args| {
+        let s: String = arg(args, 0)?;
+        let sub: String = arg(args, 1)?;
+        let index = match s.find(&sub) {
+            Some(byte) => s[..byte].chars().count() as f64,
+            None => -1.0,
+        };
+        Ok(index.into())
+    });
+    interpreter.register_native("toString", 1, |args| Ok(args[0].to_string().into()));
+    interpreter.register_native("parseNumber", 1, |args| {
+        let s: String = arg(args, 0)?;
+        Ok(parse_number(s.trim()).map_or(Value::Nil, Value::Number))
+    });
+}
+
+fn install_range(interpreter: &mut Interpreter) {
+    struct Range {
+        start: i64,
+        end: i64,
+    }
+    struct RangeIterator {
+        next: i64,
+        end: i64,
+    }
+
+    let iterator = Rc::new(
+        NativeClass::builder::<RangeIterator>("RangeIterator")
+            .getter("done", |it| Ok((it.next >= it.end).into()))
+            .method("next", 0, |it, _| {
+                it.next += 1;
+                Ok(((it.next - 1) as f64).into())
+            })
+            .build(),
+    );
+    let range = Rc::new(
+        NativeClass::builder::<Range>("Range")
+            .getter("start", |range| Ok((range.start as f64).into()))
+            .getter("end", |range| Ok((range.end as f64).into()))
+            .method("iter", 0, move |range, _| {
+                Ok(iterator.instance(RangeIterator {
+                    next: range.start,
+                    end: range.end,
+                }))
+            })
+            .display(|range| format!("range({}, {})", range.start, range.end))
+            .build(),
+    );
+    interpreter.register_native("range", 2, move |args| {
+        let start = arg(args, 0)?;
+        let end = arg(args, 1)?;
+        Ok(range.instance(Range { start, end }))
+    });
+}
+
+fn install_io(interpreter: &mut Interpreter) {
+    let host = interpreter.host.clone();
+    interpreter.register_native("readLine", 0, move |_| {
+        require(host.capabilities.get().stdin, "readLine", "--allow-stdin")?;
+        let mut line = String::new();
+        let read = match host.stdin.borrow_mut().as_mut() {
+            Some(reader) => reader.read_line(&mut line),
+            None => std::io::stdin().read_line(&mut line),
+        }
+        .map_err(|e| format!("Could not read from stdin: {}.", e))?;
+        if read == 0 {
+            return Ok(Value::Nil);
+        }
+        let line = line.strip_suffix('\n').unwrap_or(&line);
+        Ok(line.strip_suffix('\r').unwrap_or(line).into())
+    });
+    let host = interpreter.host.clone();
+    interpreter.register_native("args", 0, move |_| {
+        let args = host.args.borrow().iter().map(|arg| arg.as_str().into()).collect();
+        Ok(Value::list(args))
+    });
+    let host = interpreter.host.clone();
+    interpreter.register_native("exit", 1, move |args| {
+        let code: i64 = arg(args, 0)?;
+        let code = i32::try_from(code).map_err(|_| "Exit code is out of range.".to_string())?;
+        host.exit.set(Some(code));
+        Ok(Value::Nil)
+    });
+
+    let host = interpreter.host.clone();
+    interpreter.register_native("readFile", 1, move |args| {
+        require(host.capabilities.get().fs, "readFile", "--allow-fs")?;
+        let path: String = arg(args, 0)?;
+        std::fs::read_to_string(&path)
+            .map(Value::from)
+            .map_err(|e| format!("Could not read file '{}': {}.", path, e))
+    });
+    let host = interpreter.host.clone();
+    interpreter.register_native("writeFile", 2, move |args| {
+        require(host.capabilities.get().fs, "writeFile", "--allow-fs")?;
+        let path: String = arg(args, 0)?;
+        let contents: String = arg(args, 1)?;
+        std::fs::write(&path, contents)
+            .map(|_| Value::Nil)
+            .map_err(|e| format!("Could not write file '{}': {}.", path, e))
+    });
+    let host = interpreter.host.clone();
+    interpreter.register_native("env", 1, move |args| {
+        require(host.capabilities.get().env, "env", "--allow-env")?;
+        let name: String = arg(args, 0)?;
+        Ok(std::env::var(name).map_or(Value::Nil, Value::from))
+    });
+}
+
+/// 没有权限时的错误，提示命令行下怎么打开
+fn require(allowed: bool, name: &str, flag: &str) -> Result<(), String> {
+    if allowed {
+        Ok(())
+    } else {
+        Err(format!("{}() is not allowed; run with {} to enable it.", name, flag))
+    }
+}
+
+fn non_negative(args: &[Value], index: usize) -> Result<usize, String> {
+    let n: i64 = arg(args, index)?;
+    usize::try_from(n).map_err(|_| format!("Argument {} must not be negative.", index + 1))
+}
+
+/// 只接受 Lox 数字字面量的写法，可以带负号：`12`、`-3.5`
+fn parse_number(s: &str) -> Option<f64> {
+    let digits = s.strip_prefix('-').unwrap_or(s);
+    let (int, frac) = match digits.split_once('.') {
+        Some((int, frac)) => (int, Some(frac)),
+        None => (digits, None),
+    };
+    let is_digits = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+    if !is_digits(int) || frac.is_some_and(|frac| !is_digits(frac)) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// xorshift64* 伪随机数生成器，不依赖外部 crate
+struct Rng {
+    state: Cell<u64>,
+}
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        let rng = Self {
+            state: Cell::new(0),
+        };
+        rng.reseed(seed);
+        rng
+    }
+
+    /// 用 splitmix64 打散种子，相近的种子也能得到不相关的序列
+    fn reseed(&self, seed: u64) {
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        // 状态不能是 0
+        self.state.set(z.max(1));
+    }
+
+    /// [0, 1) 之间均匀分布的数
+    fn next(&self) -> f64 {
+        let mut x = self.state.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state.set(x);
+        (x.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_number() {
+        assert_eq!(parse_number("12"), Some(12.0));
+        assert_eq!(parse_number("-3.5"), Some(-3.5));
+        assert_eq!(parse_number("1."), None);
+        assert_eq!(parse_number(".5"), None);
+        assert_eq!(parse_number("1e5"), None);
+        assert_eq!(parse_number("inf"), None);
+        assert_eq!(parse_number(""), None);
+    }
+
+    #[test]
+    fn test_rng() {
+        let a = Rng::new(42);
+        let b = Rng::new(42);
+        for _ in 0..100 {
+            let n = a.next();
+            assert!((0.0..1.0).contains(&n));
+            assert_eq!(n, b.next());
+        }
+        assert_ne!(Rng::new(1).next(), Rng::new(2).next());
+    }
+}
@@ -0,0 +1,192 @@
+// 语句相关定义，一个程序就是一串语句
+use std::rc::Rc;
+
+use crate::expr::{Expr, Variable};
+use crate::token::{LiteralType, Token};
+
+/// AST 语句枚举
+#[derive(Debug, Clone)]
+pub enum Stmt {
+    /// 代码块 `{ ... }`，有自己的作用域
+    Block(Block),
+    /// break 语句
+    Break(Break),
+    /// 类声明
+    Class(Class),
+    /// 常量声明，resolver 保证不会被重新赋值
+    Const(Const),
+    /// continue 语句
+    Continue(Continue),
+    /// `export` 加在模块顶层的声明上
+    Export(Export),
+    /// 表达式语句
+    Expression(Expression),
+    /// 函数声明，闭包和声明共用同一份函数体
+    Function(Rc<Function>),
+    /// if 语句
+    If(If),
+    /// import 语句
+    Import(Import),
+    /// print 语句
+    Print(Print),
+    /// return 语句
+    Return(Return),
+    /// 变量声明
+    Var(Var),
+    /// while 循环
+    While(While),
+    /// for 循环，保留原本的三段结构，没有脱糖成 while
+    For(For),
+    /// `for (x in xs)` 循环
+    ForIn(ForIn),
+}
+
+#[derive(Debug, Clone)]
+pub struct Block {
+    /// 左花括号，lint 定位用
+    pub brace: Token,
+    pub statements: Vec<Stmt>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Break {
+    pub keyword: Token,
+}
+
+#[derive(Debug, Clone)]
+pub struct Class {
+    pub name: Token,
+    /// `class A < B` 里的 B
+    pub superclass: Option<Variable>,
+    pub methods: Vec<Rc<Function>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Const {
+    pub name: Token,
+    pub initializer: Expr,
+}
+
+#[derive(Debug, Clone)]
+pub struct Continue {
+    pub keyword: Token,
+}
+
+#[derive(Debug, Clone)]
+pub struct Export {
+    /// 被导出的 var、const、fun 或 class 声明
+    pub declaration: Box<Stmt>,
+}
+
+impl Export {
+    /// 被导出的名字
+    pub(crate) fn name(&self) -> &Token {
+        match &*self.declaration {
+            Stmt::Class(class) => &class.name,
+            Stmt::Const(constant) => &constant.name,
+            Stmt::Function(function) => &function.name,
+            Stmt::Var(var) => &var.name,
+            _ => unreachable!("parser only allows exporting declarations"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Expression {
+    pub expr: Expr,
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: Token,
+    pub params: Vec<Parameter>,
+    /// `...rest`，多出来的位置实参收集成列表
+    pub rest: Option<Token>,
+    pub body: Vec<Stmt>,
+}
+
+/// 函数参数，有默认值的参数只能出现在没有默认值的后面
+#[derive(Debug, Clone)]
+pub struct Parameter {
+    pub name: Token,
+    /// 调用时没有传这个参数才求值，能看到前面的参数
+    pub default: Option<Expr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct If {
+    pub condition: Expr,
+    pub then_branch: Box<Stmt>,
+    pub else_branch: Option<Box<Stmt>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Import {
+    /// import 关键字，加载模块出错时定位用
+    pub keyword: Token,
+    /// 模块路径字符串
+    pub path: Token,
+    pub imported: Imported,
+}
+
+/// import 语句引入的名字
+#[derive(Debug, Clone)]
+pub enum Imported {
+    /// `import "path" as name;`
+    Module(Token),
+    /// `import { a, b } from "path";`
+    Names(Vec<Token>),
+}
+
+impl Import {
+    /// 路径字符串的内容
+    pub(crate) fn module(&self) -> &str {
+        match &self.path.literal {
+            Some(LiteralType::String(path)) => path,
+            _ => unreachable!("parser only accepts string paths"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Print {
+    pub expr: Expr,
+}
+
+#[derive(Debug, Clone)]
+pub struct Return {
+    /// return 关键字，运行时错误定位用
+    pub keyword: Token,
+    pub value: Option<Expr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Var {
+    pub name: Token,
+    pub initializer: Option<Expr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct While {
+    pub condition: Expr,
+    pub body: Box<Stmt>,
+}
+
+#[derive(Debug, Clone)]
+pub struct For {
+    /// 初始化部分，可以是变量声明或表达式语句
+    pub initializer: Option<Box<Stmt>>,
+    pub condition: Option<Expr>,
+    pub increment: Option<Expr>,
+    pub body: Box<Stmt>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ForIn {
+    /// 循环变量，每轮都是一个新变量
+    pub name: Token,
+    /// in 关键字，遍历出错时定位用
+    pub keyword: Token,
+    pub iterable: Expr,
+    pub body: Box<Stmt>,
+}
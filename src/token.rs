@@ -1,5 +1,7 @@
-use std::{fmt::Display, u32};
+use std::fmt::Display;
 
+// 沿用书中 jlox 的大写命名
+#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum TokenType {
     // Single-character tokens.
@@ -7,23 +9,47 @@ pub enum TokenType {
     RIGHT_PAREN,
     LEFT_BRACE,
     RIGHT_BRACE,
+    LEFT_BRACKET,
+    RIGHT_BRACKET,
+    AMPERSAND,
+    CARET,
+    COLON,
     COMMA,
     DOT,
-    MINUS,
-    PLUS,
+    PIPE,
     SEMICOLON,
-    SLASH,
-    STAR,
 
     // One or two character tokens.
+    ARROW,
     BANG,
     BANG_EQUAL,
+    DOT_DOT_DOT,
     EQUAL,
     EQUAL_EQUAL,
     GREATER,
     GREATER_EQUAL,
+    GREATER_GREATER,
     LESS,
     LESS_EQUAL,
+    LESS_LESS,
+    MINUS,
+    MINUS_EQUAL,
+    MINUS_MINUS,
+    PERCENT,
+    PERCENT_EQUAL,
+    PLUS,
+    PLUS_EQUAL,
+    PLUS_PLUS,
+    QUESTION,
+    QUESTION_DOT,
+    QUESTION_QUESTION,
+    SLASH,
+    SLASH_EQUAL,
+    STAR,
+    STAR_EQUAL,
+    STAR_STAR,
+    TILDE,
+    TILDE_SLASH,
 
     // Literals.
     IDENTIFIER,
@@ -32,12 +58,19 @@ pub enum TokenType {
 
     // Keywords.
     AND,
+    BREAK,
     CLASS,
+    CONST,
+    CONTINUE,
     ELSE,
+    EXPORT,
     FALSE,
     FUN,
     FOR,
     IF,
+    IMPORT,
+    IN,
+    IS,
     NIL,
     OR,
     PRINT,
@@ -53,10 +86,15 @@ pub enum TokenType {
 
 #[derive(Debug, Clone)]
 pub struct Token {
-     lexeme: String,
+     pub(crate) lexeme: String,
      pub(crate) line: u32,
      pub(crate) token_type: TokenType,
      pub(crate) literal: Option<LiteralType>,
+     /// token 在源码中的字节偏移
+     pub(crate) offset: usize,
+     /// 前导和尾随 trivia，只有 `Scanner::with_trivia` 会填充
+     pub(crate) leading: Vec<Trivia>,
+     pub(crate) trailing: Vec<Trivia>,
 }
 
 impl Token {
@@ -66,12 +104,37 @@ impl Token {
             lexeme,
             literal,
             line,
+            offset: 0,
+            leading: Vec::new(),
+            trailing: Vec::new(),
         }
     }
-    pub fn to_string(&self) -> String {
-        format!("{:?} {} {:#?}", self.token_type, self.lexeme, self.literal)
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} {} {:#?}", self.token_type, self.lexeme, self.literal)
     }
 }
+/// token 之间的空白、换行和注释
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trivia {
+    pub(crate) kind: TriviaKind,
+    pub(crate) text: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    Whitespace,
+    Newline,
+    /// `//` 注释，不含行尾换行
+    LineComment,
+    /// `/* */` 注释
+    BlockComment,
+    /// 扫描出错时跳过的文本，比如非法字符、未结束的字符串
+    Skipped,
+}
+
 #[derive(Debug, Clone)]
 pub enum LiteralType {
     String(String),
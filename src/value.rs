@@ -0,0 +1,394 @@
+// 运行时的值
+// 字面量之外还有函数等只在运行时出现的值，宿主程序通过 `Value` 和脚本交换数据。
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::rc::Rc;
+
+use crate::environment::Environment;
+use crate::module::LoxModule;
+use crate::native::{NativeClass, NativeFunction, Userdata};
+use crate::stmt::Function;
+use crate::token::LiteralType;
+
+#[derive(Debug, Clone)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    /// 脚本里声明的函数
+    Function(Rc<LoxFunction>),
+    /// 宿主注册的原生函数
+    Native(Rc<NativeFunction>),
+    /// 脚本里声明的类
+    Class(Rc<LoxClass>),
+    Instance(Rc<LoxInstance>),
+    /// 宿主注册的类，见 `NativeClass`
+    NativeClass(Rc<NativeClass>),
+    /// 包装了 Rust 值的对象
+    Userdata(Rc<Userdata>),
+    /// 列表，多个变量可以引用同一个列表
+    List(Rc<RefCell<Vec<Value>>>),
+    /// 映射，和列表一样按引用共享
+    Map(Rc<RefCell<LoxMap>>),
+    /// `import "path" as name` 导入的模块
+    Module(Rc<LoxModule>),
+}
+
+/// 函数声明和它捕获的作用域
+#[derive(Debug)]
+pub struct LoxFunction {
+    pub(crate) declaration: Rc<Function>,
+    pub(crate) closure: Rc<RefCell<Environment>>,
+    /// 类的 `init` 方法总是返回 this
+    pub(crate) is_initializer: bool,
+}
+
+impl LoxFunction {
+    pub fn name(&self) -> &str {
+        &self.declaration.name.lexeme
+    }
+
+    /// 必填参数的个数，有默认值的参数和 `...rest` 不算
+    pub fn arity(&self) -> usize {
+        let params = self.declaration.params.iter();
+        params.filter(|param| param.default.is_none()).count()
+    }
+
+    /// 把方法绑定到实例上：在闭包外再套一层定义了 this 的作用域
+    pub(crate) fn bind(&self, instance: Rc<LoxInstance>) -> LoxFunction {
+        let mut environment = Environment::new(self.closure.clone());
+        environment.define("this", Value::Instance(instance));
+        LoxFunction {
+            declaration: self.declaration.clone(),
+            closure: Rc::new(RefCell::new(environment)),
+            is_initializer: self.is_initializer,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct LoxClass {
+    pub(crate) name: String,
+    pub(crate) superclass: Option<Rc<LoxClass>>,
+    pub(crate) methods: HashMap<String, Rc<LoxFunction>>,
+}
+
+impl LoxClass {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 沿着继承链查找方法
+    pub(crate) fn find_method(&self, name: &str) -> Option<Rc<LoxFunction>> {
+        match self.methods.get(name) {
+            Some(method) => Some(method.clone()),
+            None => self.superclass.as_ref()?.find_method(name),
+        }
+    }
+
+    /// 调用类时的参数个数，由 init 决定
+    pub fn arity(&self) -> usize {
+        self.find_method("init").map_or(0, |init| init.arity())
+    }
+
+    /// 是否是 `class` 本身或它的子类
+    pub(crate) fn is_subclass_of(&self, class: &LoxClass) -> bool {
+        std::ptr::eq(self, class)
+            || self
+                .superclass
+                .as_ref()
+                .is_some_and(|superclass| superclass.is_subclass_of(class))
+    }
+}
+
+#[derive(Debug)]
+pub struct LoxInstance {
+    pub(crate) class: Rc<LoxClass>,
+    pub(crate) fields: RefCell<HashMap<String, Value>>,
+}
+
+impl LoxInstance {
+    pub(crate) fn new(class: Rc<LoxClass>) -> Self {
+        Self {
+            class,
+            fields: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn class(&self) -> &LoxClass {
+        &self.class
+    }
+
+    /// 读取字段，宿主用
+    pub fn get_field(&self, name: &str) -> Option<Value> {
+        self.fields.borrow().get(name).cloned()
+    }
+
+    pub fn set_field(&self, name: &str, value: impl Into<Value>) {
+        self.fields
+            .borrow_mut()
+            .insert(name.to_string(), value.into());
+    }
+}
+
+/// 按插入顺序保存的映射，键只能是字符串、数字或布尔值
+#[derive(Debug, Default)]
+pub struct LoxMap {
+    entries: Vec<(MapKey, Value)>,
+    /// 键在 `entries` 里的位置
+    positions: HashMap<MapKey, usize>,
+}
+
+/// 可以哈希的键，数字按位比较
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum MapKey {
+    Bool(bool),
+    Number(u64),
+    String(String),
+}
+
+impl MapKey {
+    fn new(key: &Value) -> Result<Self, String> {
+        match key {
+            Value::Bool(b) => Ok(MapKey::Bool(*b)),
+            // 0 和 -0 相等，应该是同一个键
+            Value::Number(n) if *n == 0.0 => Ok(MapKey::Number(0f64.to_bits())),
+            Value::Number(n) => Ok(MapKey::Number(n.to_bits())),
+            Value::String(s) => Ok(MapKey::String(s.clone())),
+            key => Err(format!(
+                "Map key must be a string, number or bool but got {}.",
+                key.type_name()
+            )),
+        }
+    }
+
+    fn value(&self) -> Value {
+        match self {
+            MapKey::Bool(b) => Value::Bool(*b),
+            MapKey::Number(bits) => Value::Number(f64::from_bits(*bits)),
+            MapKey::String(s) => Value::String(s.clone()),
+        }
+    }
+}
+
+impl LoxMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// 键的类型不对时也返回 `None`
+    pub fn get(&self, key: &Value) -> Option<&Value> {
+        let position = self.positions.get(&MapKey::new(key).ok()?)?;
+        Some(&self.entries[*position].1)
+    }
+
+    pub fn contains_key(&self, key: &Value) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// 已有的键保持原来的位置，只替换值
+    pub fn insert(&mut self, key: Value, value: Value) -> Result<(), String> {
+        let key = MapKey::new(&key)?;
+        match self.positions.get(&key) {
+            Some(position) => self.entries[*position].1 = value,
+            None => {
+                self.positions.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+        Ok(())
+    }
+
+    /// 删除键，后面的元素保持原来的顺序
+    pub fn remove(&mut self, key: &Value) -> Option<Value> {
+        let position = self.positions.remove(&MapKey::new(key).ok()?)?;
+        let (_, value) = self.entries.remove(position);
+        for (key, _) in &self.entries[position..] {
+            *self.positions.get_mut(key).unwrap() -= 1;
+        }
+        Some(value)
+    }
+
+    /// 按插入顺序遍历
+    pub fn iter(&self) -> impl Iterator<Item = (Value, &Value)> {
+        self.entries.iter().map(|(key, value)| (key.value(), value))
+    }
+}
+
+impl Value {
+    /// Lox 里值的类型名
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Nil => "nil",
+            Value::Bool(_) => "bool",
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Function(_) | Value::Native(_) => "function",
+            Value::Class(_) | Value::NativeClass(_) => "class",
+            Value::Instance(_) | Value::Userdata(_) => "instance",
+            Value::List(_) => "list",
+            Value::Map(_) => "map",
+            Value::Module(_) => "module",
+        }
+    }
+
+    pub(crate) fn list(values: Vec<Value>) -> Value {
+        Value::List(Rc::new(RefCell::new(values)))
+    }
+
+    pub(crate) fn map(map: LoxMap) -> Value {
+        Value::Map(Rc::new(RefCell::new(map)))
+    }
+
+    /// nil 和 false 为假，其余都为真
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Bool(false))
+    }
+
+    /// 能写成字面量的值，优化器折叠常量时用
+    pub(crate) fn to_literal(&self) -> Option<LiteralType> {
+        match self {
+            Value::Nil => Some(LiteralType::Nil),
+            Value::Bool(b) => Some(LiteralType::Bool(*b)),
+            Value::Number(n) => Some(LiteralType::Number(*n)),
+            Value::String(s) => Some(LiteralType::String(s.clone())),
+            _ => None,
+        }
+    }
+}
+
+/// 和 `==` 的语义一致：类型不同就不相等，函数、类和对象比较是否是同一个
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
+            (Value::Native(a), Value::Native(b)) => Rc::ptr_eq(a, b),
+            (Value::Class(a), Value::Class(b)) => Rc::ptr_eq(a, b),
+            (Value::Instance(a), Value::Instance(b)) => Rc::ptr_eq(a, b),
+            (Value::NativeClass(a), Value::NativeClass(b)) => Rc::ptr_eq(a, b),
+            (Value::Userdata(a), Value::Userdata(b)) => Rc::ptr_eq(a, b),
+            (Value::List(a), Value::List(b)) => Rc::ptr_eq(a, b),
+            (Value::Map(a), Value::Map(b)) => Rc::ptr_eq(a, b),
+            (Value::Module(a), Value::Module(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Function(function) => write!(f, "<fn {}>", function.name()),
+            Value::Native(_) => write!(f, "<native fn>"),
+            Value::Class(class) => write!(f, "{}", class.name),
+            Value::Instance(instance) => write!(f, "{} instance", instance.class.name),
+            Value::NativeClass(class) => write!(f, "{}", class.name()),
+            Value::Userdata(userdata) => write!(f, "{}", userdata),
+            Value::List(_) | Value::Map(_) => write_nested(f, self, &mut Vec::new()),
+            Value::Module(module) => write!(f, "<module {}>", module.name),
+        }
+    }
+}
+
+/// 输出列表、映射和它们里面的值
+///
+/// `printing` 是正在输出的外层容器，容器包含自己时输出 `[...]` 或 `{...}` 而不是无限递归。
+fn write_nested(
+    f: &mut std::fmt::Formatter<'_>,
+    value: &Value,
+    printing: &mut Vec<*const ()>,
+) -> std::fmt::Result {
+    let pointer = match value {
+        // 容器里的字符串带引号，和数字区分开
+        Value::String(s) => return write!(f, "{:?}", s),
+        Value::List(values) => Rc::as_ptr(values) as *const (),
+        Value::Map(map) => Rc::as_ptr(map) as *const (),
+        value => return write!(f, "{}", value),
+    };
+    let (open, close) = if let Value::List(_) = value {
+        ("[", "]")
+    } else {
+        ("{", "}")
+    };
+    if printing.contains(&pointer) {
+        return write!(f, "{}...{}", open, close);
+    }
+    printing.push(pointer);
+    write!(f, "{}", open)?;
+    match value {
+        Value::List(values) => {
+            for (i, value) in values.borrow().iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write_nested(f, value, printing)?;
+            }
+        }
+        Value::Map(map) => {
+            for (i, (key, value)) in map.borrow().iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write_nested(f, &key, printing)?;
+                write!(f, ": ")?;
+                write_nested(f, value, printing)?;
+            }
+        }
+        _ => unreachable!(),
+    }
+    printing.pop();
+    write!(f, "{}", close)
+}
+
+impl From<LiteralType> for Value {
+    fn from(literal: LiteralType) -> Self {
+        match literal {
+            LiteralType::Nil => Value::Nil,
+            LiteralType::Bool(b) => Value::Bool(b),
+            LiteralType::Number(n) => Value::Number(n),
+            LiteralType::String(s) => Value::String(s),
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Value::Number(n)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
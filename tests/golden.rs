@@ -0,0 +1,189 @@
+// 用注释描述期望结果的脚本测试，格式和 Crafting Interpreters 上游测试集一致：
+//
+//   1 + 2 // expect: 3
+//   -"a"  // expect runtime error: Operand must be a number.
+//   1 +   // Error at end: Expect expression.
+//   // [line 3] Error: Unterminated string.
+//
+// 另外可以指定命令行和退出码，脚本运行时环境变量 LOX_GOLDEN 为 1：
+//
+//   // flags: --allow-fs      脚本路径前的选项
+//   // args: a b              脚本路径后的参数
+//   exit(3); // expect exit: 3
+//
+// 每个脚本跑两遍，第二遍设置 LOX_OPTIMIZE=0 关掉优化器，两遍都要符合期望。
+// `[java line N]` 也当作 `[line N]`，`[c line N]` 只属于 clox，忽略。
+// 被测试脚本导入的模块放在 `modules` 目录里，不单独运行。
+// 运行 `cargo test --test golden -- <路径片段>...` 只跑路径里包含这些片段的脚本。
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const TEST_DIR: &str = "tests/lox";
+
+#[derive(Debug, Default)]
+struct Expected {
+    output: Vec<String>,
+    errors: Vec<String>,
+    runtime_error: Option<String>,
+    exit_code: i32,
+    flags: Vec<String>,
+    args: Vec<String>,
+}
+
+fn main() {
+    let filters: Vec<String> = std::env::args()
+        .skip(1)
+        .filter(|arg| !arg.starts_with('-'))
+        .collect();
+
+    let mut scripts = Vec::new();
+    collect_scripts(Path::new(TEST_DIR), &mut scripts);
+    scripts.retain(|path| {
+        let path = path.to_string_lossy();
+        filters.is_empty() || filters.iter().any(|filter| path.contains(filter.as_str()))
+    });
+
+    let mut failed = 0;
+    for script in &scripts {
+        let mut failures = run_script(script, true);
+        if failures.is_empty() {
+            failures = run_script(script, false);
+            if !failures.is_empty() {
+                println!("FAIL {} (optimizer off)", script.display());
+            }
+        } else {
+            println!("FAIL {}", script.display());
+        }
+        if !failures.is_empty() {
+            failed += 1;
+            for failure in failures {
+                println!("     {}", failure);
+            }
+        }
+    }
+
+    println!(
+        "golden: {} passed, {} failed, {} total",
+        scripts.len() - failed,
+        failed,
+        scripts.len()
+    );
+    if failed > 0 {
+        std::process::exit(1);
+    }
+}
+
+fn collect_scripts(dir: &Path, scripts: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut paths: Vec<PathBuf> = entries.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+    paths.sort();
+    for path in paths {
+        if path.is_dir() {
+            if path.file_name().is_some_and(|name| name == "modules") {
+                continue;
+            }
+            collect_scripts(&path, scripts);
+        } else if path.extension().is_some_and(|ext| ext == "lox") {
+            scripts.push(path);
+        }
+    }
+}
+
+fn parse_expected(source: &str) -> Expected {
+    let mut expected = Expected::default();
+    let mut exit_code = None;
+    for (i, line) in source.lines().enumerate() {
+        let line_no = i + 1;
+        if let Some((_, output)) = line.split_once("// expect: ") {
+            expected.output.push(output.to_string());
+        } else if let Some((_, code)) = line.split_once("// expect exit: ") {
+            exit_code = code.trim().parse().ok();
+        } else if let Some((_, flags)) = line.split_once("// flags: ") {
+            expected.flags = flags.split_whitespace().map(String::from).collect();
+        } else if let Some((_, args)) = line.split_once("// args: ") {
+            expected.args = args.split_whitespace().map(String::from).collect();
+        } else if let Some((_, message)) = line.split_once("// expect runtime error: ") {
+            expected.runtime_error = Some(format!("{}\n[line {}]", message, line_no));
+        } else if let Some((_, error)) = line.split_once("// ") {
+            if error.starts_with("[c line ") {
+                continue;
+            }
+            if let Some(rest) = error
+                .strip_prefix("[line ")
+                .or_else(|| error.strip_prefix("[java line "))
+            {
+                if let Some((n, message)) = rest.split_once("] ") {
+                    expected.errors.push(format!("[line {}] {}", n, message));
+                }
+            } else if error.starts_with("Error") {
+                expected
+                    .errors
+                    .push(format!("[line {}] {}", line_no, error));
+            }
+        }
+    }
+    expected.exit_code = if let Some(code) = exit_code {
+        code
+    } else if !expected.errors.is_empty() {
+        65
+    } else if expected.runtime_error.is_some() {
+        70
+    } else {
+        0
+    };
+    expected
+}
+
+/// 运行一个脚本，返回和期望不一致的地方
+fn run_script(path: &Path, optimize: bool) -> Vec<String> {
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => return vec![format!("cannot read script: {}", e)],
+    };
+    let expected = parse_expected(&source);
+    let output = Command::new(env!("CARGO_BIN_EXE_lox-r"))
+        .args(&expected.flags)
+        .arg(path)
+        .args(&expected.args)
+        .env("LOX_GOLDEN", "1")
+        .env("LOX_OPTIMIZE", if optimize { "1" } else { "0" })
+        .output();
+    let output = match output {
+        Ok(output) => output,
+        Err(e) => return vec![format!("cannot run interpreter: {}", e)],
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    let mut failures = Vec::new();
+    let actual_output: Vec<&str> = stdout.lines().collect();
+    if actual_output != expected.output {
+        failures.push(format!(
+            "expected output {:?}, got {:?}",
+            expected.output, actual_output
+        ));
+    }
+
+    let actual_errors = stderr.trim_end();
+    let expected_errors = match &expected.runtime_error {
+        Some(runtime_error) if expected.errors.is_empty() => runtime_error.clone(),
+        _ => expected.errors.join("\n"),
+    };
+    if actual_errors != expected_errors {
+        failures.push(format!(
+            "expected errors {:?}, got {:?}",
+            expected_errors, actual_errors
+        ));
+    }
+
+    let exit_code = output.status.code().unwrap_or(-1);
+    if exit_code != expected.exit_code {
+        failures.push(format!(
+            "expected exit code {}, got {}",
+            expected.exit_code, exit_code
+        ));
+    }
+    failures
+}